@@ -0,0 +1,261 @@
+//! # Automatic boot assessment
+//!
+//! Newly staged deployments are given a budget of boot attempts.  On each boot
+//! of a deployment that is still pending assessment, one attempt is consumed and
+//! the health checks shipped by the image in `/usr/lib/bootc/health.d` are run.
+//! If they all succeed, the deployment is marked good.  If any fails, or the budget
+//! was already exhausted (e.g. because a previous boot hung or crashed during
+//! assessment), the deployment is marked bad and the system reboots into
+//! the rollback deployment.
+//!
+//! The state is stored per deployment in `/sysroot/ostree/bootc/assessment`.
+
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use cap_std_ext::cap_std;
+use cap_std_ext::cap_std::fs::{Dir, MetadataExt};
+use cap_std_ext::cap_std::fs_utf8::Dir as DirUtf8;
+use cap_std_ext::dirext::{CapStdExtDirExt, CapStdExtDirExtUtf8};
+use fn_error_context::context;
+use ostree_ext::{gio, ostree};
+
+use crate::spec::{BootAssessment, BootAssessmentState};
+use crate::store::Storage;
+use crate::task::Task;
+
+/// The directory containing health checks, relative to the deployment root
+const HEALTH_D: &str = "usr/lib/bootc/health.d";
+/// The directory holding assessment state, relative to the physical sysroot
+const STATE_DIR: &str = "ostree/bootc/assessment";
+/// The number of boots a deployment gets to pass its health checks
+const DEFAULT_BOOT_ATTEMPTS: u32 = 3;
+/// Journal message ID for a deployment which passed boot assessment
+const ASSESSMENT_GOOD_JOURNAL_ID: &str = "7d6a5f3c0b1e4c4f8a2e9d61c1a0b7e4";
+
+/// The name of the state file for a deployment
+fn state_filename(deployment: &ostree::Deployment) -> String {
+    format!(
+        "{}-{}.{}.json",
+        deployment.stateroot(),
+        deployment.csum(),
+        deployment.deployserial()
+    )
+}
+
+/// Read the assessment state of a deployment, if it is subject to assessment.
+#[context("Reading boot assessment state")]
+pub(crate) fn read_state(
+    sysroot: &Storage,
+    deployment: &ostree::Deployment,
+) -> Result<Option<BootAssessment>> {
+    let sysroot_dir = crate::utils::sysroot_dir(sysroot)?;
+    let Some(d) = sysroot_dir.open_dir_optional(STATE_DIR)? else {
+        return Ok(None);
+    };
+    let name = state_filename(deployment);
+    let Some(f) = d.open_optional(&name)? else {
+        return Ok(None);
+    };
+    let state = serde_json::from_reader(std::io::BufReader::new(f))
+        .with_context(|| format!("Parsing {name}"))?;
+    Ok(Some(state))
+}
+
+#[context("Writing boot assessment state")]
+fn write_state(
+    sysroot: &Storage,
+    deployment: &ostree::Deployment,
+    state: &BootAssessment,
+) -> Result<()> {
+    let sysroot_dir = crate::utils::sysroot_dir(sysroot)?;
+    sysroot_dir.create_dir_all(STATE_DIR)?;
+    let d = sysroot_dir.open_dir(STATE_DIR)?;
+    let buf = serde_json::to_vec(state)?;
+    d.atomic_write(state_filename(deployment), buf)?;
+    Ok(())
+}
+
+/// Mark a newly staged deployment as pending assessment, and remove
+/// state for deployments which no longer exist.
+#[context("Initializing boot assessment")]
+pub(crate) fn init_pending(sysroot: &Storage, deployment: &ostree::Deployment) -> Result<()> {
+    let state = BootAssessment {
+        state: BootAssessmentState::Pending,
+        boot_attempts_left: DEFAULT_BOOT_ATTEMPTS,
+    };
+    write_state(sysroot, deployment, &state)?;
+
+    // Ensure the deployment list includes the deployment we just created
+    sysroot.load(gio::Cancellable::NONE)?;
+    let sysroot_dir = crate::utils::sysroot_dir(sysroot)?;
+    let d = DirUtf8::from_cap_std(sysroot_dir.open_dir(STATE_DIR)?);
+    let known = sysroot
+        .deployments()
+        .iter()
+        .map(state_filename)
+        .collect::<std::collections::HashSet<_>>();
+    for name in d.filenames_filtered_sorted(|_, name| !known.contains(name))? {
+        tracing::debug!("Removing stale boot assessment state {name}");
+        d.remove_file(&name)?;
+    }
+    Ok(())
+}
+
+/// Returns false if a deployment in the given state must not be the target of
+/// an automatic rollback, because it has itself failed boot assessment.
+pub(crate) fn is_rollback_candidate(state: Option<&BootAssessment>) -> bool {
+    !matches!(state, Some(s) if s.state == BootAssessmentState::Bad)
+}
+
+/// Account for a boot of a deployment pending assessment; returns `None`
+/// if the boot attempt budget was already exhausted.
+fn consume_boot_attempt(state: &BootAssessment) -> Option<BootAssessment> {
+    let boot_attempts_left = state.boot_attempts_left.checked_sub(1)?;
+    Some(BootAssessment {
+        state: state.state,
+        boot_attempts_left,
+    })
+}
+
+/// Find the executable health checks in the target root, in the order they should run.
+fn health_checks(root: &Dir) -> Result<Vec<String>> {
+    let Some(d) = root.open_dir_optional(HEALTH_D)?.map(DirUtf8::from_cap_std) else {
+        return Ok(Default::default());
+    };
+    let r = d.filenames_filtered_sorted(|entry, name| {
+        !name.starts_with('.')
+            && entry
+                .metadata()
+                .map(|m| m.is_file() && m.mode() & 0o111 != 0)
+                .unwrap_or_default()
+    })?;
+    Ok(r)
+}
+
+/// Run all health checks from the booted root.
+fn run_health_checks(root: &Dir) -> Result<()> {
+    for name in health_checks(root)? {
+        let path = Utf8Path::new("/").join(HEALTH_D).join(&name);
+        Task::new(format!("Running health check {name}"), path.as_str())
+            .run()
+            .with_context(|| format!("Health check {name} failed"))?;
+    }
+    Ok(())
+}
+
+/// Perform assessment of the booted deployment; this is run once per boot.
+#[context("Assessing boot")]
+pub(crate) async fn assess(sysroot: &Storage) -> Result<()> {
+    let booted = sysroot.require_booted_deployment()?;
+    let Some(state) = read_state(sysroot, &booted)? else {
+        tracing::debug!("Booted deployment is not subject to boot assessment");
+        return Ok(());
+    };
+    if state.state != BootAssessmentState::Pending {
+        tracing::debug!("Booted deployment already assessed: {:?}", state.state);
+        return Ok(());
+    }
+    let (boot_attempts_left, failure) = if let Some(next) = consume_boot_attempt(&state) {
+        // Persist the attempt before running checks, so that a hang or crash
+        // during assessment still counts against the budget.
+        write_state(sysroot, &booted, &next)?;
+        let root = &Dir::open_ambient_dir("/", cap_std::ambient_authority())?;
+        let failure = run_health_checks(root).err().map(|e| format!("{e:#}"));
+        (next.boot_attempts_left, failure)
+    } else {
+        (0, Some("boot attempt budget exhausted".to_string()))
+    };
+    let Some(reason) = failure else {
+        write_state(
+            sysroot,
+            &booted,
+            &BootAssessment {
+                state: BootAssessmentState::Good,
+                boot_attempts_left,
+            },
+        )?;
+        crate::journal::journal_send(
+            libsystemd::logging::Priority::Info,
+            "Booted deployment passed boot assessment",
+            [("MESSAGE_ID", ASSESSMENT_GOOD_JOURNAL_ID)].into_iter(),
+        );
+        println!("Boot assessment passed");
+        return Ok(());
+    };
+    write_state(
+        sysroot,
+        &booted,
+        &BootAssessment {
+            state: BootAssessmentState::Bad,
+            boot_attempts_left,
+        },
+    )?;
+    eprintln!("Boot assessment failed: {reason}");
+    if crate::deploy::rollback_unhealthy(sysroot, &reason).await? {
+        crate::reboot::reboot()?;
+    }
+    anyhow::bail!(
+        "Boot assessment failed ({reason}), and no healthy rollback deployment is available"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consume_boot_attempt() {
+        let state = BootAssessment {
+            state: BootAssessmentState::Pending,
+            boot_attempts_left: 2,
+        };
+        let state = consume_boot_attempt(&state).unwrap();
+        assert_eq!(state.boot_attempts_left, 1);
+        let state = consume_boot_attempt(&state).unwrap();
+        assert_eq!(state.boot_attempts_left, 0);
+        assert_eq!(state.state, BootAssessmentState::Pending);
+        assert!(consume_boot_attempt(&state).is_none());
+    }
+
+    #[test]
+    fn test_is_rollback_candidate() {
+        let state = |state| BootAssessment {
+            state,
+            boot_attempts_left: 0,
+        };
+        assert!(is_rollback_candidate(None));
+        assert!(is_rollback_candidate(Some(&state(
+            BootAssessmentState::Good
+        ))));
+        assert!(is_rollback_candidate(Some(&state(
+            BootAssessmentState::Pending
+        ))));
+        assert!(!is_rollback_candidate(Some(&state(
+            BootAssessmentState::Bad
+        ))));
+    }
+
+    #[test]
+    fn test_health_checks() -> Result<()> {
+        use cap_std::fs::PermissionsExt;
+
+        let td = cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        // No directory
+        assert_eq!(health_checks(&td)?.len(), 0);
+        td.create_dir_all(HEALTH_D)?;
+        assert_eq!(health_checks(&td)?.len(), 0);
+
+        let exec = cap_std::fs::Permissions::from_mode(0o755);
+        for name in ["20-network", "10-app", ".hidden"] {
+            let path = format!("{HEALTH_D}/{name}");
+            td.write(&path, "#!/bin/true\n")?;
+            td.set_permissions(&path, exec.clone())?;
+        }
+        // Not executable
+        td.write(format!("{HEALTH_D}/README"), "docs")?;
+        td.create_dir_all(format!("{HEALTH_D}/subdir"))?;
+
+        similar_asserts::assert_eq!(health_checks(&td)?, ["10-app", "20-network"]);
+        Ok(())
+    }
+}
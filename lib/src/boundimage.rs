@@ -5,42 +5,120 @@
 //! pre-pulled (and in the future, pinned) before a new image root
 //! is considered ready.
 
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
 use anyhow::{Context, Result};
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use cap_std_ext::cap_std::fs::Dir;
 use cap_std_ext::dirext::CapStdExtDirExt;
 use fn_error_context::context;
-use ostree_ext::containers_image_proxy;
+use futures_util::{StreamExt, TryStreamExt};
+use ostree_ext::containers_image_proxy::ImageProxy;
+use ostree_ext::oci_spec::image::Digest;
 use ostree_ext::ostree::Deployment;
+use serde::{Deserialize, Serialize};
 
 use crate::imgstorage::PullMode;
+use crate::progress_jsonl::{Event, ProgressWriter, SubTaskStep};
 use crate::store::Storage;
 
 /// The path in a root for bound images; this directory should only contain
-/// symbolic links to `.container` or `.image` files.
+/// symbolic links to `.container`, `.image`, `.kube`, `.volume` or `.build` files.
 const BOUND_IMAGE_DIR: &str = "usr/lib/bootc/bound-images.d";
+/// Machine-local bound images (`spec.boundImages`); being in `/etc`, these
+/// are carried forward into new deployments.
+const LOCAL_BOUND_IMAGES: &str = "etc/bootc/bound-images.toml";
+/// The group in `.image` and `.container` files for bootc-specific options;
+/// this is ignored by podman and systemd.
+const BOOTC_GROUP: &str = "X-Bootc";
+/// The key in [`BOOTC_GROUP`] naming the sigstore public key for the image.
+const SIGSTORE_PUBLIC_KEY: &str = "SigstorePublicKey";
+/// Suffixes of quadlet units which may be referenced by an `Image=` key instead
+/// of an image reference; these need to be bound separately.
+const QUADLET_IMAGE_UNITS: &[&str] = &[".image", ".build"];
+/// The maximum number of bound images fetched concurrently.
+const MAX_CONCURRENT_PULLS: usize = 4;
+/// The number of times pulling a bound image is attempted.
+const PULL_ATTEMPTS: u32 = 3;
+/// The delay before retrying a failed pull; this doubles with each retry.
+const PULL_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// The format of [`LOCAL_BOUND_IMAGES`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct LocalBoundImages {
+    /// Container image references
+    images: Vec<String>,
+}
 
 /// A subset of data parsed from a `.image` or `.container` file with
 /// the minimal information necessary to fetch the image.
 ///
 /// In the future this may be extended to include e.g. certificates or
 /// other pull options.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct BoundImage {
     pub(crate) image: String,
     pub(crate) auth_file: Option<String>,
+    /// The digest the image reference is pinned to (via `@sha256:...`), if any
+    pub(crate) digest: Option<Digest>,
+    pub(crate) signature: Option<BoundImageSignature>,
+}
+
+/// A signature policy required for a bound image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BoundImageSignature {
+    /// The image must have a valid sigstore signature made with this key.
+    Sigstore {
+        /// Path to the key in the root
+        path: String,
+        /// The PEM-encoded public key
+        public_key: String,
+    },
+}
+
+impl BoundImageSignature {
+    /// Verify the signature of a bound image with the given manifest digest.
+    async fn verify(&self, proxy: &ImageProxy, image: &str, digest: &str) -> Result<()> {
+        match self {
+            Self::Sigstore { path, public_key } => {
+                tracing::debug!("Verifying {image} with sigstore key {path}");
+                let imgref = ostree_ext::container::ImageReference {
+                    transport: ostree_ext::container::Transport::Registry,
+                    name: image.to_owned(),
+                };
+                let digest = digest.parse()?;
+                crate::sigstore::verify_with_key(proxy, &imgref, public_key.as_bytes(), &digest)
+                    .await
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct ResolvedBoundImage {
-    pub(crate) image: String,
+    pub(crate) bound_image: BoundImage,
     pub(crate) digest: String,
 }
 
-/// Given a deployment, pull all container images it references.
-pub(crate) async fn pull_bound_images(sysroot: &Storage, deployment: &Deployment) -> Result<()> {
+/// A bound image failed verification against its pinned digest or signature policy.
+#[derive(thiserror::Error, Debug)]
+#[error("Verifying bound image {0}")]
+pub(crate) struct VerificationError(String);
+
+/// Given a deployment, pull all container images it references.  Each image
+/// is reported as a subtask of `steps`, the `ProgressSteps` event of the
+/// enclosing operation (if any).
+pub(crate) async fn pull_bound_images(
+    sysroot: &Storage,
+    deployment: &Deployment,
+    prog: &ProgressWriter,
+    steps: Option<&Event<'_>>,
+) -> Result<()> {
     let bound_images = query_bound_images_for_deployment(sysroot, deployment)?;
-    pull_images(sysroot, bound_images).await
+    pull_images(sysroot, bound_images, prog, steps).await
 }
 
 #[context("Querying bound images")]
@@ -49,7 +127,56 @@ pub(crate) fn query_bound_images_for_deployment(
     deployment: &Deployment,
 ) -> Result<Vec<BoundImage>> {
     let deployment_root = &crate::utils::deployment_fd(sysroot, deployment)?;
-    query_bound_images(deployment_root)
+    let mut r = query_bound_images(deployment_root)?;
+    for image in query_local_bound_images(deployment_root)? {
+        if !r.iter().any(|b| b.image == image) {
+            r.push(BoundImage::new(image, None)?);
+        }
+    }
+    Ok(r)
+}
+
+/// Query the machine-local bound images in the target root.
+#[context("Querying local bound images")]
+pub(crate) fn query_local_bound_images(root: &Dir) -> Result<Vec<String>> {
+    let Some(f) = root.open_optional(LOCAL_BOUND_IMAGES)? else {
+        return Ok(Default::default());
+    };
+    let buf = std::io::read_to_string(f)?;
+    let config: LocalBoundImages =
+        toml::from_str(&buf).with_context(|| format!("Parsing {LOCAL_BOUND_IMAGES}"))?;
+    Ok(config.images)
+}
+
+#[context("Writing local bound images")]
+fn write_local_bound_images(root: &Dir, images: &[String]) -> Result<()> {
+    let config = LocalBoundImages {
+        images: images.to_vec(),
+    };
+    let buf = toml::to_string(&config)?;
+    if let Some(parent) = Utf8Path::new(LOCAL_BOUND_IMAGES).parent() {
+        root.create_dir_all(parent)?;
+    }
+    root.atomic_write(LOCAL_BOUND_IMAGES, buf)?;
+    Ok(())
+}
+
+/// Persist a new set of machine-local bound images in the booted root,
+/// and ensure they are pulled.  Images which are no longer referenced will be
+/// garbage collected along with other unreferenced bound images.
+#[context("Reconciling local bound images")]
+pub(crate) async fn reconcile_local_bound_images(
+    sysroot: &Storage,
+    root: &Dir,
+    images: &[String],
+    prog: &ProgressWriter,
+) -> Result<()> {
+    let bound_images = images
+        .iter()
+        .map(|image| BoundImage::new(image.clone(), None))
+        .collect::<Result<Vec<_>>>()?;
+    write_local_bound_images(root, images)?;
+    pull_images(sysroot, bound_images, prog, None).await
 }
 
 #[context("Querying bound images")]
@@ -88,15 +215,47 @@ pub(crate) fn query_bound_images(root: &Dir) -> Result<Vec<BoundImage>> {
 
         let file_ini = tini::Ini::from_string(&file_contents).context("Parse to ini")?;
         let file_extension = Utf8Path::new(file_name).extension();
-        let bound_image = match file_extension {
-            Some("image") => parse_image_file(&file_ini).with_context(|| format!("Parsing {path}")),
-            Some("container") => {
-                parse_container_file(&file_ini).with_context(|| format!("Parsing {path}"))
-            }
+        let images = match file_extension {
+            Some("image") => parse_image_file(&file_ini).map(|i| vec![i]),
+            Some("container") => parse_container_file(&file_ini),
+            Some("volume") => parse_optional_image(&file_ini, "Volume"),
+            Some("build") => parse_build_file_path(&file_ini)
+                .and_then(|file| resolve_unit_path(&bound_images_dir, file_name, &file))
+                .and_then(|file| {
+                    let buf = absroot
+                        .read_to_string(&file)
+                        .with_context(|| format!("Reading {file}"))?;
+                    images_from_containerfile(&buf)
+                }),
+            Some("kube") => parse_kube_yaml_path(&file_ini)
+                .and_then(|yaml| resolve_unit_path(&bound_images_dir, file_name, &yaml))
+                .and_then(|yaml| {
+                    let buf = absroot
+                        .read_to_string(&yaml)
+                        .with_context(|| format!("Reading {yaml}"))?;
+                    images_from_kube_yaml(&buf)
+                }),
             _ => anyhow::bail!("Invalid file extension: {file_name}"),
-        }?;
-
-        bound_images.push(bound_image);
+        }
+        .with_context(|| format!("Parsing {path}"))?;
+        let signature = match parse_sigstore_public_key(&file_ini)
+            .with_context(|| format!("Parsing {path}"))?
+        {
+            Some(key_path) => {
+                let public_key = absroot
+                    .read_to_string(&key_path)
+                    .with_context(|| format!("Reading {key_path} (from {path})"))?;
+                Some(BoundImageSignature::Sigstore {
+                    path: key_path,
+                    public_key,
+                })
+            }
+            None => None,
+        };
+        bound_images.extend(images.into_iter().map(|image| BoundImage {
+            signature: signature.clone(),
+            ..image
+        }));
     }
 
     Ok(bound_images)
@@ -105,18 +264,38 @@ pub(crate) fn query_bound_images(root: &Dir) -> Result<Vec<BoundImage>> {
 impl ResolvedBoundImage {
     #[context("resolving bound image {}", src.image)]
     pub(crate) async fn from_image(src: &BoundImage) -> Result<Self> {
-        let proxy = containers_image_proxy::ImageProxy::new().await?;
+        // The host storage is not under our control; check that the stored
+        // image still answers to the pinned digest, if any.
+        if src.digest.is_some() {
+            src.verify_digest(&crate::podman::inspect(&src.image)?)?;
+        }
+        let proxy = ImageProxy::new().await?;
         let img = proxy
             .open_image(&format!("containers-storage:{}", src.image))
             .await?;
         let digest = proxy.fetch_manifest(&img).await?.0;
         Ok(Self {
-            image: src.image.clone(),
+            bound_image: src.clone(),
             digest,
         })
     }
 }
 
+/// Copy resolved bound images from the host container storage into the target
+/// storage, verifying them in the same way as when pulling.
+pub(crate) async fn copy_from_host_storage(
+    imgstore: &crate::imgstorage::Storage,
+    images: Vec<ResolvedBoundImage>,
+) -> Result<()> {
+    let proxy = tokio::sync::OnceCell::new();
+    for resolved in images {
+        let bound_image = &resolved.bound_image;
+        imgstore.pull_from_host_storage(&bound_image.image).await?;
+        bound_image.verify_stored(imgstore, &proxy).await?;
+    }
+    Ok(())
+}
+
 fn parse_image_file(file_contents: &tini::Ini) -> Result<BoundImage> {
     let image: String = file_contents
         .get("Image", "Image")
@@ -134,50 +313,378 @@ fn parse_image_file(file_contents: &tini::Ini) -> Result<BoundImage> {
     Ok(bound_image)
 }
 
-fn parse_container_file(file_contents: &tini::Ini) -> Result<BoundImage> {
+/// Parse the bootc-specific sigstore public key path for a bound image, if any.
+fn parse_sigstore_public_key(file_contents: &tini::Ini) -> Result<Option<String>> {
+    let Some(path): Option<String> = file_contents.get(BOOTC_GROUP, SIGSTORE_PUBLIC_KEY) else {
+        return Ok(None);
+    };
+    let path = parse_spec_value(&path).context("Invalid SigstorePublicKey value")?;
+    anyhow::ensure!(
+        path.starts_with('/'),
+        "SigstorePublicKey must be an absolute path: {path}"
+    );
+    Ok(Some(path))
+}
+
+fn parse_container_file(file_contents: &tini::Ini) -> Result<Vec<BoundImage>> {
     let image: String = file_contents
         .get("Container", "Image")
         .ok_or_else(|| anyhow::anyhow!("Missing Image field"))?;
 
-    let bound_image = BoundImage::new(image.to_string(), None)?;
-    Ok(bound_image)
+    parse_image_value(image)
+}
+
+/// Parse the optional `Image=` key of a `.volume` file.
+fn parse_optional_image(file_contents: &tini::Ini, group: &str) -> Result<Vec<BoundImage>> {
+    let Some(image): Option<String> = file_contents.get(group, "Image") else {
+        return Ok(Vec::new());
+    };
+    parse_image_value(image)
+}
+
+/// Parse an `Image=` value, which may also be a reference to another quadlet unit
+/// rather than a container image.
+fn parse_image_value(image: String) -> Result<Vec<BoundImage>> {
+    if QUADLET_IMAGE_UNITS.iter().any(|s| image.ends_with(s)) {
+        tracing::debug!("Skipping reference to quadlet unit: {image}");
+        return Ok(Vec::new());
+    }
+    Ok(vec![BoundImage::new(image, None)?])
+}
+
+/// Parse the path to the Kubernetes YAML of a `.kube` file.
+fn parse_kube_yaml_path(file_contents: &tini::Ini) -> Result<String> {
+    let yaml: String = file_contents
+        .get("Kube", "Yaml")
+        .ok_or_else(|| anyhow::anyhow!("Missing Yaml field"))?;
+    parse_spec_value(&yaml).context("Invalid Yaml value")
+}
+
+/// Parse the path to the Containerfile of a `.build` file.  Building from
+/// a context directory without `File=` (via `SetWorkingDirectory=`) or from a URL
+/// is not supported.
+fn parse_build_file_path(file_contents: &tini::Ini) -> Result<String> {
+    let auth_file: Option<String> = file_contents.get("Build", "AuthFile");
+    if auth_file.is_some() {
+        anyhow::bail!("AuthFile is not supported by bound bootc images");
+    }
+    let file: String = file_contents
+        .get("Build", "File")
+        .ok_or_else(|| anyhow::anyhow!("Missing File field"))?;
+    let file = parse_spec_value(&file).context("Invalid File value")?;
+    if file.contains("://") {
+        anyhow::bail!("Building from a URL is not supported: {file}");
+    }
+    Ok(file)
+}
+
+/// Resolve a path referenced by a quadlet unit; like quadlet, relative paths
+/// are relative to the directory containing the unit (the target of the symlink
+/// in the bound images directory).
+fn resolve_unit_path(bound_images_dir: &Dir, file_name: &str, path: &str) -> Result<Utf8PathBuf> {
+    let path = Utf8Path::new(path);
+    if path.is_absolute() {
+        return Ok(path.to_owned());
+    }
+    let target = bound_images_dir.read_link_contents(file_name)?;
+    let target = Utf8PathBuf::try_from(target)?;
+    // Note that joining an absolute target replaces the base
+    let unit = Utf8Path::new(BOUND_IMAGE_DIR).join(target);
+    let unit_dir = unit.parent().unwrap_or(Utf8Path::new(BOUND_IMAGE_DIR));
+    Ok(unit_dir.join(path))
+}
+
+/// Find the container images referenced by the pods and workloads in
+/// (possibly multi-document) Kubernetes YAML.
+fn images_from_kube_yaml(buf: &str) -> Result<Vec<BoundImage>> {
+    fn collect(v: &serde_yaml::Value, images: &mut Vec<String>) {
+        use serde_yaml::Value;
+        match v {
+            Value::Mapping(m) => {
+                for (k, v) in m {
+                    match (k.as_str(), v) {
+                        (Some("containers" | "initContainers"), Value::Sequence(containers)) => {
+                            let refs = containers
+                                .iter()
+                                .filter_map(|c| c.get("image").and_then(Value::as_str));
+                            for image in refs {
+                                if !images.iter().any(|i| i == image) {
+                                    images.push(image.to_owned());
+                                }
+                            }
+                        }
+                        _ => collect(v, images),
+                    }
+                }
+            }
+            Value::Sequence(s) => s.iter().for_each(|v| collect(v, images)),
+            _ => {}
+        }
+    }
+
+    let mut images = Vec::new();
+    for doc in serde_yaml::Deserializer::from_str(buf) {
+        let v = serde_yaml::Value::deserialize(doc).context("Parsing YAML")?;
+        collect(&v, &mut images);
+    }
+    images
+        .into_iter()
+        .map(|image| BoundImage::new(image, None))
+        .collect()
+}
+
+/// Find the base images in the `FROM` instructions of a Containerfile, excluding
+/// `scratch` and references to earlier build stages.
+fn images_from_containerfile(buf: &str) -> Result<Vec<BoundImage>> {
+    let mut stages: Vec<String> = Vec::new();
+    let mut images: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for l in buf.lines() {
+        let l = l.trim();
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        // Join continuation lines
+        if let Some(l) = l.strip_suffix('\\') {
+            line.push_str(l);
+            line.push(' ');
+            continue;
+        }
+        line.push_str(l);
+        let instruction = std::mem::take(&mut line);
+        let mut words = instruction.split_whitespace();
+        if !words.next().is_some_and(|w| w.eq_ignore_ascii_case("FROM")) {
+            continue;
+        }
+        let mut words = words.skip_while(|w| w.starts_with("--"));
+        let image = words
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Missing image in: {instruction}"))?;
+        if image.contains('$') {
+            anyhow::bail!("Variables are not supported in bound base images: {instruction}");
+        }
+        if image != "scratch"
+            && !stages.iter().any(|s| s.eq_ignore_ascii_case(image))
+            && !images.iter().any(|i| i == image)
+        {
+            images.push(image.to_owned());
+        }
+        if let (Some(as_), Some(name)) = (words.next(), words.next()) {
+            if as_.eq_ignore_ascii_case("AS") {
+                stages.push(name.to_owned());
+            }
+        }
+    }
+    images
+        .into_iter()
+        .map(|image| BoundImage::new(image, None))
+        .collect()
 }
 
 #[context("Pulling bound images")]
 pub(crate) async fn pull_images(
     sysroot: &Storage,
     bound_images: Vec<crate::boundimage::BoundImage>,
+    prog: &ProgressWriter,
+    steps: Option<&Event<'_>>,
 ) -> Result<()> {
     // Always initialize the img store to ensure labels are set when upgrading
     let imgstore = sysroot.get_ensure_imgstore()?;
     if bound_images.is_empty() {
         return Ok(());
     }
-    pull_images_impl(imgstore, bound_images).await
+    pull_images_impl(imgstore, bound_images, prog, steps).await
+}
+
+/// Run `pull` until it succeeds, at most [`PULL_ATTEMPTS`] times.  A failure is only
+/// retried (with exponential backoff starting at `delay`) if `is_transient` returns true.
+async fn retry_pull<P, T>(
+    image: &str,
+    mut delay: Duration,
+    mut pull: impl FnMut() -> P,
+    mut is_transient: impl FnMut() -> T,
+) -> Result<()>
+where
+    P: Future<Output = Result<()>>,
+    T: Future<Output = bool>,
+{
+    let mut attempt = 1;
+    loop {
+        match pull().await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < PULL_ATTEMPTS && is_transient().await => {
+                tracing::warn!(
+                    "Failed to pull {image} (attempt {attempt}/{PULL_ATTEMPTS}), retrying in {}s: {e:#}",
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Return false if a failed pull of an image is known to not be resolved by retrying,
+/// because the registry reports that the image does not exist.  Rather than interpreting
+/// the error output of podman, this queries the registry via the image proxy; errors
+/// from that (such as network errors) are assumed to be transient too.
+async fn is_transient_pull_error(proxy: &tokio::sync::OnceCell<ImageProxy>, image: &str) -> bool {
+    let exists = async {
+        let proxy = proxy.get_or_try_init(crate::sigstore::new_proxy).await?;
+        let img = proxy
+            .open_image_optional(&format!("docker://{image}"))
+            .await?;
+        if let Some(img) = img.as_ref() {
+            proxy.close_image(img).await?;
+        }
+        anyhow::Ok(img.is_some())
+    };
+    match exists.await {
+        Ok(exists) => exists,
+        Err(e) => {
+            tracing::debug!("Failed to query {image}: {e:#}");
+            true
+        }
+    }
+}
+
+/// Pull an image, retrying failures which may be transient (e.g. network errors).
+async fn pull_with_retry(
+    imgstore: &crate::imgstorage::Storage,
+    proxy: &tokio::sync::OnceCell<ImageProxy>,
+    image: &str,
+) -> Result<()> {
+    retry_pull(
+        image,
+        PULL_RETRY_DELAY,
+        || async { imgstore.pull(image, PullMode::IfNotExists).await.map(drop) },
+        || is_transient_pull_error(proxy, image),
+    )
+    .await
 }
 
+/// Record the state of a bound image in `image_subtasks`, and return `steps` (a
+/// `ProgressSteps` event) with a subtask for each bound image appended.
+fn image_steps_event<'s>(
+    steps: &Event<'s>,
+    image_subtasks: &mut Vec<SubTaskStep<'s>>,
+    image: &str,
+    completed: bool,
+) -> Event<'s> {
+    match image_subtasks.iter_mut().find(|s| s.id == image) {
+        Some(s) => s.completed = completed,
+        None => image_subtasks.push(SubTaskStep {
+            subtask: "bound_image".into(),
+            description: format!("Bound Image: {image}").into(),
+            id: image.to_owned().into(),
+            completed,
+        }),
+    }
+    let mut event = steps.clone();
+    if let Event::ProgressSteps { subtasks, .. } = &mut event {
+        subtasks.extend(image_subtasks.iter().cloned());
+    }
+    event
+}
+
+/// Pull the bound images which are not already present (with bounded concurrency),
+/// and verify their digests and signatures. Progress is reported per image, both as
+/// `BoundImage` events and as subtasks of `steps` (the `ProgressSteps` event of the
+/// enclosing operation, if any); note that podman does not provide byte-level
+/// progress for pulls.
 #[context("Pulling bound images")]
 pub(crate) async fn pull_images_impl(
     imgstore: &crate::imgstorage::Storage,
     bound_images: Vec<crate::boundimage::BoundImage>,
+    prog: &ProgressWriter,
+    steps: Option<&Event<'_>>,
 ) -> Result<()> {
     let n = bound_images.len();
     tracing::debug!("Pulling bound images: {n}");
-    // TODO: do this in parallel
-    for bound_image in bound_images {
-        let image = &bound_image.image;
-        if imgstore.exists(image).await? {
-            tracing::debug!("Bound image already present: {image}");
-            continue;
+    let steps_total = n as u64;
+    let steps = Cell::new(0u64);
+    let event = |image: &str, completed: bool| {
+        if completed {
+            steps.set(steps.get() + 1);
         }
-        let desc = format!("Fetching bound image: {image}");
-        crate::utils::async_task_with_spinner(&desc, async move {
-            imgstore
-                .pull(&bound_image.image, PullMode::IfNotExists)
-                .await
-        })
+        Event::BoundImage {
+            image: image.to_owned().into(),
+            steps: steps.get(),
+            steps_total,
+            completed,
+        }
+    };
+    let image_subtasks = RefCell::new(Vec::<SubTaskStep>::new());
+    let steps_event = |image: &str, completed: bool| {
+        let steps = steps?;
+        let mut image_subtasks = image_subtasks.borrow_mut();
+        Some(image_steps_event(
+            steps,
+            &mut image_subtasks,
+            image,
+            completed,
+        ))
+    };
+    let send = |image: &str, completed: bool| {
+        let events = [Some(event(image, completed)), steps_event(image, completed)];
+        async move {
+            for event in events.into_iter().flatten() {
+                prog.send(event).await;
+            }
+        }
+    };
+
+    let pb = indicatif::ProgressBar::new(steps_total);
+    pb.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("{prefix} {bar} {pos}/{len}")
+            .unwrap(),
+    );
+    pb.set_prefix("Fetching bound images");
+    let log = |msg: String| {
+        // We need to handle the case where we aren't connected to a tty,
+        // so indicatif would show nothing.
+        if pb.is_hidden() {
+            println!("{msg}");
+        } else {
+            pb.println(msg);
+        }
+    };
+
+    // A single image proxy is shared for fetching the signatures of all images,
+    // and querying the registry when a pull fails; it is only created if needed.
+    let proxy = tokio::sync::OnceCell::new();
+    let fetch = |bound_image: BoundImage| {
+        let (send, pb, log, proxy) = (&send, &pb, &log, &proxy);
+        async move {
+            let image = bound_image.image.as_str();
+            if imgstore.exists(image).await? {
+                tracing::debug!("Bound image already present: {image}");
+            } else {
+                send(image, false).await;
+                let start = Instant::now();
+                pull_with_retry(imgstore, proxy, image).await?;
+                let elapsed = indicatif::HumanDuration(start.elapsed());
+                log(format!("Fetched bound image: {image} ({elapsed})"));
+            }
+            // Images which are already present are verified too, as they may have
+            // been pulled without a signature policy.
+            bound_image.verify_stored(imgstore, proxy).await?;
+            send(image, true).await;
+            pb.inc(1);
+            anyhow::Ok(())
+        }
+    };
+    // Note that if one image fails, the others are dropped; this also kills
+    // their podman processes.
+    futures_util::stream::iter(bound_images)
+        .map(fetch)
+        .buffer_unordered(MAX_CONCURRENT_PULLS)
+        .try_collect::<()>()
         .await?;
-    }
+    pb.finish_and_clear();
 
     println!("Bound images stored: {n}");
 
@@ -194,7 +701,87 @@ impl BoundImage {
             None
         };
 
-        Ok(BoundImage { image, auth_file })
+        // Digest-pinned references (`image@sha256:...`, optionally with a tag) are
+        // passed through as is, but we validate the digest.
+        let digest = image
+            .rsplit_once('@')
+            .map(|(_, digest)| digest.parse::<Digest>())
+            .transpose()
+            .context("Invalid image digest")?;
+
+        Ok(BoundImage {
+            image,
+            auth_file,
+            digest,
+            signature: None,
+        })
+    }
+
+    /// For a reference pinned with both a tag and a digest, the `name@digest` form
+    /// (without the tag) under which podman records the image.
+    pub(crate) fn stored_name(&self) -> Option<String> {
+        self.digest.as_ref()?;
+        let (name, digest) = self.image.rsplit_once('@')?;
+        // A `:` before the last `/` separates a registry port, not a tag
+        let repo_start = name.rfind('/').map_or(0, |i| i + 1);
+        let tag_start = repo_start + name[repo_start..].rfind(':')?;
+        Some(format!("{}@{digest}", &name[..tag_start]))
+    }
+
+    /// Verify a stored image against the pinned digest and signature policy, if any;
+    /// failures are reported as a [`VerificationError`].
+    async fn verify_stored(
+        &self,
+        imgstore: &crate::imgstorage::Storage,
+        proxy: &tokio::sync::OnceCell<ImageProxy>,
+    ) -> Result<()> {
+        if self.digest.is_none() && self.signature.is_none() {
+            return Ok(());
+        }
+        let image = self.image.as_str();
+        let inspect = imgstore.inspect(image).await?;
+        let verify = async {
+            self.verify_digest(&inspect)?;
+            // Signatures are checked against the pinned digest, if any
+            let digest = self
+                .digest
+                .as_ref()
+                .map_or(inspect.digest.as_str(), |d| d.as_ref());
+            if let Some(signature) = self.signature.as_ref() {
+                let proxy = proxy.get_or_try_init(crate::sigstore::new_proxy).await?;
+                signature.verify(proxy, image, digest).await?;
+            }
+            anyhow::Ok(())
+        };
+        verify
+            .await
+            .map_err(|e| e.context(VerificationError(image.to_owned())))
+    }
+
+    /// Verify that the stored image answers to the pinned digest, if any.  This
+    /// is either its manifest digest or, for a multi-architecture image, the digest
+    /// of the manifest list (index) it was pulled from, which is one of its repo digests.
+    fn verify_digest(&self, inspect: &crate::podman::Inspect) -> Result<()> {
+        let Some(expected) = self.digest.as_ref() else {
+            return Ok(());
+        };
+        let expected: &str = expected.as_ref();
+        let found = std::iter::once(inspect.digest.as_str())
+            .chain(
+                inspect
+                    .repo_digests
+                    .iter()
+                    .flatten()
+                    .filter_map(|r| r.rsplit_once('@').map(|(_, d)| d)),
+            )
+            .any(|d| d == expected);
+        anyhow::ensure!(
+            found,
+            "Bound image {} has digest {}, expected {expected}",
+            self.image,
+            inspect.digest
+        );
+        Ok(())
     }
 }
 
@@ -271,11 +858,102 @@ mod tests {
         )
         .unwrap();
 
+        const PINNED: &str = "quay.io/baz/baz:latest@sha256:b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c";
+        td.write(
+            format!("{CONTAINER_IMAGE_DIR}/baz.image"),
+            format!("[Image]\nImage={PINNED}\n"),
+        )?;
+        td.symlink_contents(
+            format!("/{CONTAINER_IMAGE_DIR}/baz.image"),
+            format!("{BOUND_IMAGE_DIR}/baz.image"),
+        )?;
         let mut images = query_bound_images(td).unwrap();
         images.sort_by(|a, b| a.image.as_str().cmp(&b.image.as_str()));
+        assert_eq!(images.len(), 3);
+        // Digests are preserved
+        let pinned = images.remove(1);
+        assert_eq!(pinned.image, PINNED);
+        assert_eq!(
+            pinned.digest.as_ref().unwrap().digest(),
+            "b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c"
+        );
+        let inspect = |digest: &str, repo_digests: &[&str]| crate::podman::Inspect {
+            digest: digest.into(),
+            repo_digests: Some(repo_digests.iter().map(|&d| d.into()).collect()),
+        };
+        const OTHER_DIGEST: &str =
+            "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+        pinned.verify_digest(&inspect(
+            "sha256:b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c",
+            &[],
+        ))?;
+        assert!(pinned.verify_digest(&inspect(OTHER_DIGEST, &[])).is_err());
+        // Pinned to the digest of a multi-architecture image index; the manifest
+        // digest is that of the image for our architecture.
+        pinned.verify_digest(&inspect(
+            OTHER_DIGEST,
+            &[
+                "quay.io/baz/baz@sha256:0000000000000000000000000000000000000000000000000000000000000000",
+                "quay.io/baz/baz@sha256:b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c",
+            ],
+        ))?;
+        assert!(pinned
+            .verify_digest(&inspect(
+                OTHER_DIGEST,
+                &["quay.io/baz/baz@sha256:0000000000000000000000000000000000000000000000000000000000000000"],
+            ))
+            .is_err());
+        assert!(images.iter().all(|i| i.digest.is_none()));
         assert_eq!(images.len(), 2);
         assert_eq!(images[0].image, "quay.io/bar/bar:latest");
         assert_eq!(images[1].image, "quay.io/foo/foo:latest");
+        assert!(images.iter().all(|i| i.signature.is_none()));
+
+        // A bootc-specific signature policy
+        td.create_dir_all("etc/pki/containers")?;
+        td.write("etc/pki/containers/app.pub", "fake key\n")?;
+        td.write(
+            format!("{CONTAINER_IMAGE_DIR}/signed.container"),
+            indoc::indoc! { r#"
+            [Container]
+            Image=quay.io/signed/signed:latest
+
+            [X-Bootc]
+            SigstorePublicKey=/etc/pki/containers/app.pub
+            "# },
+        )?;
+        td.symlink_contents(
+            format!("/{CONTAINER_IMAGE_DIR}/signed.container"),
+            format!("{BOUND_IMAGE_DIR}/signed.container"),
+        )?;
+        let images = query_bound_images(td)?;
+        let signed = images
+            .iter()
+            .find(|i| i.image == "quay.io/signed/signed:latest")
+            .unwrap();
+        assert_eq!(
+            signed.signature,
+            Some(BoundImageSignature::Sigstore {
+                path: "/etc/pki/containers/app.pub".into(),
+                public_key: "fake key\n".into()
+            })
+        );
+        // A missing key is an error
+        td.remove_file("etc/pki/containers/app.pub")?;
+        assert!(query_bound_images(td).is_err());
+        td.remove_file(format!("{BOUND_IMAGE_DIR}/signed.container"))?;
+
+        // An invalid digest should return an error
+        td.write(
+            "invalid-digest.image",
+            "[Image]\nImage=quay.io/foo/foo@sha256:foo\n",
+        )?;
+        td.symlink_contents(
+            "/invalid-digest.image",
+            format!("{BOUND_IMAGE_DIR}/invalid-digest.image"),
+        )?;
+        assert!(query_bound_images(td).is_err());
+        td.remove_file(format!("{BOUND_IMAGE_DIR}/invalid-digest.image"))?;
 
         // Invalid symlink should return an error
         td.symlink("./blah", format!("{BOUND_IMAGE_DIR}/blah.image"))
@@ -291,6 +969,209 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_quadlets() -> Result<()> {
+        const QUADLET_DIR: &str = "usr/share/containers/systemd";
+        let td = &cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        td.create_dir_all(BOUND_IMAGE_DIR)?;
+        td.create_dir_all(format!("{QUADLET_DIR}/app"))?;
+        let bind = |name: &str, contents: &str| -> Result<()> {
+            td.write(format!("{QUADLET_DIR}/{name}"), contents)?;
+            let link = Utf8Path::new(name).file_name().unwrap();
+            td.symlink_contents(
+                format!("/{QUADLET_DIR}/{name}"),
+                format!("{BOUND_IMAGE_DIR}/{link}"),
+            )?;
+            Ok(())
+        };
+
+        // Relative to the quadlet, not the bound images directory
+        td.write(
+            format!("{QUADLET_DIR}/app/app.yaml"),
+            indoc::indoc! { r#"
+            apiVersion: v1
+            kind: Pod
+            metadata:
+              name: app
+            spec:
+              initContainers:
+              - name: init
+                image: quay.io/example/init:latest
+              containers:
+              - name: web
+                image: quay.io/example/web:latest
+              - name: sidecar
+                image: quay.io/example/init:latest
+            ---
+            apiVersion: apps/v1
+            kind: Deployment
+            spec:
+              template:
+                spec:
+                  containers:
+                  - name: db
+                    image: quay.io/example/db:latest
+            "# },
+        )?;
+        bind("app/app.kube", "[Kube]\nYaml=app.yaml\n")?;
+        bind(
+            "data.volume",
+            "[Volume]\nDriver=image\nImage=quay.io/example/data:latest\n",
+        )?;
+        bind("plain.volume", "[Volume]\nDevice=tmpfs\n")?;
+        td.write(
+            format!("{QUADLET_DIR}/app/Containerfile"),
+            indoc::indoc! { r#"
+            # A multi-stage build
+            FROM --platform=$BUILDPLATFORM \
+              quay.io/example/builder:latest AS builder
+            RUN make
+            from builder as test
+            FROM scratch AS empty
+            FROM quay.io/example/base:latest
+            COPY --from=builder /app /app
+            "# },
+        )?;
+        bind(
+            "app/app.build",
+            "[Build]\nImageTag=localhost/app\nFile=Containerfile\n",
+        )?;
+        // References to other quadlets are not images
+        bind("built.container", "[Container]\nImage=app.build\n")?;
+
+        let mut images = query_bound_images(td)?
+            .into_iter()
+            .map(|i| i.image)
+            .collect::<Vec<_>>();
+        images.sort();
+        assert_eq!(
+            images,
+            [
+                "quay.io/example/base:latest",
+                "quay.io/example/builder:latest",
+                "quay.io/example/data:latest",
+                "quay.io/example/db:latest",
+                "quay.io/example/init:latest",
+                "quay.io/example/web:latest"
+            ]
+        );
+
+        // A missing YAML file is an error
+        bind("missing.kube", "[Kube]\nYaml=/etc/missing.yaml\n")?;
+        assert!(query_bound_images(td).is_err());
+        td.remove_file(format!("{BOUND_IMAGE_DIR}/missing.kube"))?;
+        bind("noyaml.kube", "[Kube]\n")?;
+        assert!(query_bound_images(td).is_err());
+        td.remove_file(format!("{BOUND_IMAGE_DIR}/noyaml.kube"))?;
+        // Only builds from a Containerfile given by File= are supported
+        bind("context.build", "[Build]\nSetWorkingDirectory=unit\n")?;
+        assert!(query_bound_images(td).is_err());
+        td.remove_file(format!("{BOUND_IMAGE_DIR}/context.build"))?;
+        td.write(format!("{QUADLET_DIR}/arg.Containerfile"), "FROM $BASE\n")?;
+        bind("arg.build", "[Build]\nFile=arg.Containerfile\n")?;
+        assert!(query_bound_images(td).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_bound_images() -> Result<()> {
+        let td = &cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        assert_eq!(query_local_bound_images(td)?.len(), 0);
+        let images = ["quay.io/example/app:latest".to_string()];
+        write_local_bound_images(td, &images)?;
+        assert_eq!(query_local_bound_images(td)?, images);
+        write_local_bound_images(td, &[])?;
+        assert_eq!(query_local_bound_images(td)?.len(), 0);
+
+        td.write(LOCAL_BOUND_IMAGES, "foo = 42")?;
+        assert!(query_local_bound_images(td).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_sigstore_public_key() {
+        let ini = |s: &str| tini::Ini::from_string(s).unwrap();
+        let none = ini("[Image]\nImage=quay.io/foo/foo:latest\n");
+        assert_eq!(parse_sigstore_public_key(&none).unwrap(), None);
+        let key = ini("[X-Bootc]\nSigstorePublicKey=/etc/pki/foo.pub\n");
+        assert_eq!(
+            parse_sigstore_public_key(&key).unwrap().as_deref(),
+            Some("/etc/pki/foo.pub")
+        );
+        let relative = ini("[X-Bootc]\nSigstorePublicKey=foo.pub\n");
+        assert!(parse_sigstore_public_key(&relative).is_err());
+        let specifier = ini("[X-Bootc]\nSigstorePublicKey=/etc/%a.pub\n");
+        assert!(parse_sigstore_public_key(&specifier).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retry_pull() {
+        let pulls = Cell::new(0);
+        let pull = |ok_after: u32| {
+            let pulls = &pulls;
+            move || {
+                pulls.set(pulls.get() + 1);
+                let ok = pulls.get() >= ok_after;
+                async move { ok.then_some(()).ok_or_else(|| anyhow::anyhow!("failed")) }
+            }
+        };
+        retry_pull("a", Duration::ZERO, pull(PULL_ATTEMPTS), || async { true })
+            .await
+            .unwrap();
+        assert_eq!(pulls.replace(0), PULL_ATTEMPTS);
+        let e = retry_pull("a", Duration::ZERO, pull(u32::MAX), || async { true })
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "failed");
+        assert_eq!(pulls.replace(0), PULL_ATTEMPTS);
+        // A failure which is not transient is not retried
+        retry_pull("a", Duration::ZERO, pull(u32::MAX), || async { false })
+            .await
+            .unwrap_err();
+        assert_eq!(pulls.replace(0), 1);
+    }
+
+    #[test]
+    fn test_image_steps_event() {
+        let step = |id: &'static str, completed: bool| SubTaskStep {
+            subtask: id.into(),
+            description: id.into(),
+            id: id.into(),
+            completed,
+        };
+        let steps = Event::ProgressSteps {
+            task: "staging".into(),
+            description: "Deploying Image".into(),
+            id: "sha256:abc".into(),
+            steps_cached: 0,
+            steps: 1,
+            steps_total: 3,
+            subtasks: vec![step("deploying", true), step("bound_images", false)],
+        };
+        let subtasks = |event: Event| match event {
+            Event::ProgressSteps { subtasks, .. } => subtasks
+                .into_iter()
+                .map(|s| (s.id.into_owned(), s.completed))
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        let mut images = Vec::new();
+        image_steps_event(&steps, &mut images, "quay.io/example/a", false);
+        image_steps_event(&steps, &mut images, "quay.io/example/b", false);
+        let event = image_steps_event(&steps, &mut images, "quay.io/example/a", true);
+        assert_eq!(
+            subtasks(event),
+            [
+                ("deploying".to_owned(), true),
+                ("bound_images".to_owned(), false),
+                ("quay.io/example/a".to_owned(), true),
+                ("quay.io/example/b".to_owned(), false),
+            ]
+        );
+        assert!(images.iter().all(|s| s.subtask == "bound_image"));
+    }
+
     #[test]
     fn test_parse_spec_value() -> Result<()> {
         //should parse string with no % characters
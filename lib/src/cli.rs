@@ -29,11 +29,11 @@ use crate::deploy::RequiredHostSpec;
 use crate::lints;
 use crate::progress_jsonl::{ProgressWriter, RawProgressFd};
 use crate::spec::Host;
-use crate::spec::ImageReference;
+use crate::spec::{ImageReference, ImageSignature};
 use crate::utils::sigpolicy_from_opt;
 
 /// Shared progress options
-#[derive(Debug, Parser, PartialEq, Eq)]
+#[derive(Debug, Default, Parser, PartialEq, Eq)]
 pub(crate) struct ProgressOptions {
     /// File descriptor number which must refer to an open pipe (anonymous or named).
     ///
@@ -56,6 +56,17 @@ impl TryFrom<ProgressOptions> for ProgressWriter {
     }
 }
 
+/// Render a progress stream
+#[derive(Debug, Parser, PartialEq, Eq)]
+pub(crate) struct ProgressRenderOpts {
+    /// Read the stream from this file or named pipe; `-` or no argument reads standard input.
+    pub(crate) input: Option<Utf8PathBuf>,
+
+    /// Read the stream from this file descriptor, which must be open for reading.
+    #[clap(long, conflicts_with = "input")]
+    pub(crate) fd: Option<RawProgressFd>,
+}
+
 /// Perform an upgrade operation
 #[derive(Debug, Parser, PartialEq, Eq)]
 pub(crate) struct UpgradeOpts {
@@ -72,12 +83,37 @@ pub(crate) struct UpgradeOpts {
 
     /// Restart or reboot into the new target image.
     ///
-    /// Currently, this option always reboots.  In the future this command
-    /// will detect the case where no kernel changes are queued, and perform
-    /// a userspace-only restart.
+    /// If the kernel, initramfs and kernel arguments are unchanged from the
+    /// booted deployment, a userspace-only restart (`systemctl soft-reboot`)
+    /// is performed; otherwise the system is rebooted.
     #[clap(long, conflicts_with = "check")]
     pub(crate) apply: bool,
 
+    /// Download and stage the update, but do not apply it at shutdown.
+    ///
+    /// The staged update will only be applied once requested via `bootc upgrade --apply`
+    /// or `bootc upgrade --from-downloaded`.
+    #[clap(long, conflicts_with_all = ["check", "apply"])]
+    pub(crate) download_only: bool,
+
+    /// Apply a staged update previously downloaded via `--download-only`, without
+    /// fetching from the container image source.
+    ///
+    /// The update will be applied at the next shutdown; combine with `--apply` to
+    /// reboot immediately.
+    #[clap(long, conflicts_with_all = ["check", "download_only"])]
+    pub(crate) from_downloaded: bool,
+
+    /// Allow staging an image which is older than the booted image, as determined
+    /// by its creation timestamp or version.
+    #[clap(long)]
+    pub(crate) allow_downgrade: bool,
+
+    /// Operate on the ostree sysroot at this path (e.g. a mounted disk image) instead of
+    /// the booted system. The update is written as the default boot entry.
+    #[clap(long, conflicts_with_all = ["apply", "download_only", "from_downloaded"])]
+    pub(crate) sysroot: Option<Utf8PathBuf>,
+
     #[clap(flatten)]
     pub(crate) progress: ProgressOptions,
 }
@@ -91,9 +127,9 @@ pub(crate) struct SwitchOpts {
 
     /// Restart or reboot into the new target image.
     ///
-    /// Currently, this option always reboots.  In the future this command
-    /// will detect the case where no kernel changes are queued, and perform
-    /// a userspace-only restart.
+    /// If the kernel, initramfs and kernel arguments are unchanged from the
+    /// booted deployment, a userspace-only restart (`systemctl soft-reboot`)
+    /// is performed; otherwise the system is rebooted.
     #[clap(long)]
     pub(crate) apply: bool,
 
@@ -113,6 +149,14 @@ pub(crate) struct SwitchOpts {
     #[clap(long)]
     pub(crate) enforce_container_sigpolicy: bool,
 
+    /// Verify sigstore (cosign) signatures and attestations of the target image
+    /// using the PEM-encoded public key at this path.
+    ///
+    /// This is performed by bootc itself before staging, and the key is retained
+    /// for future upgrades.
+    #[clap(long, conflicts_with = "enforce_container_sigpolicy")]
+    pub(crate) sigstore_public_key: Option<Utf8PathBuf>,
+
     /// Don't create a new deployment, but directly mutate the booted state.
     /// This is hidden because it's not something we generally expect to be done,
     /// but this can be used in e.g. Anaconda %post to fixup
@@ -123,6 +167,16 @@ pub(crate) struct SwitchOpts {
     #[clap(long)]
     pub(crate) retain: bool,
 
+    /// Allow switching to an image which is older than the booted image, as determined
+    /// by its creation timestamp or version.
+    #[clap(long)]
+    pub(crate) allow_downgrade: bool,
+
+    /// Operate on the ostree sysroot at this path (e.g. a mounted disk image) instead of
+    /// the booted system. The new image is written as the default boot entry.
+    #[clap(long, conflicts_with_all = ["apply", "mutate_in_place"])]
+    pub(crate) sysroot: Option<Utf8PathBuf>,
+
     /// Target image to use for the next boot.
     pub(crate) target: String,
 
@@ -135,13 +189,29 @@ pub(crate) struct SwitchOpts {
 pub(crate) struct RollbackOpts {
     /// Restart or reboot into the rollback image.
     ///
-    /// Currently, this option always reboots.  In the future this command
-    /// will detect the case where no kernel changes are queued, and perform
-    /// a userspace-only restart.
+    /// If the kernel, initramfs and kernel arguments are unchanged from the
+    /// booted deployment, a userspace-only restart (`systemctl soft-reboot`)
+    /// is performed; otherwise the system is rebooted.
     #[clap(long)]
     pub(crate) apply: bool,
 }
 
+/// Options for displaying the deployment history
+#[derive(Debug, Parser, PartialEq, Eq)]
+pub(crate) struct HistoryOpts {
+    /// The output format.
+    #[clap(long)]
+    pub(crate) format: Option<OutputFormat>,
+}
+
+/// Options for pinning or unpinning a deployment
+#[derive(Debug, Parser, PartialEq, Eq)]
+pub(crate) struct PinOpts {
+    /// The deployment: one of `booted`, `rollback`, `staged`, or a numeric index into
+    /// the deployment list (as ordered by `ostree admin status`).
+    pub(crate) deployment: crate::deploy::DeploymentRef,
+}
+
 /// Perform an edit operation
 #[derive(Debug, Parser, PartialEq, Eq)]
 pub(crate) struct EditOpts {
@@ -165,6 +235,20 @@ pub(crate) enum OutputFormat {
     Json,
 }
 
+/// The output formats supported by `bootc status`.
+#[derive(Debug, Clone, ValueEnum, PartialEq, Eq)]
+#[clap(rename_all = "lowercase")]
+pub(crate) enum StatusOutputFormat {
+    /// Output in Human Readable format.
+    HumanReadable,
+    /// Output in YAML format.
+    Yaml,
+    /// Output in JSON format.
+    Json,
+    /// Output as metrics in the Prometheus text format.
+    Prometheus,
+}
+
 /// Perform an status operation
 #[derive(Debug, Parser, PartialEq, Eq)]
 pub(crate) struct StatusOpts {
@@ -176,7 +260,7 @@ pub(crate) struct StatusOpts {
 
     /// The output format.
     #[clap(long)]
-    pub(crate) format: Option<OutputFormat>,
+    pub(crate) format: Option<StatusOutputFormat>,
 
     /// The desired format version. There is currently one supported
     /// version, which is exposed as both `0` and `1`. Pass this
@@ -188,6 +272,16 @@ pub(crate) struct StatusOpts {
     /// Only display status for the booted deployment.
     #[clap(long)]
     pub(crate) booted: bool,
+
+    /// Include more details in the human readable output, such as the kernel
+    /// version, kernel arguments, bound images and composefs state of each deployment.
+    #[clap(long, short = 'v')]
+    pub(crate) verbose: bool,
+
+    /// Inspect the ostree sysroot at this path (e.g. a mounted disk image) instead of
+    /// the booted system. The default boot entry is shown in place of the booted one.
+    #[clap(long)]
+    pub(crate) sysroot: Option<Utf8PathBuf>,
 }
 
 #[derive(Debug, clap::Subcommand, PartialEq, Eq)]
@@ -249,6 +343,28 @@ pub(crate) struct ManOpts {
     pub(crate) directory: Utf8PathBuf,
 }
 
+/// Operations on machine-local kernel arguments.
+#[derive(Debug, clap::Subcommand, PartialEq, Eq)]
+pub(crate) enum KargsOpts {
+    /// List the machine-local kernel arguments.
+    List,
+    /// Add machine-local kernel arguments.
+    Add {
+        /// The kernel arguments to add, e.g. `console=ttyS0,115200`
+        #[clap(required = true)]
+        kargs: Vec<String>,
+    },
+    /// Remove machine-local kernel arguments.
+    Remove {
+        /// The kernel arguments to remove
+        #[clap(required = true)]
+        kargs: Vec<String>,
+    },
+    /// Edit the machine-local kernel arguments in the system default `$EDITOR`,
+    /// one per line.
+    Edit,
+}
+
 /// Subcommands which can be executed as part of a container build.
 #[derive(Debug, clap::Subcommand, PartialEq, Eq)]
 pub(crate) enum ContainerOpts {
@@ -441,6 +557,12 @@ pub(crate) enum InternalsOpts {
     Fsck,
     /// Perform cleanup actions
     Cleanup,
+    /// Run health checks on the booted deployment, rolling back if they fail.
+    AssessBoot,
+    /// Record the first boot of the booted deployment in the history.
+    RecordBoot,
+    /// Serve the varlink API on the socket passed via systemd socket activation.
+    Varlink,
     Relabel {
         #[clap(long)]
         /// Relabel using this path as root
@@ -506,8 +628,8 @@ pub(crate) enum Opt {
     /// There is also an explicit `bootc upgrade --apply` verb which will automatically take action (rebooting)
     /// if the system has changed.
     ///
-    /// However, in the future this is likely to change such that reboots outside of a `bootc upgrade --apply`
-    /// do *not* automatically apply the update in addition.
+    /// With `--download-only`, the update is staged but will *not* be applied by an unrelated reboot;
+    /// it is only applied once requested via `bootc upgrade --apply` or `bootc upgrade --from-downloaded`.
     #[clap(alias = "update")]
     Upgrade(UpgradeOpts),
     /// Target a new container image reference to boot.
@@ -545,6 +667,32 @@ pub(crate) enum Opt {
         merges happen when new deployments are created.
     "#})]
     Rollback(RollbackOpts),
+    /// Pin a deployment, so that it is retained (along with its container image
+    /// and bound images) and not garbage collected.
+    ///
+    /// Pinned deployments are shown as `Pinned: yes` in `bootc status`.
+    Pin(PinOpts),
+    /// Remove the pin from a deployment, allowing it to be garbage collected.
+    Unpin(PinOpts),
+    /// Display the history of images staged, switched to, rolled back to and booted.
+    ///
+    /// This history is retained even after the corresponding deployments
+    /// are garbage collected.
+    History(HistoryOpts),
+    /// Manage machine-local kernel arguments.
+    ///
+    /// These are stored in `/etc/bootc/kargs.d`, and are applied in addition to the
+    /// kernel arguments from the container image to every new deployment.  Changes
+    /// are also applied to the booted deployment, taking effect on the next boot.
+    #[clap(subcommand)]
+    Kargs(KargsOpts),
+    /// Display a progress stream, as written via `--progress-fd`.
+    ///
+    /// The stream is validated, and rendered as progress bars if standard output
+    /// is a terminal, and as a compact log otherwise.
+    ///
+    /// Stability: This interface is experimental, as is `--progress-fd`.
+    Progress(ProgressRenderOpts),
     /// Apply full changes to the host specification.
     ///
     /// This command operates very similarly to `kubectl apply`; if invoked interactively,
@@ -553,7 +701,9 @@ pub(crate) enum Opt {
     ///
     /// It is also possible to directly provide new contents via `bootc edit --filename`.
     ///
-    /// Only changes to the `spec` section are honored.
+    /// Only changes to the `spec` section are honored.  Besides the image and boot order,
+    /// this includes machine-local kernel arguments (`spec.kargs`) and additional
+    /// container images to keep pulled (`spec.boundImages`).
     Edit(EditOpts),
     /// Display status
     ///
@@ -690,6 +840,27 @@ pub(crate) async fn get_storage() -> Result<crate::store::Storage> {
     crate::store::Storage::new(sysroot, &global_run)
 }
 
+/// Load storage state from an offline (not booted) sysroot, such as a mounted disk image.
+#[context("Initializing storage for {sysroot}")]
+pub(crate) async fn get_storage_offline(
+    sysroot: &camino::Utf8Path,
+) -> Result<crate::store::Storage> {
+    let global_run = Dir::open_ambient_dir("/run", cap_std::ambient_authority())?;
+    let sysroot = ostree::Sysroot::new(Some(&gio::File::for_path(sysroot)));
+    sysroot.load(gio::Cancellable::NONE)?;
+    let sysroot = ostree_ext::sysroot::SysrootLock::new_from_sysroot(&sysroot).await?;
+    crate::store::Storage::new_offline(sysroot, &global_run)
+}
+
+/// Load storage state from the offline sysroot at the given path if any, otherwise
+/// from the booted system.
+async fn get_storage_for(sysroot: Option<&camino::Utf8Path>) -> Result<crate::store::Storage> {
+    match sysroot {
+        Some(sysroot) => get_storage_offline(sysroot).await,
+        None => get_storage().await,
+    }
+}
+
 #[context("Querying root privilege")]
 pub(crate) fn require_root(is_container: bool) -> Result<()> {
     ensure!(
@@ -754,13 +925,22 @@ fn prepare_for_write() -> Result<()> {
 
 /// Implementation of the `bootc upgrade` CLI command.
 #[context("Upgrading")]
-async fn upgrade(opts: UpgradeOpts) -> Result<()> {
-    let sysroot = &get_storage().await?;
+pub(crate) async fn upgrade(mut opts: UpgradeOpts) -> Result<()> {
+    // Take ownership of the progress fd first, so it is closed on any error
+    let prog: ProgressWriter = std::mem::take(&mut opts.progress).try_into()?;
+    let r = upgrade_impl(opts, &prog).await;
+    if let Err(e) = &r {
+        prog.send_failure("upgrade", e).await;
+    }
+    r
+}
+
+async fn upgrade_impl(opts: UpgradeOpts, prog: &ProgressWriter) -> Result<()> {
+    let sysroot = &get_storage_for(opts.sysroot.as_deref()).await?;
     let repo = &sysroot.repo();
     let (booted_deployment, _deployments, host) =
-        crate::status::get_status_require_booted(sysroot)?;
+        crate::status::get_status_require_booted_or_default(sysroot)?;
     let imgref = host.spec.image.as_ref();
-    let prog: ProgressWriter = opts.progress.try_into()?;
 
     // If there's no specified image, let's be nice and check if the booted system is using rpm-ostree
     if imgref.is_none() {
@@ -783,10 +963,25 @@ async fn upgrade(opts: UpgradeOpts) -> Result<()> {
         }
     }
 
+    if opts.from_downloaded {
+        anyhow::ensure!(
+            host.status.staged.as_ref().is_some_and(|s| s.download_only),
+            "No downloaded update is staged"
+        );
+        crate::deploy::set_staged_locked(sysroot, false)?;
+        println!("Staged update will be applied at next boot.");
+        sysroot.update_mtime()?;
+        if opts.apply {
+            apply_staged(sysroot)?;
+        }
+        return Ok(());
+    }
+
     let spec = RequiredHostSpec::from_spec(&host.spec)?;
     let booted_image = host
         .status
         .booted
+        .as_ref()
         .map(|b| b.query_image(repo))
         .transpose()?
         .flatten();
@@ -809,6 +1004,16 @@ async fn upgrade(opts: UpgradeOpts) -> Result<()> {
                     println!("  Version: {version}");
                 }
                 println!("  Digest: {}", r.manifest_digest);
+                let layers_to_fetch = r.layers_to_fetch().collect::<Result<Vec<_>>>()?;
+                let required = crate::deploy::estimate_required_space(
+                    layers_to_fetch.iter().map(|(l, _)| &l.layer),
+                );
+                let available = crate::deploy::available_space(repo)?;
+                println!(
+                    "  Estimated disk space required: {} ({} available)",
+                    ostree::glib::format_size(required),
+                    ostree::glib::format_size(available)
+                );
                 changed = true;
                 if let Some(previous_image) = booted_image.as_ref() {
                     let diff =
@@ -833,15 +1038,45 @@ async fn upgrade(opts: UpgradeOpts) -> Result<()> {
             .unwrap_or_default();
         if staged_unchanged {
             println!("Staged update present, not changed.");
+            // An explicit --apply overrides a previous --download-only, and vice versa
+            let locked =
+                !opts.apply && (opts.download_only || staged.is_some_and(|s| s.download_only));
+            if crate::deploy::set_staged_locked(sysroot, locked)? {
+                sysroot.update_mtime()?;
+            }
+            if locked {
+                println!(
+                    "Staged update is download only; use --apply or --from-downloaded to apply it."
+                );
+            }
 
             if opts.apply {
-                crate::reboot::reboot()?;
+                apply_staged(sysroot)?;
             }
         } else if booted_unchanged {
             println!("No update available.")
         } else {
+            if !opts.allow_downgrade {
+                let booted = host.status.booted.as_ref().and_then(|b| b.image.as_ref());
+                crate::deploy::verify_not_downgrade(booted, imgref, &fetched)?;
+            }
             let osname = booted_deployment.osname();
-            crate::deploy::stage(sysroot, &osname, &fetched, &spec, prog.clone()).await?;
+            let deployment = crate::deploy::stage(
+                sysroot,
+                &osname,
+                &fetched,
+                &spec,
+                opts.download_only,
+                prog.clone(),
+            )
+            .await?;
+            crate::history::record_staged(
+                sysroot,
+                crate::history::HistoryEvent::Stage,
+                spec.image,
+                &fetched,
+                &deployment,
+            )?;
             changed = true;
             if let Some(prev) = booted_image.as_ref() {
                 if let Some(fetched_manifest) = fetched.get_manifest(repo)? {
@@ -856,7 +1091,7 @@ async fn upgrade(opts: UpgradeOpts) -> Result<()> {
         sysroot.update_mtime()?;
 
         if opts.apply {
-            crate::reboot::reboot()?;
+            apply_staged(sysroot)?;
         }
     } else {
         tracing::debug!("No changes");
@@ -867,7 +1102,17 @@ async fn upgrade(opts: UpgradeOpts) -> Result<()> {
 
 /// Implementation of the `bootc switch` CLI command.
 #[context("Switching")]
-async fn switch(opts: SwitchOpts) -> Result<()> {
+pub(crate) async fn switch(mut opts: SwitchOpts) -> Result<()> {
+    // Take ownership of the progress fd first, so it is closed on any error
+    let prog: ProgressWriter = std::mem::take(&mut opts.progress).try_into()?;
+    let r = switch_impl(opts, &prog).await;
+    if let Err(e) = &r {
+        prog.send_failure("switch", e).await;
+    }
+    r
+}
+
+async fn switch_impl(opts: SwitchOpts, prog: &ProgressWriter) -> Result<()> {
     let transport = ostree_container::Transport::try_from(opts.transport.as_str())?;
     let imgref = ostree_container::ImageReference {
         transport,
@@ -875,8 +1120,16 @@ async fn switch(opts: SwitchOpts) -> Result<()> {
     };
     let sigverify = sigpolicy_from_opt(opts.enforce_container_sigpolicy);
     let target = ostree_container::OstreeImageReference { sigverify, imgref };
-    let target = ImageReference::from(target);
-    let prog: ProgressWriter = opts.progress.try_into()?;
+    let mut target = ImageReference::from(target);
+    if let Some(public_key) = opts.sigstore_public_key.as_ref() {
+        // Canonicalize so the configuration doesn't depend on the working directory
+        let public_key = public_key
+            .canonicalize_utf8()
+            .with_context(|| format!("Opening {public_key}"))?;
+        target.signature = Some(ImageSignature::Sigstore {
+            public_key: public_key.into_string(),
+        });
+    }
 
     // If we're doing an in-place mutation, we shortcut most of the rest of the work here
     if opts.mutate_in_place {
@@ -895,10 +1148,10 @@ async fn switch(opts: SwitchOpts) -> Result<()> {
 
     let cancellable = gio::Cancellable::NONE;
 
-    let sysroot = &get_storage().await?;
+    let sysroot = &get_storage_for(opts.sysroot.as_deref()).await?;
     let repo = &sysroot.repo();
     let (booted_deployment, _deployments, host) =
-        crate::status::get_status_require_booted(sysroot)?;
+        crate::status::get_status_require_booted_or_default(sysroot)?;
 
     let new_spec = {
         let mut new_spec = host.spec.clone();
@@ -914,6 +1167,11 @@ async fn switch(opts: SwitchOpts) -> Result<()> {
 
     let fetched = crate::deploy::pull(repo, &target, None, opts.quiet, prog.clone()).await?;
 
+    if !opts.allow_downgrade {
+        let booted = host.status.booted.as_ref().and_then(|b| b.image.as_ref());
+        crate::deploy::verify_not_downgrade(booted, &target, &fetched)?;
+    }
+
     if !opts.retain {
         // By default, we prune the previous ostree ref so it will go away after later upgrades
         if let Some(booted_origin) = booted_deployment.origin() {
@@ -926,25 +1184,53 @@ async fn switch(opts: SwitchOpts) -> Result<()> {
     }
 
     let stateroot = booted_deployment.osname();
-    crate::deploy::stage(sysroot, &stateroot, &fetched, &new_spec, prog.clone()).await?;
+    let deployment = crate::deploy::stage(
+        sysroot,
+        &stateroot,
+        &fetched,
+        &new_spec,
+        false,
+        prog.clone(),
+    )
+    .await?;
+    crate::history::record_staged(
+        sysroot,
+        crate::history::HistoryEvent::Switch,
+        &target,
+        &fetched,
+        &deployment,
+    )?;
 
     sysroot.update_mtime()?;
 
     if opts.apply {
-        crate::reboot::reboot()?;
+        apply_staged(sysroot)?;
     }
 
     Ok(())
 }
 
+/// Restart into the staged deployment.
+fn apply_staged(sysroot: &crate::store::Storage) -> Result<()> {
+    let staged = sysroot
+        .staged_deployment()
+        .ok_or_else(|| anyhow::anyhow!("No staged deployment"))?;
+    crate::reboot::reboot_into(sysroot, &staged)
+}
+
 /// Implementation of the `bootc rollback` CLI command.
 #[context("Rollback")]
-async fn rollback(opts: RollbackOpts) -> Result<()> {
+pub(crate) async fn rollback(opts: RollbackOpts) -> Result<()> {
     let sysroot = &get_storage().await?;
     crate::deploy::rollback(sysroot).await?;
 
     if opts.apply {
-        crate::reboot::reboot()?;
+        let target = sysroot
+            .deployments()
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No deployments found"))?;
+        crate::reboot::reboot_into(sysroot, &target)?;
     }
 
     Ok(())
@@ -952,7 +1238,7 @@ async fn rollback(opts: RollbackOpts) -> Result<()> {
 
 /// Implementation of the `bootc edit` CLI command.
 #[context("Editing spec")]
-async fn edit(opts: EditOpts) -> Result<()> {
+pub(crate) async fn edit(opts: EditOpts) -> Result<()> {
     let sysroot = &get_storage().await?;
     let repo = &sysroot.repo();
 
@@ -974,22 +1260,61 @@ async fn edit(opts: EditOpts) -> Result<()> {
         return Ok(());
     }
     host.spec.verify_transition(&new_host.spec)?;
-    let new_spec = RequiredHostSpec::from_spec(&new_host.spec)?;
-
-    let prog = ProgressWriter::default();
 
-    // We only support two state transitions right now; switching the image,
-    // or flipping the bootloader ordering.
+    // Flipping the bootloader ordering is exclusive with other changes.
     if host.spec.boot_order != new_host.spec.boot_order {
         return crate::deploy::rollback(sysroot).await;
     }
 
+    // Machine-local state is reconciled first, so that it is carried into
+    // a newly staged deployment.
+    let booted_root = &crate::utils::deployment_fd(sysroot, &booted_deployment)?;
+    if host.spec.kargs != new_host.spec.kargs {
+        crate::kargs::reconcile_local_kargs(
+            sysroot,
+            booted_root,
+            &host.spec.kargs,
+            &new_host.spec.kargs,
+        )?;
+    }
+    if host.spec.bound_images != new_host.spec.bound_images {
+        crate::boundimage::reconcile_local_bound_images(
+            sysroot,
+            booted_root,
+            &new_host.spec.bound_images,
+            &ProgressWriter::default(),
+        )
+        .await?;
+    }
+    if host.spec.image == new_host.spec.image {
+        sysroot.update_mtime()?;
+        return Ok(());
+    }
+
+    let new_spec = RequiredHostSpec::from_spec(&new_host.spec)?;
+    let prog = ProgressWriter::default();
+
     let fetched = crate::deploy::pull(repo, new_spec.image, None, opts.quiet, prog.clone()).await?;
 
     // TODO gc old layers here
 
     let stateroot = booted_deployment.osname();
-    crate::deploy::stage(sysroot, &stateroot, &fetched, &new_spec, prog.clone()).await?;
+    let deployment = crate::deploy::stage(
+        sysroot,
+        &stateroot,
+        &fetched,
+        &new_spec,
+        false,
+        prog.clone(),
+    )
+    .await?;
+    crate::history::record_staged(
+        sysroot,
+        crate::history::HistoryEvent::Stage,
+        new_spec.image,
+        &fetched,
+        &deployment,
+    )?;
 
     sysroot.update_mtime()?;
 
@@ -1094,6 +1419,17 @@ async fn run_from_opt(opt: Opt) -> Result<()> {
         Opt::Upgrade(opts) => upgrade(opts).await,
         Opt::Switch(opts) => switch(opts).await,
         Opt::Rollback(opts) => rollback(opts).await,
+        Opt::Pin(opts) => {
+            let sysroot = &get_storage().await?;
+            crate::deploy::set_pinned(sysroot, opts.deployment, true).await
+        }
+        Opt::Unpin(opts) => {
+            let sysroot = &get_storage().await?;
+            crate::deploy::set_pinned(sysroot, opts.deployment, false).await
+        }
+        Opt::History(opts) => crate::history::history(opts.format).await,
+        Opt::Kargs(opts) => crate::kargs::local_kargs(opts).await,
+        Opt::Progress(opts) => crate::progress_render::render(opts),
         Opt::Edit(opts) => edit(opts).await,
         Opt::UsrOverlay => usroverlay().await,
         Opt::Container(opts) => match opts {
@@ -1244,6 +1580,15 @@ async fn run_from_opt(opt: Opt) -> Result<()> {
                 let sysroot = get_storage().await?;
                 crate::deploy::cleanup(&sysroot).await
             }
+            InternalsOpts::AssessBoot => {
+                let sysroot = get_storage().await?;
+                crate::assessment::assess(&sysroot).await
+            }
+            InternalsOpts::RecordBoot => {
+                let sysroot = get_storage().await?;
+                crate::history::record_boot(&sysroot)
+            }
+            InternalsOpts::Varlink => crate::varlink::serve().await,
             InternalsOpts::Relabel { as_path, path } => {
                 let root = &Dir::open_ambient_dir("/", cap_std::ambient_authority())?;
                 let path = path.strip_prefix("/")?;
@@ -1343,7 +1688,9 @@ mod tests {
                 json: false,
                 format: None,
                 format_version: None,
-                booted: false
+                booted: false,
+                verbose: false,
+                sysroot: None
             })
         ));
         assert!(matches!(
@@ -1353,6 +1700,116 @@ mod tests {
                 ..
             })
         ));
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "status", "-v"]),
+            Opt::Status(StatusOpts { verbose: true, .. })
+        ));
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "status", "--format", "prometheus"]),
+            Opt::Status(StatusOpts {
+                format: Some(StatusOutputFormat::Prometheus),
+                ..
+            })
+        ));
+        assert!(Opt::try_parse_from(["bootc", "history", "--format", "prometheus"]).is_err());
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "status", "--sysroot", "/mnt/disk"]),
+            Opt::Status(StatusOpts {
+                sysroot: Some(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "pin", "rollback"]),
+            Opt::Pin(PinOpts {
+                deployment: crate::deploy::DeploymentRef::Slot(crate::status::Slot::Rollback)
+            })
+        ));
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "unpin", "2"]),
+            Opt::Unpin(PinOpts {
+                deployment: crate::deploy::DeploymentRef::Index(2)
+            })
+        ));
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "upgrade", "--download-only"]),
+            Opt::Upgrade(UpgradeOpts {
+                download_only: true,
+                apply: false,
+                ..
+            })
+        ));
+        assert!(Opt::try_parse_from(["bootc", "upgrade", "--download-only", "--apply"]).is_err());
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "upgrade", "--sysroot", "/mnt/disk"]),
+            Opt::Upgrade(UpgradeOpts {
+                sysroot: Some(_),
+                ..
+            })
+        ));
+        assert!(
+            Opt::try_parse_from(["bootc", "upgrade", "--sysroot", "/mnt/disk", "--apply"]).is_err()
+        );
+        assert!(matches!(
+            Opt::parse_including_static([
+                "bootc",
+                "switch",
+                "--sysroot",
+                "/mnt/disk",
+                "quay.io/example/os:latest"
+            ]),
+            Opt::Switch(SwitchOpts {
+                sysroot: Some(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            Opt::parse_including_static([
+                "bootc",
+                "switch",
+                "--transport",
+                "oci",
+                "--sigstore-public-key",
+                "/etc/pki/cosign.pub",
+                "/var/lib/images/os"
+            ]),
+            Opt::Switch(SwitchOpts {
+                sigstore_public_key: Some(_),
+                ..
+            })
+        ));
+        assert!(Opt::try_parse_from([
+            "bootc",
+            "switch",
+            "--enforce-container-sigpolicy",
+            "--sigstore-public-key",
+            "/etc/pki/cosign.pub",
+            "quay.io/example/os:latest"
+        ])
+        .is_err());
+        assert_eq!(
+            Opt::parse_including_static(["bootc", "kargs", "add", "console=ttyS0", "debug"]),
+            Opt::Kargs(KargsOpts::Add {
+                kargs: vec!["console=ttyS0".into(), "debug".into()]
+            })
+        );
+        assert!(Opt::try_parse_from(["bootc", "kargs", "remove"]).is_err());
+        assert_eq!(
+            Opt::parse_including_static(["bootc", "progress", "--fd", "3"]),
+            Opt::Progress(ProgressRenderOpts {
+                input: None,
+                fd: Some("3".parse().unwrap()),
+            })
+        );
+        assert!(Opt::try_parse_from(["bootc", "progress", "--fd", "3", "/tmp/progress"]).is_err());
+        assert!(matches!(
+            Opt::parse_including_static(["bootc", "upgrade", "--from-downloaded", "--apply"]),
+            Opt::Upgrade(UpgradeOpts {
+                from_downloaded: true,
+                apply: true,
+                ..
+            })
+        ));
     }
 
     #[test]
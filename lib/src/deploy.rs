@@ -15,16 +15,17 @@ use ostree::{gio, glib};
 use ostree_container::OstreeImageReference;
 use ostree_ext::container as ostree_container;
 use ostree_ext::container::store::{ImageImporter, ImportProgress, PrepareResult, PreparedImport};
-use ostree_ext::oci_spec::image::{Descriptor, Digest};
+use ostree_ext::oci_spec::image::{Descriptor, Digest, MediaType};
 use ostree_ext::ostree::Deployment;
 use ostree_ext::ostree::{self, Sysroot};
 use ostree_ext::sysroot::SysrootLock;
 use ostree_ext::tokio_util::spawn_blocking_cancellable_flatten;
 
+use crate::boundimage::BoundImage;
 use crate::progress_jsonl::{Event, ProgressWriter, SubTaskBytes, SubTaskStep};
-use crate::spec::ImageReference;
 use crate::spec::{BootOrder, HostSpec};
-use crate::status::labels_of_config;
+use crate::spec::{ImageReference, ImageSignature};
+use crate::status::{labels_of_config, Slot};
 use crate::store::Storage;
 use crate::utils::async_task_with_spinner;
 
@@ -43,6 +44,7 @@ pub(crate) struct RequiredHostSpec<'a> {
 pub(crate) struct ImageState {
     pub(crate) manifest_digest: Digest,
     pub(crate) version: Option<String>,
+    pub(crate) timestamp: Option<chrono::DateTime<chrono::Utc>>,
     pub(crate) ostree_commit: String,
 }
 
@@ -61,10 +63,12 @@ impl<'a> RequiredHostSpec<'a> {
 impl From<ostree_container::store::LayeredImageState> for ImageState {
     fn from(value: ostree_container::store::LayeredImageState) -> Self {
         let version = value.version().map(|v| v.to_owned());
+        let timestamp = crate::status::timestamp_of_config(&value.configuration);
         let ostree_commit = value.get_commit().to_owned();
         Self {
             manifest_digest: value.manifest_digest,
             version,
+            timestamp,
             ostree_commit,
         }
     }
@@ -81,6 +85,177 @@ impl ImageState {
     }
 }
 
+/// The features of this bootc binary, independent of the host system.
+const BASE_HOST_FEATURES: &[&str] = &["bound-images", "kargs-d", "boot-assessment"];
+
+/// The features of this bootc binary and the host system which images may
+/// require via [`crate::metadata::BOOTC_REQUIRED_FEATURES_LABEL`].  The state
+/// of the host is taken from the given deployment, i.e. the booted one, or the
+/// default one of an offline sysroot.
+#[context("Querying host features")]
+pub(crate) fn host_features(
+    sysroot: &Storage,
+    deployment: Option<&Deployment>,
+) -> Result<Vec<&'static str>> {
+    let mut r = BASE_HOST_FEATURES.to_vec();
+    // A soft reboot is only possible from the running system
+    if sysroot.booted_deployment().is_some() && crate::reboot::soft_reboot_supported() {
+        r.push("soft-reboot");
+    }
+    if let Some(deployment) = deployment {
+        let root = &crate::utils::deployment_fd(sysroot, deployment)?;
+        if crate::status::composefs_state_of_root(root)? != crate::spec::ComposefsState::Disabled {
+            r.push("composefs");
+        }
+    }
+    Ok(r)
+}
+
+/// Verify that an image can be deployed on this host: it must match the host
+/// architecture, not require a newer bootc, and not require unavailable host features.
+fn check_compatibility(
+    config: &ostree_ext::oci_spec::image::ImageConfiguration,
+    host_arch: &ostree_ext::oci_spec::image::Arch,
+    bootc_version: &str,
+    host_features: &[&str],
+) -> Result<()> {
+    let arch = config.architecture();
+    if arch != host_arch {
+        anyhow::bail!("Image has architecture {arch}, but the host architecture is {host_arch}");
+    }
+    let labels = labels_of_config(config);
+    let label = |name: &str| labels.and_then(|l| l.get(name)).map(|s| s.as_str());
+    if let Some(min_version) = label(crate::metadata::BOOTC_MIN_VERSION_LABEL) {
+        match compare_versions(bootc_version, min_version) {
+            Some(std::cmp::Ordering::Less) => anyhow::bail!(
+                "Image requires bootc version {min_version} or newer, but this is bootc {bootc_version}"
+            ),
+            Some(_) => {}
+            None => anyhow::bail!(
+                "Invalid value for label {}: {min_version}",
+                crate::metadata::BOOTC_MIN_VERSION_LABEL
+            ),
+        }
+    }
+    if let Some(required) = label(crate::metadata::BOOTC_REQUIRED_FEATURES_LABEL) {
+        let missing = required
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty() && !host_features.contains(f))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "Image requires host features which are not available: {}",
+                missing.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Compare two version strings, splitting them into runs of digits (compared
+/// numerically) and other alphanumeric characters (compared lexically); any
+/// other characters are separators.  Returns `None` if the versions have a
+/// different structure and hence cannot be meaningfully compared.
+fn compare_versions(a: &str, b: &str) -> Option<std::cmp::Ordering> {
+    use std::cmp::Ordering;
+
+    fn segments(s: &str) -> impl Iterator<Item = &str> {
+        s.split(|c: char| !c.is_ascii_alphanumeric())
+            .flat_map(|part| {
+                let mut r = Vec::new();
+                let mut start = 0;
+                for (i, c) in part.char_indices().skip(1) {
+                    let prev_digit = part[..i].ends_with(|p: char| p.is_ascii_digit());
+                    if prev_digit != c.is_ascii_digit() {
+                        r.push(&part[start..i]);
+                        start = i;
+                    }
+                }
+                r.push(&part[start..]);
+                r
+            })
+            .filter(|s| !s.is_empty())
+    }
+
+    let mut a = segments(a);
+    let mut b = segments(b);
+    loop {
+        let (sa, sb) = match (a.next(), b.next()) {
+            (None, None) => return Some(Ordering::Equal),
+            (Some(_), None) => return Some(Ordering::Greater),
+            (None, Some(_)) => return Some(Ordering::Less),
+            (Some(sa), Some(sb)) => (sa, sb),
+        };
+        let ord = match (sa.parse::<u64>(), sb.parse::<u64>()) {
+            (Ok(na), Ok(nb)) => na.cmp(&nb),
+            (Err(_), Err(_)) => sa.cmp(sb),
+            _ => return None,
+        };
+        if ord != Ordering::Equal {
+            return Some(ord);
+        }
+    }
+}
+
+/// Strip the tag and digest from an image name, e.g. `quay.io/example/os:42@sha256:...`
+/// becomes `quay.io/example/os`.
+fn image_repository(image: &str) -> &str {
+    let image = image.split_once('@').map_or(image, |(name, _)| name);
+    match image.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => name,
+        _ => image,
+    }
+}
+
+/// Return true if both image references point to the same repository, irrespective
+/// of the tag or digest.
+fn same_repository(a: &ImageReference, b: &ImageReference) -> bool {
+    a.transport == b.transport && image_repository(&a.image) == image_repository(&b.image)
+}
+
+/// Return an error if the fetched image is older than the booted image, by
+/// creation timestamp or by version.  Images from a different repository than
+/// the booted one are not compared, as their timestamps and versions are unrelated.
+pub(crate) fn verify_not_downgrade(
+    booted: Option<&crate::spec::ImageStatus>,
+    target: &ImageReference,
+    fetched: &ImageState,
+) -> Result<()> {
+    let Some(booted) = booted else {
+        return Ok(());
+    };
+    if !same_repository(&booted.image, target) {
+        tracing::debug!("Not checking for downgrade to {target} from another repository");
+        return Ok(());
+    }
+    let describe = |version: Option<&str>, timestamp: Option<&chrono::DateTime<chrono::Utc>>| {
+        let timestamp = timestamp.map(|t| t.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+        match (version, timestamp) {
+            (Some(v), Some(t)) => format!("{v} ({t})"),
+            (Some(v), None) => v.to_owned(),
+            (None, Some(t)) => t,
+            (None, None) => "unknown".to_owned(),
+        }
+    };
+    let older_timestamp = matches!(
+        (fetched.timestamp, booted.timestamp),
+        (Some(f), Some(b)) if f < b
+    );
+    let older_version = match (fetched.version.as_deref(), booted.version.as_deref()) {
+        (Some(f), Some(b)) => compare_versions(f, b) == Some(std::cmp::Ordering::Less),
+        _ => false,
+    };
+    if older_timestamp || older_version {
+        anyhow::bail!(
+            "Refusing to downgrade from {} to {}; use --allow-downgrade to override",
+            describe(booted.version.as_deref(), booted.timestamp.as_ref()),
+            describe(fetched.version.as_deref(), fetched.timestamp.as_ref()),
+        );
+    }
+    Ok(())
+}
+
 /// Wrapper for pulling a container image, wiring up status output.
 pub(crate) async fn new_importer(
     repo: &ostree::Repo,
@@ -224,6 +399,10 @@ async fn handle_layer_progress_print(
                             steps: layers_bar.position(),
                             steps_total: n_layers_to_fetch as u64,
                             subtasks: subtasks.clone(),
+                            // Filled in by the writer
+                            timestamp_ms: 0,
+                            bytes_per_second: 0,
+                            eta_seconds: None,
                         }).await;
                     }
                 } else {
@@ -254,6 +433,9 @@ async fn handle_layer_progress_print(
                         steps: layers_bar.position(),
                         steps_total: n_layers_to_fetch as u64,
                         subtasks: subtasks.clone().into_iter().chain([subtask.clone()]).collect(),
+                        timestamp_ms: 0,
+                        bytes_per_second: 0,
+                        eta_seconds: None,
                     }).await;
                 }
             }
@@ -264,6 +446,7 @@ async fn handle_layer_progress_print(
     if let Err(e) = bar.clear() {
         tracing::warn!("clearing bar: {e}");
     }
+    prog.add_bytes_transferred(total_read).await;
     let end = std::time::Instant::now();
     let elapsed = end.duration_since(start);
     let persec = total_read as f64 / elapsed.as_secs_f64();
@@ -304,14 +487,14 @@ async fn handle_layer_progress_print(
 /// Gather all bound images in all deployments, then prune the image store,
 /// using the gathered images as the roots (that will not be GC'd).
 pub(crate) async fn prune_container_store(sysroot: &Storage) -> Result<()> {
-    let deployments = sysroot.deployments();
-    let mut all_bound_images = Vec::new();
-    for deployment in deployments {
-        let bound = crate::boundimage::query_bound_images_for_deployment(sysroot, &deployment)?;
-        all_bound_images.extend(bound.into_iter());
-    }
-    // Convert to a hashset of just the image names
-    let image_names = HashSet::from_iter(all_bound_images.iter().map(|img| img.image.as_str()));
+    // Note this includes the staged, rollback and pinned deployments, so their
+    // bound images are retained.
+    let all_bound_images = sysroot
+        .deployments()
+        .iter()
+        .map(|deployment| crate::boundimage::query_bound_images_for_deployment(sysroot, deployment))
+        .collect::<Result<Vec<_>>>()?;
+    let image_names = bound_image_roots(&all_bound_images);
     let pruned = sysroot
         .get_ensure_imgstore()?
         .prune_except_roots(&image_names)
@@ -320,6 +503,16 @@ pub(crate) async fn prune_container_store(sysroot: &Storage) -> Result<()> {
     Ok(())
 }
 
+/// Convert the bound images of each deployment into the set of image names
+/// which are retained when pruning the image store.
+fn bound_image_roots(deployments: &[Vec<BoundImage>]) -> HashSet<String> {
+    deployments
+        .iter()
+        .flatten()
+        .flat_map(|img| std::iter::once(img.image.clone()).chain(img.stored_name()))
+        .collect()
+}
+
 pub(crate) struct PreparedImportMeta {
     pub imp: ImageImporter,
     pub prep: Box<PreparedImport>,
@@ -335,6 +528,78 @@ pub(crate) enum PreparedPullResult {
     AlreadyPresent(Box<ImageState>),
 }
 
+/// Estimated ratio of uncompressed to compressed layer size, for layers whose
+/// uncompressed size is not known.
+const LAYER_EXPANSION_RATIO: u64 = 3;
+/// Layer annotation with the uncompressed size of an eStargz layer.
+const ESTARGZ_UNCOMPRESSED_SIZE: &str = "io.containers.estargz.uncompressed-size";
+/// Estimated additional space needed to check out a new deployment; most
+/// files are hardlinked from the repository, but e.g. `/etc` is copied.
+const DEPLOYMENT_CHECKOUT_OVERHEAD: u64 = 128 * 1024 * 1024;
+
+/// The uncompressed size of a layer, if known: either the layer isn't
+/// compressed, or it is annotated with its uncompressed size.
+fn uncompressed_layer_size(layer: &Descriptor) -> Option<u64> {
+    if matches!(layer.media_type(), MediaType::ImageLayer) {
+        return Some(layer.size());
+    }
+    layer
+        .annotations()
+        .as_ref()?
+        .get(ESTARGZ_UNCOMPRESSED_SIZE)?
+        .parse()
+        .ok()
+}
+
+/// Estimate the disk space needed to import and deploy the given layers, using
+/// the uncompressed size of each layer where known and otherwise approximating
+/// it from the compressed size.
+pub(crate) fn estimate_required_space<'a>(layers: impl IntoIterator<Item = &'a Descriptor>) -> u64 {
+    layers
+        .into_iter()
+        .map(|layer| {
+            uncompressed_layer_size(layer)
+                .unwrap_or_else(|| layer.size().saturating_mul(LAYER_EXPANSION_RATIO))
+        })
+        .fold(DEPLOYMENT_CHECKOUT_OVERHEAD, u64::saturating_add)
+}
+
+/// Query the free space on the filesystem holding the repository.
+pub(crate) fn available_space(repo: &ostree::Repo) -> Result<u64> {
+    let st = rustix::fs::fstatvfs(repo.dfd_borrow())?;
+    Ok(st.f_bavail.saturating_mul(st.f_frsize))
+}
+
+/// Fail early if the filesystem holding the repository does not have enough
+/// space to fetch and deploy the given layers.
+#[context("Checking available disk space")]
+fn check_disk_space<'a>(
+    repo: &ostree::Repo,
+    layers: impl IntoIterator<Item = &'a Descriptor>,
+) -> Result<()> {
+    let required = estimate_required_space(layers);
+    let available = available_space(repo)?;
+    tracing::debug!("Estimated space required: {required}, available: {available}");
+    if required > available {
+        anyhow::bail!(
+            "Insufficient disk space: the update needs an estimated {} but only {} is available; \
+             free up space (e.g. in /var, or by unpinning deployments via `bootc unpin`) and retry",
+            glib::format_size(required),
+            glib::format_size(available)
+        );
+    }
+    Ok(())
+}
+
+/// Verify the sigstore signature of an image if its reference requires it; as the
+/// signatures refer to the manifest digest, this can be done before fetching any layers.
+async fn verify_sigstore(imgref: &ImageReference, digest: &Digest) -> Result<()> {
+    if let Some(ImageSignature::Sigstore { public_key }) = imgref.signature.as_ref() {
+        crate::sigstore::verify(imgref, public_key, digest).await?;
+    }
+    Ok(())
+}
+
 pub(crate) async fn prepare_for_pull(
     repo: &ostree::Repo,
     imgref: &ImageReference,
@@ -349,23 +614,28 @@ pub(crate) async fn prepare_for_pull(
     }
     let prep = match imp.prepare().await? {
         PrepareResult::AlreadyPresent(c) => {
+            // The image may have been fetched without requiring a signature
+            verify_sigstore(imgref, &c.manifest_digest).await?;
             println!("No changes in {imgref:#} => {}", c.manifest_digest);
             return Ok(PreparedPullResult::AlreadyPresent(Box::new((*c).into())));
         }
         PrepareResult::Ready(p) => p,
     };
+    verify_sigstore(imgref, &prep.manifest_digest).await?;
     check_bootc_label(&prep.config);
     if let Some(warning) = prep.deprecated_warning() {
         ostree_ext::cli::print_deprecated_warning(warning).await;
     }
     ostree_ext::cli::print_layer_status(&prep);
     let layers_to_fetch = prep.layers_to_fetch().collect::<Result<Vec<_>>>()?;
+    let bytes_to_fetch = layers_to_fetch.iter().map(|(l, _)| l.layer.size()).sum();
+    check_disk_space(repo, layers_to_fetch.iter().map(|(l, _)| &l.layer))?;
 
     let prepared_image = PreparedImportMeta {
         imp,
         n_layers_to_fetch: layers_to_fetch.len(),
         layers_total: prep.all_layers().count(),
-        bytes_to_fetch: layers_to_fetch.iter().map(|(l, _)| l.layer.size()).sum(),
+        bytes_to_fetch,
         bytes_total: prep.all_layers().map(|l| l.layer.size()).sum(),
         digest: prep.manifest_digest.clone(),
         prep,
@@ -497,7 +767,8 @@ pub(crate) async fn cleanup(sysroot: &Storage) -> Result<()> {
             }
 
             // Then, for each deployment which is derived (e.g. has configmaps) we synthesize
-            // a base ref to ensure that it's not GC'd.
+            // a base ref to ensure that it's not GC'd.  Pinned deployments are included here,
+            // and the image prune below also retains all images referenced by a deployment.
             for (i, deployment) in sysroot.deployments().into_iter().enumerate() {
                 let commit = deployment.csum();
                 if let Some(base) = get_base_commit(repo, &commit)? {
@@ -579,45 +850,95 @@ async fn deploy(
             let merge_deployment = merge_deployment.map(|m| &deployments[m]);
             let origin = glib::KeyFile::new();
             origin.load_from_data(&origin_data, glib::KeyFileFlags::NONE)?;
-            let d = sysroot.stage_tree_with_options(
-                stateroot.as_deref(),
-                &ostree_commit,
-                Some(&origin),
-                merge_deployment,
-                &opts,
-                Some(cancellable),
-            )?;
-            Ok(d.index())
+            if sysroot.booted_deployment().is_some() {
+                let d = sysroot.stage_tree_with_options(
+                    stateroot.as_deref(),
+                    &ostree_commit,
+                    Some(&origin),
+                    merge_deployment,
+                    &opts,
+                    Some(cancellable),
+                )?;
+                Ok(d.index())
+            } else {
+                // An offline sysroot has no ostree-finalize-staged.service to apply a
+                // staged deployment, so write the new one directly as the default.
+                let d = sysroot.deploy_tree_with_options(
+                    stateroot.as_deref(),
+                    &ostree_commit,
+                    Some(&origin),
+                    merge_deployment,
+                    Some(&opts),
+                    Some(cancellable),
+                )?;
+                sysroot.simple_write_deployment(
+                    stateroot.as_deref(),
+                    &d,
+                    merge_deployment,
+                    ostree::SysrootSimpleWriteDeploymentFlags::NONE,
+                    Some(cancellable),
+                )?;
+                sysroot.load(Some(cancellable))?;
+                Ok(0)
+            }
         }),
     )
     .await?;
-    // SAFETY: We must have a staged deployment
-    let staged = sysroot.staged_deployment().unwrap();
-    assert_eq!(staged.index(), r);
-    Ok(staged)
+    let deployment = if sysroot.booted_deployment().is_some() {
+        sysroot.staged_deployment()
+    } else {
+        sysroot.deployments().into_iter().next()
+    };
+    // SAFETY: We must have written a deployment
+    let deployment = deployment.unwrap();
+    assert_eq!(deployment.index(), r);
+    Ok(deployment)
 }
 
 #[context("Generating origin")]
 fn origin_from_imageref(imgref: &ImageReference) -> Result<glib::KeyFile> {
     let origin = glib::KeyFile::new();
+    let imgref_signature = imgref.signature.as_ref();
     let imgref = OstreeImageReference::from(imgref.clone());
     origin.set_string(
         "origin",
         ostree_container::deploy::ORIGIN_CONTAINER,
         imgref.to_string().as_str(),
     );
+    if let Some(ImageSignature::Sigstore { public_key }) = imgref_signature {
+        origin.set_string("bootc", crate::sigstore::ORIGIN_PUBLIC_KEY, public_key);
+    }
     Ok(origin)
 }
 
-/// Stage (queue deployment of) a fetched container image.
+/// Stage (queue deployment of) a fetched container image.  If `lock_finalization`
+/// is set, the staged deployment will not be applied at shutdown until it is
+/// unlocked via [`set_staged_locked`].  On an offline (not booted) sysroot,
+/// the new deployment is instead written as the default boot entry.
 #[context("Staging")]
 pub(crate) async fn stage(
     sysroot: &Storage,
     stateroot: &str,
     image: &ImageState,
     spec: &RequiredHostSpec<'_>,
+    lock_finalization: bool,
     prog: ProgressWriter,
-) -> Result<()> {
+) -> Result<Deployment> {
+    let imgstate =
+        ostree_container::store::query_image_commit(&sysroot.repo(), &image.ostree_commit)?;
+    let merge_deployment = sysroot.merge_deployment(Some(stateroot));
+    let host_deployment = sysroot
+        .booted_deployment()
+        .or_else(|| merge_deployment.clone());
+    let features = host_features(sysroot, host_deployment.as_ref())?;
+    check_compatibility(
+        &imgstate.configuration,
+        &Default::default(),
+        env!("CARGO_PKG_VERSION"),
+        &features,
+    )
+    .context("Checking image compatibility")?;
+
     let mut subtask = SubTaskStep {
         subtask: "merging".into(),
         description: "Merging Image".into(),
@@ -639,7 +960,6 @@ pub(crate) async fn stage(
             .collect(),
     })
     .await;
-    let merge_deployment = sysroot.merge_deployment(Some(stateroot));
 
     subtask.completed = true;
     subtasks.push(subtask.clone());
@@ -670,6 +990,10 @@ pub(crate) async fn stage(
         &origin,
     )
     .await?;
+    if lock_finalization {
+        sysroot.change_finalization(&deployment)?;
+    }
+    crate::assessment::init_pending(sysroot, &deployment)?;
 
     subtask.completed = true;
     subtasks.push(subtask.clone());
@@ -677,10 +1001,11 @@ pub(crate) async fn stage(
     subtask.id = "bound_images".into();
     subtask.description = "Pulling Bound Images".into();
     subtask.completed = false;
-    prog.send(Event::ProgressSteps {
+    // Each bound image is reported as an additional subtask of this step
+    let bound_images_step = Event::ProgressSteps {
         task: "staging".into(),
         description: "Deploying Image".into(),
-        id: image.manifest_digest.clone().as_ref().into(),
+        id: image.manifest_digest.as_ref().into(),
         steps_cached: 0,
         steps: 1,
         steps_total: 3,
@@ -689,9 +1014,23 @@ pub(crate) async fn stage(
             .into_iter()
             .chain([subtask.clone()])
             .collect(),
-    })
-    .await;
-    crate::boundimage::pull_bound_images(sysroot, &deployment).await?;
+    };
+    prog.send(bound_images_step.clone()).await;
+    if let Err(e) =
+        crate::boundimage::pull_bound_images(sysroot, &deployment, &prog, Some(&bound_images_step))
+            .await
+    {
+        // The new deployment must not be booted with bound images which failed
+        // verification, so don't leave it queued.
+        if e.downcast_ref::<crate::boundimage::VerificationError>()
+            .is_some()
+        {
+            if let Err(undeploy_err) = undeploy(sysroot, &deployment) {
+                tracing::warn!("{undeploy_err:#}");
+            }
+        }
+        return Err(e);
+    }
 
     subtask.completed = true;
     subtasks.push(subtask.clone());
@@ -714,7 +1053,11 @@ pub(crate) async fn stage(
     })
     .await;
     crate::deploy::cleanup(sysroot).await?;
-    println!("Queued for next boot: {:#}", spec.image);
+    if lock_finalization {
+        println!("Downloaded (not queued for next boot): {:#}", spec.image);
+    } else {
+        println!("Queued for next boot: {:#}", spec.image);
+    }
     if let Some(version) = image.version.as_deref() {
         println!("  Version: {version}");
     }
@@ -736,13 +1079,200 @@ pub(crate) async fn stage(
             .collect(),
     })
     .await;
+    prog.send_summary("staging", &image.manifest_digest, image.version.as_deref())
+        .await;
+
+    Ok(deployment)
+}
+
+/// A reference to a deployment, as accepted on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DeploymentRef {
+    /// A deployment by its role
+    Slot(Slot),
+    /// A deployment by its index in the deployment list (as ordered by `ostree admin status`)
+    Index(usize),
+}
+
+impl std::str::FromStr for DeploymentRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let r = match s {
+            "staged" => Self::Slot(Slot::Staged),
+            "booted" => Self::Slot(Slot::Booted),
+            "rollback" => Self::Slot(Slot::Rollback),
+            o => o.parse().map(Self::Index).map_err(|_| {
+                anyhow!("Invalid deployment {o}; expected staged, booted, rollback or an index")
+            })?,
+        };
+        Ok(r)
+    }
+}
+
+/// Find the deployment corresponding to a reference.
+fn resolve_deployment(sysroot: &Storage, target: DeploymentRef) -> Result<Deployment> {
+    match target {
+        DeploymentRef::Index(i) => sysroot
+            .deployments()
+            .into_iter()
+            .nth(i)
+            .ok_or_else(|| anyhow!("No deployment at index {i}")),
+        DeploymentRef::Slot(slot) => {
+            let (booted, deployments, _host) = crate::status::get_status_require_booted(sysroot)?;
+            match slot {
+                Slot::Staged => deployments.staged,
+                Slot::Booted => Some(booted),
+                Slot::Rollback => deployments.rollback,
+            }
+            .ok_or_else(|| anyhow!("No {slot} deployment"))
+        }
+    }
+}
+
+/// Pin or unpin a deployment; pinned deployments are not garbage collected.
+#[context("Setting pin state")]
+pub(crate) async fn set_pinned(
+    sysroot: &Storage,
+    target: DeploymentRef,
+    pinned: bool,
+) -> Result<()> {
+    let deployment = resolve_deployment(sysroot, target)?;
+    let verb = if pinned { "pinned" } else { "unpinned" };
+    if deployment.is_staged() {
+        anyhow::bail!("Cannot change the pin state of a staged deployment");
+    }
+    if deployment.is_pinned() == pinned {
+        println!("Deployment is already {verb}");
+        return Ok(());
+    }
+    sysroot.deployment_set_pinned(&deployment, pinned)?;
+    sysroot.update_mtime()?;
+    println!(
+        "Deployment {}.{} is now {verb}",
+        deployment.csum(),
+        deployment.deployserial()
+    );
+    Ok(())
+}
+
+/// Set whether the staged deployment is applied at shutdown; returns `false` if
+/// there is no staged deployment, or it was already in the requested state.
+#[context("Changing staged deployment finalization")]
+pub(crate) fn set_staged_locked(sysroot: &Storage, locked: bool) -> Result<bool> {
+    let Some(staged) = sysroot.staged_deployment() else {
+        return Ok(false);
+    };
+    if staged.is_finalization_locked() == locked {
+        return Ok(false);
+    }
+    // This toggles the lock state
+    sysroot.change_finalization(&staged)?;
+    Ok(true)
+}
 
+/// Journal message ID for a manually requested rollback.
+const ROLLBACK_JOURNAL_ID: &str = "26f3b1eb24464d12aa5e7b544a6b5468";
+/// Journal message ID for a rollback performed because the booted
+/// deployment failed boot assessment.
+const AUTO_ROLLBACK_JOURNAL_ID: &str = "9a4b7bd0a4e54b1f9ac7d1d4f1e6c2c3";
+
+/// Remove a newly created deployment.
+#[context("Removing deployment")]
+fn undeploy(sysroot: &Storage, deployment: &Deployment) -> Result<()> {
+    let deployments = sysroot
+        .deployments()
+        .into_iter()
+        .filter(|d| !d.equal(deployment))
+        .collect::<Vec<_>>();
+    sysroot.write_deployments(&deployments, gio::Cancellable::NONE)?;
     Ok(())
 }
 
+/// Compute the deployment list with either the booted deployment (if `reverting`)
+/// or the rollback deployment as the default for the next boot.  Note that any
+/// staged deployment is not included, and hence discarded.
+fn rollback_order(
+    booted_deployment: Deployment,
+    deployments: crate::status::Deployments,
+    reverting: bool,
+) -> Vec<Deployment> {
+    // SAFETY: Callers have verified there is a rollback deployment
+    let rollback_deployment = deployments.rollback.expect("rollback deployment");
+    let new_deployments = if reverting {
+        [booted_deployment, rollback_deployment]
+    } else {
+        [rollback_deployment, booted_deployment]
+    };
+    new_deployments
+        .into_iter()
+        .chain(deployments.other)
+        .collect()
+}
+
+/// Write the deployment list with either the booted deployment (if `reverting`)
+/// or the rollback deployment as the default for the next boot.
+fn write_rollback_order(
+    sysroot: &Storage,
+    booted_deployment: Deployment,
+    deployments: crate::status::Deployments,
+    reverting: bool,
+) -> Result<()> {
+    let new_deployments = rollback_order(booted_deployment, deployments, reverting);
+    tracing::debug!("Writing new deployments: {new_deployments:?}");
+    sysroot.write_deployments(&new_deployments, gio::Cancellable::NONE)?;
+    Ok(())
+}
+
+/// Queue the rollback deployment for the next boot because the booted deployment
+/// failed boot assessment.  Returns `false` if there is no rollback deployment
+/// to fall back to, or it has failed boot assessment too.  A staged deployment
+/// is discarded, as it would otherwise be booted instead of the rollback.
+#[context("Rolling back unhealthy deployment")]
+pub(crate) async fn rollback_unhealthy(sysroot: &Storage, reason: &str) -> Result<bool> {
+    let repo = &sysroot.repo();
+    let (booted_deployment, deployments, host) = crate::status::get_status_require_booted(sysroot)?;
+    let (Some(rollback_status), Some(rollback_deployment)) =
+        (host.status.rollback.as_ref(), deployments.rollback.as_ref())
+    else {
+        return Ok(false);
+    };
+    let rollback_state = crate::assessment::read_state(sysroot, rollback_deployment)?;
+    if !crate::assessment::is_rollback_candidate(rollback_state.as_ref()) {
+        eprintln!("The rollback deployment also failed boot assessment");
+        return Ok(false);
+    }
+    if deployments.staged.is_some() {
+        println!("Discarding staged deployment");
+    }
+    let rollback_digest = rollback_status
+        .query_image(repo)?
+        .map(|img| img.manifest_digest.to_string())
+        .unwrap_or_default();
+    let msg = format!("Booted deployment failed boot assessment ({reason}); rolling back");
+    crate::journal::journal_send(
+        libsystemd::logging::Priority::Error,
+        &msg,
+        [
+            ("MESSAGE_ID", AUTO_ROLLBACK_JOURNAL_ID),
+            ("BOOTC_MANIFEST_DIGEST", rollback_digest.as_str()),
+        ]
+        .into_iter(),
+    );
+    if let Some(image) = rollback_status.image.as_ref() {
+        crate::history::record_rollback(sysroot, image, rollback_deployment)?;
+    }
+    // If a rollback is already queued (and nothing is staged), the deployment
+    // order is already what we want
+    if !host.status.rollback_queued || deployments.staged.is_some() {
+        write_rollback_order(sysroot, booted_deployment, deployments, false)?;
+    }
+    sysroot.update_mtime()?;
+    Ok(true)
+}
+
 /// Implementation of rollback functionality
 pub(crate) async fn rollback(sysroot: &Storage) -> Result<()> {
-    const ROLLBACK_JOURNAL_ID: &str = "26f3b1eb24464d12aa5e7b544a6b5468";
     let repo = &sysroot.repo();
     let (booted_deployment, deployments, host) = crate::status::get_status_require_booted(sysroot)?;
 
@@ -779,19 +1309,19 @@ pub(crate) async fn rollback(sysroot: &Storage) -> Result<()> {
         ]
         .into_iter(),
     )?;
-    // SAFETY: If there's a rollback status, then there's a deployment
-    let rollback_deployment = deployments.rollback.expect("rollback deployment");
-    let new_deployments = if reverting {
-        [booted_deployment, rollback_deployment]
+    // Record the image which will be booted next
+    let (next_image, next_deployment) = if reverting {
+        (host.status.booted.as_ref(), Some(&booted_deployment))
     } else {
-        [rollback_deployment, booted_deployment]
+        (Some(&rollback_status), deployments.rollback.as_ref())
     };
-    let new_deployments = new_deployments
-        .into_iter()
-        .chain(deployments.other)
-        .collect::<Vec<_>>();
-    tracing::debug!("Writing new deployments: {new_deployments:?}");
-    sysroot.write_deployments(&new_deployments, gio::Cancellable::NONE)?;
+    if let (Some(image), Some(deployment)) =
+        (next_image.and_then(|e| e.image.as_ref()), next_deployment)
+    {
+        crate::history::record_rollback(sysroot, image, deployment)?;
+    }
+    // If there's a rollback status, then there's a deployment
+    write_rollback_order(sysroot, booted_deployment, deployments, reverting)?;
     if reverting {
         println!("Next boot: current deployment");
     } else {
@@ -951,6 +1481,310 @@ pub(crate) fn fixup_etc_fstab(root: &Dir) -> Result<()> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_prune_retains_rollback_images() {
+        let bound = |names: &[&str]| {
+            names
+                .iter()
+                .map(|&image| BoundImage {
+                    image: image.into(),
+                    auth_file: None,
+                    digest: None,
+                    signature: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let digest = "sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566";
+        let booted = bound(&["quay.io/example/app:v2"]);
+        let mut rollback = bound(&["quay.io/example/app:v1", "quay.io/example/db:v1"]);
+        // Pinned with both a tag and a digest; podman records these without the tag
+        for image in [
+            format!("quay.io/example/pinned:v1@{digest}"),
+            format!("localhost:5000/pinned:v1@{digest}"),
+        ] {
+            rollback.push(BoundImage {
+                image,
+                auth_file: None,
+                digest: Some(digest.parse().unwrap()),
+                signature: None,
+            });
+        }
+        let roots = bound_image_roots(&[booted, rollback]);
+        let entry = |id: &str, names: Option<&[&str]>| crate::podman::ImageListEntry {
+            id: id.into(),
+            names: names.map(|n| n.iter().map(|&s| s.to_owned()).collect()),
+            repo_digests: None,
+        };
+        let images = vec![
+            entry(
+                "pinned",
+                Some(&[format!("quay.io/example/pinned@{digest}").as_str()]),
+            ),
+            crate::podman::ImageListEntry {
+                repo_digests: Some(vec![format!("localhost:5000/pinned@{digest}")]),
+                ..entry("pinned-local", Some(&["localhost:5000/pinned:latest"]))
+            },
+            entry("booted", Some(&["quay.io/example/app:v2"])),
+            // Still referenced by the rollback deployment, under one of its names
+            entry(
+                "rollback",
+                Some(&["quay.io/example/app:v1", "quay.io/example/app:old"]),
+            ),
+            entry("rollback-db", Some(&["quay.io/example/db:v1"])),
+            entry("unnamed", None),
+            entry("old", Some(&["quay.io/example/app:v0"])),
+        ];
+        assert_eq!(
+            crate::imgstorage::unreferenced_images(images, &roots),
+            ["old"]
+        );
+    }
+
+    #[test]
+    fn test_rollback_order() {
+        let deployment = |index: i32, csum: &str| {
+            ostree::Deployment::new(index, "default", csum, 0, None, index)
+        };
+        let ids = |deployments: &[Deployment]| {
+            deployments
+                .iter()
+                .map(|d| d.csum().to_string())
+                .collect::<Vec<_>>()
+        };
+        let deployments = || crate::status::Deployments {
+            staged: Some(deployment(0, "staged")),
+            rollback: Some(deployment(2, "rollback")),
+            other: [deployment(3, "other")].into(),
+        };
+        // The staged deployment is discarded
+        let order = rollback_order(deployment(1, "booted"), deployments(), false);
+        assert_eq!(ids(&order), ["rollback", "booted", "other"]);
+        let order = rollback_order(deployment(1, "booted"), deployments(), true);
+        assert_eq!(ids(&order), ["booted", "rollback", "other"]);
+    }
+
+    #[test]
+    fn test_check_compatibility() {
+        use ostree_ext::oci_spec::image::{Arch, ConfigBuilder, ImageConfigurationBuilder};
+
+        let config = |arch: Arch, labels: &[(&str, &str)]| {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            ImageConfigurationBuilder::default()
+                .architecture(arch)
+                .config(ConfigBuilder::default().labels(labels).build().unwrap())
+                .build()
+                .unwrap()
+        };
+        let features = ["bound-images", "composefs"];
+        let check = |c| check_compatibility(&c, &Arch::Amd64, "1.1.6", &features);
+
+        check(config(Arch::Amd64, &[])).unwrap();
+        let e = check(config(Arch::ARM64, &[])).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Image has architecture arm64, but the host architecture is amd64"
+        );
+
+        let min_version = crate::metadata::BOOTC_MIN_VERSION_LABEL;
+        check(config(Arch::Amd64, &[(min_version, "1.1.6")])).unwrap();
+        check(config(Arch::Amd64, &[(min_version, "1.0")])).unwrap();
+        let e = check(config(Arch::Amd64, &[(min_version, "1.2.0")])).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Image requires bootc version 1.2.0 or newer, but this is bootc 1.1.6"
+        );
+        assert!(check(config(Arch::Amd64, &[(min_version, "latest")])).is_err());
+
+        let required = crate::metadata::BOOTC_REQUIRED_FEATURES_LABEL;
+        check(config(
+            Arch::Amd64,
+            &[(required, "composefs, bound-images")],
+        ))
+        .unwrap();
+        let e = check(config(
+            Arch::Amd64,
+            &[(required, "composefs,soft-reboot,unknown")],
+        ))
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Image requires host features which are not available: soft-reboot, unknown"
+        );
+    }
+
+    #[test]
+    fn test_estimate_required_space() {
+        use std::str::FromStr;
+
+        let digest = Digest::from_str(
+            "sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566",
+        )
+        .unwrap();
+        let layer =
+            |media_type: MediaType, size: u64| Descriptor::new(media_type, size, digest.clone());
+        assert_eq!(estimate_required_space([]), DEPLOYMENT_CHECKOUT_OVERHEAD);
+        // Compressed layers of unknown uncompressed size
+        let gzip = layer(MediaType::ImageLayerGzip, 1024);
+        assert_eq!(
+            estimate_required_space([&gzip]),
+            3 * 1024 + DEPLOYMENT_CHECKOUT_OVERHEAD
+        );
+        // Uncompressed layers
+        let tar = layer(MediaType::ImageLayer, 1000);
+        assert_eq!(
+            estimate_required_space([&gzip, &tar]),
+            3 * 1024 + 1000 + DEPLOYMENT_CHECKOUT_OVERHEAD
+        );
+        // Layers annotated with their uncompressed size
+        let mut estargz = layer(MediaType::ImageLayerGzip, 1024);
+        estargz.set_annotations(Some(
+            [(ESTARGZ_UNCOMPRESSED_SIZE.to_owned(), "4096".to_owned())].into(),
+        ));
+        assert_eq!(
+            estimate_required_space([&estargz]),
+            4096 + DEPLOYMENT_CHECKOUT_OVERHEAD
+        );
+        let huge = layer(MediaType::ImageLayerZstd, u64::MAX);
+        assert_eq!(estimate_required_space([&huge]), u64::MAX);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        use std::cmp::Ordering::*;
+        for (a, b, expected) in [
+            ("1.2.3", "1.2.3", Some(Equal)),
+            ("1.2.3", "1.2.10", Some(Less)),
+            ("42.20240807.0", "41.20240901.0", Some(Greater)),
+            ("stream9.20240807.0", "stream9.20240807.1", Some(Less)),
+            ("stream10.20240807.0", "stream9.20240807.0", Some(Greater)),
+            ("1.2", "1.2.1", Some(Less)),
+            ("1.2a", "1.2b", Some(Less)),
+            ("nightly", "1.0", None),
+        ] {
+            assert_eq!(compare_versions(a, b), expected, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn test_verify_not_downgrade() {
+        use std::str::FromStr;
+
+        let booted = crate::spec::ImageStatus {
+            image: ImageReference {
+                image: "quay.io/example/os:latest".into(),
+                transport: "registry".into(),
+                signature: None,
+            },
+            version: Some("42.1".into()),
+            timestamp: Some("2024-08-07T12:00:00Z".parse().unwrap()),
+            image_digest: "sha256:aaaa".into(),
+            architecture: "amd64".into(),
+        };
+        let fetched = |version: Option<&str>, timestamp: Option<&str>| ImageState {
+            manifest_digest: Digest::from_str(
+                "sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566",
+            )
+            .unwrap(),
+            version: version.map(ToOwned::to_owned),
+            timestamp: timestamp.map(|t| t.parse().unwrap()),
+            ostree_commit: "abc".into(),
+        };
+        let target = |image: &str| ImageReference {
+            image: image.into(),
+            transport: "registry".into(),
+            signature: None,
+        };
+        let same = &target("quay.io/example/os:42");
+        // No booted image
+        verify_not_downgrade(None, same, &fetched(Some("1"), None)).unwrap();
+        // Newer
+        verify_not_downgrade(
+            Some(&booted),
+            same,
+            &fetched(Some("42.2"), Some("2024-08-08T12:00:00Z")),
+        )
+        .unwrap();
+        // Same
+        verify_not_downgrade(
+            Some(&booted),
+            same,
+            &fetched(Some("42.1"), Some("2024-08-07T12:00:00Z")),
+        )
+        .unwrap();
+        // Unknown metadata
+        verify_not_downgrade(Some(&booted), same, &fetched(None, None)).unwrap();
+        // Older timestamp
+        assert!(verify_not_downgrade(
+            Some(&booted),
+            same,
+            &fetched(Some("42.2"), Some("2024-08-06T12:00:00Z"))
+        )
+        .is_err());
+        // Older version
+        let e =
+            verify_not_downgrade(Some(&booted), same, &fetched(Some("41.9"), None)).unwrap_err();
+        similar_asserts::assert_eq!(
+            e.to_string(),
+            "Refusing to downgrade from 42.1 (2024-08-07T12:00:00Z) to 41.9; use --allow-downgrade to override"
+        );
+        // The same repository, pinned by digest
+        assert!(verify_not_downgrade(
+            Some(&booted),
+            &target("quay.io/example/os@sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566"),
+            &fetched(Some("41.9"), None)
+        )
+        .is_err());
+        // Another repository, or the same one via another transport, isn't compared
+        for other in [
+            target("quay.io/example/otheros:latest"),
+            target("registry.example.com:5000/example/os:latest"),
+            ImageReference {
+                transport: "containers-storage".into(),
+                ..same.clone()
+            },
+        ] {
+            verify_not_downgrade(
+                Some(&booted),
+                &other,
+                &fetched(Some("1.0"), Some("2020-01-01T00:00:00Z")),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_image_repository() {
+        for (image, expected) in [
+            ("quay.io/example/os", "quay.io/example/os"),
+            ("quay.io/example/os:latest", "quay.io/example/os"),
+            ("localhost:5000/os:42", "localhost:5000/os"),
+            ("localhost:5000/os", "localhost:5000/os"),
+            ("quay.io/example/os:42@sha256:abcd", "quay.io/example/os"),
+            ("/var/lib/images/os:latest", "/var/lib/images/os"),
+        ] {
+            assert_eq!(image_repository(image), expected, "{image}");
+        }
+    }
+
+    #[test]
+    fn test_parse_deployment_ref() {
+        for (s, expected) in [
+            ("staged", DeploymentRef::Slot(Slot::Staged)),
+            ("booted", DeploymentRef::Slot(Slot::Booted)),
+            ("rollback", DeploymentRef::Slot(Slot::Rollback)),
+            ("0", DeploymentRef::Index(0)),
+            ("3", DeploymentRef::Index(3)),
+        ] {
+            assert_eq!(s.parse::<DeploymentRef>().unwrap(), expected);
+        }
+        for s in ["", "other", "-1", "1.5"] {
+            assert!(s.parse::<DeploymentRef>().is_err());
+        }
+    }
+
     #[test]
     fn test_switch_inplace() -> Result<()> {
         use cap_std::fs::DirBuilderExt;
@@ -11,6 +11,7 @@ use std::future::Future;
 use std::num::NonZeroUsize;
 use std::pin::Pin;
 
+use anyhow::Context as _;
 use bootc_utils::collect_until;
 use camino::Utf8PathBuf;
 use cap_std::fs::{Dir, MetadataExt as _};
@@ -22,10 +23,50 @@ use ostree_ext::ostree_prepareroot::Tristate;
 use ostree_ext::{composefs, ostree};
 use serde::{Deserialize, Serialize};
 
-use crate::store::Storage;
+use crate::store::{Storage, BOOTC_ROOT};
 
 use std::os::fd::AsFd;
 
+/// The result of the last fsck run, relative to [`BOOTC_ROOT`].  The checks can be
+/// slow, so rather than running them whenever the status is computed, the `Degraded`
+/// condition reflects the result recorded here.
+const STATE_FILE: &str = "fsck.json";
+
+/// The persisted result of the last fsck run.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FsckState {
+    /// When the checks were run
+    pub(crate) timestamp: chrono::DateTime<chrono::Utc>,
+    /// The checks which failed, with their errors
+    pub(crate) errors: Vec<String>,
+}
+
+/// Read the result of the last fsck run, if any.
+#[context("Reading fsck state")]
+pub(crate) fn read_state(storage: &Storage) -> anyhow::Result<Option<FsckState>> {
+    let sysroot_dir = crate::utils::sysroot_dir(storage)?;
+    let Some(d) = sysroot_dir.open_dir_optional(BOOTC_ROOT)? else {
+        return Ok(None);
+    };
+    let Some(f) = d.open_optional(STATE_FILE)? else {
+        return Ok(None);
+    };
+    let state = serde_json::from_reader(std::io::BufReader::new(f))
+        .with_context(|| format!("Parsing {STATE_FILE}"))?;
+    Ok(Some(state))
+}
+
+#[context("Writing fsck state")]
+fn write_state(storage: &Storage, state: &FsckState) -> anyhow::Result<()> {
+    let sysroot_dir = crate::utils::sysroot_dir(storage)?;
+    sysroot_dir.create_dir_all(BOOTC_ROOT)?;
+    let d = sysroot_dir.open_dir(BOOTC_ROOT)?;
+    let buf = serde_json::to_vec(state)?;
+    d.atomic_write(STATE_FILE, buf)?;
+    Ok(())
+}
+
 /// A lint check has failed.
 #[derive(thiserror::Error, Debug)]
 struct FsckError(String);
@@ -273,7 +314,8 @@ pub(crate) async fn fsck(storage: &Storage, mut output: impl std::io::Write) ->
     let mut checks = FSCK_CHECKS.static_slice().iter().collect::<Vec<_>>();
     checks.sort_by(|a, b| a.ordering.cmp(&b.ordering));
 
-    let mut errors = false;
+    let timestamp = chrono::Utc::now();
+    let mut errors = Vec::new();
     for check in checks.iter() {
         let name = check.name;
         let r = match check.f {
@@ -285,16 +327,19 @@ pub(crate) async fn fsck(storage: &Storage, mut output: impl std::io::Write) ->
                 println!("ok: {name}");
             }
             Ok(Err(e)) => {
-                errors = true;
                 writeln!(output, "fsck error: {name}: {e}")?;
+                errors.push(format!("{name}: {e}"));
             }
             Err(e) => {
-                errors = true;
                 writeln!(output, "Unexpected runtime error in check {name}: {e}")?;
+                errors.push(format!("{name}: unexpected runtime error: {e}"));
             }
         }
     }
-    if errors {
+    // Persist the result, so that it can be reflected in the host status
+    let failed = !errors.is_empty();
+    write_state(storage, &FsckState { timestamp, errors })?;
+    if failed {
         anyhow::bail!("Encountered errors")
     }
 
@@ -0,0 +1,406 @@
+//! # Deployment history
+//!
+//! A persistent log of the images this host has staged, switched to, rolled back to
+//! and booted.  Unlike the deployments themselves, this survives garbage collection.
+//! It is stored as JSON lines in `/sysroot/ostree/bootc/history.jsonl`.
+
+use std::io::{BufRead, IsTerminal, Seek, SeekFrom, Write};
+
+use anyhow::{Context, Result};
+use cap_std_ext::cap_std::fs::{Dir, OpenOptions};
+use cap_std_ext::dirext::CapStdExtDirExt;
+use chrono::{DateTime, Utc};
+use comfy_table::{presets::NOTHING, Table};
+use fn_error_context::context;
+use ostree_ext::ostree;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::OutputFormat;
+use crate::spec::{ImageReference, ImageStatus};
+use crate::store::{Storage, BOOTC_ROOT};
+
+/// The history file, relative to [`BOOTC_ROOT`]
+const HISTORY_FILE: &str = "history.jsonl";
+/// The maximum size of the history file; once exceeded, the oldest entries
+/// are dropped until it is at most half this size.
+const MAX_HISTORY_SIZE: u64 = 1024 * 1024;
+/// The amount of the end of the history read by [`load_recent`].
+const RECENT_HISTORY_SIZE: u64 = 64 * 1024;
+
+/// The kind of change recorded in the history
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum HistoryEvent {
+    /// An image was staged for the next boot
+    Stage,
+    /// The system was switched to a new image
+    Switch,
+    /// The rollback deployment was queued for the next boot
+    Rollback,
+    /// The system booted into a deployment for the first time
+    FirstBoot,
+}
+
+impl std::fmt::Display for HistoryEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            HistoryEvent::Stage => "stage",
+            HistoryEvent::Switch => "switch",
+            HistoryEvent::Rollback => "rollback",
+            HistoryEvent::FirstBoot => "first-boot",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A single record in the history
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryEntry {
+    /// When the change happened
+    pub(crate) timestamp: DateTime<Utc>,
+    /// The kind of change
+    pub(crate) event: HistoryEvent,
+    /// The image reference
+    pub(crate) image: ImageReference,
+    /// The manifest digest of the image
+    pub(crate) manifest_digest: String,
+    /// The version of the image, if any
+    #[serde(default)]
+    pub(crate) version: Option<String>,
+    /// The ostree deployment, in the form `<checksum>.<serial>`
+    #[serde(default)]
+    pub(crate) deployment: Option<String>,
+}
+
+fn deployment_id(deployment: &ostree::Deployment) -> String {
+    format!("{}.{}", deployment.csum(), deployment.deployserial())
+}
+
+impl HistoryEntry {
+    fn new_from_status(
+        event: HistoryEvent,
+        image: &ImageStatus,
+        deployment: Option<&ostree::Deployment>,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            event,
+            image: image.image.clone(),
+            manifest_digest: image.image_digest.clone(),
+            version: image.version.clone(),
+            deployment: deployment.map(deployment_id),
+        }
+    }
+}
+
+fn serialize_entry(entry: &HistoryEntry) -> Result<Vec<u8>> {
+    let mut buf = serde_json::to_vec(entry)?;
+    buf.push(b'\n');
+    Ok(buf)
+}
+
+/// Append an entry to the history in `d`, dropping the oldest entries if the
+/// file grows beyond `max_size`.
+fn append_to(d: &Dir, entry: &HistoryEntry, max_size: u64) -> Result<()> {
+    let buf = serialize_entry(entry)?;
+    let mut f = d.open_with(HISTORY_FILE, OpenOptions::new().create(true).append(true))?;
+    f.write_all(&buf)?;
+    f.flush()?;
+    if f.metadata()?.len() > max_size {
+        truncate(d, max_size / 2)?;
+    }
+    Ok(())
+}
+
+/// Rewrite the history in `d` with only the most recent entries which fit in `size`.
+#[context("Truncating history")]
+fn truncate(d: &Dir, size: u64) -> Result<()> {
+    let entries = load_from(d)?;
+    let mut lines = Vec::new();
+    let mut total = 0;
+    for entry in entries.iter().rev() {
+        let line = serialize_entry(entry)?;
+        total += line.len() as u64;
+        if total > size {
+            break;
+        }
+        lines.push(line);
+    }
+    tracing::debug!("Dropping {} history entries", entries.len() - lines.len());
+    let buf = lines.into_iter().rev().flatten().collect::<Vec<_>>();
+    d.atomic_write(HISTORY_FILE, buf)?;
+    Ok(())
+}
+
+fn parse_entries(r: impl BufRead) -> Result<Vec<HistoryEntry>> {
+    let mut entries = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Tolerate a truncated final line, e.g. from a crash while appending
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => tracing::warn!("Skipping invalid history entry on line {}: {e}", i + 1),
+        }
+    }
+    Ok(entries)
+}
+
+fn load_from(d: &Dir) -> Result<Vec<HistoryEntry>> {
+    let Some(f) = d.open_optional(HISTORY_FILE)? else {
+        return Ok(Default::default());
+    };
+    parse_entries(std::io::BufReader::new(f))
+}
+
+/// Load the entries in the last `size` bytes of the history in `d`.
+fn load_recent_from(d: &Dir, size: u64) -> Result<Vec<HistoryEntry>> {
+    let Some(mut f) = d.open_optional(HISTORY_FILE)? else {
+        return Ok(Default::default());
+    };
+    let offset = f.metadata()?.len().saturating_sub(size);
+    // Start from the byte before the offset, so that we can skip the rest of
+    // the line we seeked into (which is empty if the offset starts a line).
+    f.seek(SeekFrom::Start(offset.saturating_sub(1)))?;
+    let mut r = std::io::BufReader::new(f);
+    if offset > 0 {
+        r.read_until(b'\n', &mut Vec::new())?;
+    }
+    parse_entries(r)
+}
+
+/// Open the directory holding the history, if it exists.
+fn open_history_dir(sysroot: &Storage) -> Result<Option<Dir>> {
+    let sysroot_dir = crate::utils::sysroot_dir(sysroot)?;
+    sysroot_dir
+        .open_dir_optional(BOOTC_ROOT)
+        .map_err(Into::into)
+}
+
+/// Open the directory holding the history, creating it if necessary.
+fn ensure_history_dir(sysroot: &Storage) -> Result<Dir> {
+    if let Some(d) = open_history_dir(sysroot)? {
+        return Ok(d);
+    }
+    let sysroot_dir = crate::utils::sysroot_dir(sysroot)?;
+    sysroot_dir.create_dir_all(BOOTC_ROOT)?;
+    sysroot_dir.open_dir(BOOTC_ROOT).map_err(Into::into)
+}
+
+/// Load the history, without creating it if it doesn't exist.
+#[context("Loading history")]
+pub(crate) fn load(sysroot: &Storage) -> Result<Vec<HistoryEntry>> {
+    match open_history_dir(sysroot)? {
+        Some(d) => load_from(&d),
+        None => Ok(Default::default()),
+    }
+}
+
+/// Load the most recent entries of the history, without creating it if it
+/// doesn't exist.  Older entries may be omitted.
+#[context("Loading history")]
+pub(crate) fn load_recent(sysroot: &Storage) -> Result<Vec<HistoryEntry>> {
+    match open_history_dir(sysroot)? {
+        Some(d) => load_recent_from(&d, RECENT_HISTORY_SIZE),
+        None => Ok(Default::default()),
+    }
+}
+
+/// Append a record to the history.
+#[context("Recording history")]
+fn append(sysroot: &Storage, entry: &HistoryEntry) -> Result<()> {
+    append_to(&ensure_history_dir(sysroot)?, entry, MAX_HISTORY_SIZE)
+}
+
+/// Record that a fetched image was staged (or switched to).
+pub(crate) fn record_staged(
+    sysroot: &Storage,
+    event: HistoryEvent,
+    image: &ImageReference,
+    fetched: &crate::deploy::ImageState,
+    deployment: &ostree::Deployment,
+) -> Result<()> {
+    let entry = HistoryEntry {
+        timestamp: Utc::now(),
+        event,
+        image: image.clone(),
+        manifest_digest: fetched.manifest_digest.to_string(),
+        version: fetched.version.clone(),
+        deployment: Some(deployment_id(deployment)),
+    };
+    append(sysroot, &entry)
+}
+
+/// Record that a deployment with the given image was queued as the result of a rollback.
+pub(crate) fn record_rollback(
+    sysroot: &Storage,
+    image: &ImageStatus,
+    deployment: &ostree::Deployment,
+) -> Result<()> {
+    let entry = HistoryEntry::new_from_status(HistoryEvent::Rollback, image, Some(deployment));
+    append(sysroot, &entry)
+}
+
+/// Record the booted deployment, if this is the first time it was booted.
+#[context("Recording boot")]
+pub(crate) fn record_boot(sysroot: &Storage) -> Result<()> {
+    let (booted, _deployments, host) = crate::status::get_status_require_booted(sysroot)?;
+    let Some(image) = host.status.booted.as_ref().and_then(|b| b.image.as_ref()) else {
+        tracing::debug!("Booted deployment is not container image based");
+        return Ok(());
+    };
+    let id = deployment_id(&booted);
+    let seen = load(sysroot)?.iter().any(|e| {
+        e.event == HistoryEvent::FirstBoot && e.deployment.as_deref() == Some(id.as_str())
+    });
+    if seen {
+        tracing::debug!("Already recorded boot of {id}");
+        return Ok(());
+    }
+    let entry = HistoryEntry::new_from_status(HistoryEvent::FirstBoot, image, Some(&booted));
+    append(sysroot, &entry)
+}
+
+fn human_render(mut out: impl Write, entries: &[HistoryEntry]) -> Result<()> {
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
+        .set_header(["TIMESTAMP", "EVENT", "IMAGE", "VERSION", "DIGEST"]);
+    for entry in entries {
+        table.add_row([
+            entry.timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            entry.event.to_string(),
+            entry.image.to_string(),
+            entry.version.clone().unwrap_or_default(),
+            entry.manifest_digest.clone(),
+        ]);
+    }
+    writeln!(out, "{table}")?;
+    Ok(())
+}
+
+/// Implementation of the `bootc history` CLI command.
+#[context("History")]
+pub(crate) async fn history(format: Option<OutputFormat>) -> Result<()> {
+    let sysroot = &crate::cli::get_storage().await?;
+    let entries = load(sysroot)?;
+    let format = format.unwrap_or_else(|| {
+        if std::io::stdout().is_terminal() {
+            OutputFormat::HumanReadable
+        } else {
+            OutputFormat::Yaml
+        }
+    });
+    let out = std::io::stdout();
+    let mut out = out.lock();
+    match format {
+        OutputFormat::Json => serde_json::to_writer(&mut out, &entries).map_err(anyhow::Error::new),
+        OutputFormat::Yaml => serde_yaml::to_writer(&mut out, &entries).map_err(anyhow::Error::new),
+        OutputFormat::HumanReadable => human_render(&mut out, &entries),
+    }
+    .context("Writing to stdout")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cap_std_ext::cap_std;
+
+    use super::*;
+
+    fn test_entry(event: HistoryEvent, digest: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: "2024-08-07T12:00:00Z".parse().unwrap(),
+            event,
+            image: ImageReference {
+                image: "quay.io/example/os:latest".into(),
+                transport: "registry".into(),
+                signature: None,
+            },
+            manifest_digest: digest.into(),
+            version: Some("42.1".into()),
+            deployment: None,
+        }
+    }
+
+    #[test]
+    fn test_append_load() -> Result<()> {
+        let td = cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        assert_eq!(load_from(&td)?.len(), 0);
+
+        let a = test_entry(HistoryEvent::Stage, "sha256:aaaa");
+        let b = test_entry(HistoryEvent::Rollback, "sha256:bbbb");
+        append_to(&td, &a, MAX_HISTORY_SIZE)?;
+        append_to(&td, &b, MAX_HISTORY_SIZE)?;
+        assert_eq!(load_from(&td)?, [a.clone(), b.clone()]);
+
+        // A truncated trailing line is ignored
+        let mut f = td.open_with(HISTORY_FILE, OpenOptions::new().append(true))?;
+        f.write_all(br#"{"timestamp":"2024-08"#)?;
+        assert_eq!(load_from(&td)?, [a, b]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_truncate() -> Result<()> {
+        let td = cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        let entries = (0..10)
+            .map(|i| test_entry(HistoryEvent::Stage, &format!("sha256:{i:04}")))
+            .collect::<Vec<_>>();
+        let entry_size = serialize_entry(&entries[0])?.len() as u64;
+        let max_size = entry_size * 4;
+        for (i, entry) in entries.iter().enumerate() {
+            append_to(&td, entry, max_size)?;
+            let size = td.metadata(HISTORY_FILE)?.len();
+            assert!(size <= max_size, "size {size} after {i} entries");
+        }
+        // The most recent entries are retained
+        let loaded = load_from(&td)?;
+        assert!(!loaded.is_empty());
+        assert_eq!(loaded, entries[entries.len() - loaded.len()..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_recent() -> Result<()> {
+        let td = cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        assert_eq!(load_recent_from(&td, 1)?.len(), 0);
+        let entries = (0..10)
+            .map(|i| test_entry(HistoryEvent::Stage, &format!("sha256:{i:04}")))
+            .collect::<Vec<_>>();
+        for entry in entries.iter() {
+            append_to(&td, entry, MAX_HISTORY_SIZE)?;
+        }
+        let entry_size = serialize_entry(&entries[0])?.len() as u64;
+        // Exactly three entries, and three entries plus part of another
+        assert_eq!(load_recent_from(&td, entry_size * 3)?, entries[7..]);
+        assert_eq!(load_recent_from(&td, entry_size * 3 + 5)?, entries[7..]);
+        assert_eq!(load_recent_from(&td, entry_size * 100)?, entries);
+        Ok(())
+    }
+
+    #[test]
+    fn test_human_render() -> Result<()> {
+        let entries = [
+            test_entry(HistoryEvent::Switch, "sha256:aaaa"),
+            test_entry(HistoryEvent::FirstBoot, "sha256:aaaa"),
+        ];
+        let mut w = Vec::new();
+        human_render(&mut w, &entries)?;
+        let w = String::from_utf8(w)?;
+        let mut lines = w.lines();
+        let header = lines.next().unwrap();
+        assert!(header.contains("TIMESTAMP"));
+        assert!(header.contains("DIGEST"));
+        let row = lines.next().unwrap();
+        assert!(row.contains("2024-08-07T12:00:00Z"));
+        assert!(row.contains("switch"));
+        assert!(row.contains("42.1"));
+        assert!(lines.next().unwrap().contains("first-boot"));
+        Ok(())
+    }
+}
@@ -287,20 +287,10 @@ impl Storage {
     }
 
     #[context("Pruning")]
-    pub(crate) async fn prune_except_roots(&self, roots: &HashSet<&str>) -> Result<Vec<String>> {
+    pub(crate) async fn prune_except_roots(&self, roots: &HashSet<String>) -> Result<Vec<String>> {
         let all_images = self.list_images().await?;
         tracing::debug!("Images total: {}", all_images.len(),);
-        let mut garbage = Vec::new();
-        for image in all_images {
-            if image
-                .names
-                .iter()
-                .flatten()
-                .any(|name| !roots.contains(name.as_str()))
-            {
-                garbage.push(image.id);
-            }
-        }
+        let garbage = unreferenced_images(all_images, roots);
         tracing::debug!("Images to prune: {}", garbage.len());
         for garbage in garbage.chunks(SUBCMD_ARGV_CHUNKING) {
             let mut cmd = self.new_image_cmd()?;
@@ -319,9 +309,25 @@ impl Storage {
         // doesn't work with containers-storage yet
         let mut cmd = AsyncCommand::from(self.new_image_cmd()?);
         cmd.args(["exists", image]);
+        cmd.kill_on_drop(true);
         Ok(cmd.status().await?.success())
     }
 
+    /// Inspect an image in the storage.
+    #[context("Inspecting image {image}")]
+    pub(crate) async fn inspect(&self, image: &str) -> Result<crate::podman::Inspect> {
+        let mut cmd = self.new_image_cmd()?;
+        cmd.stdin(Stdio::null());
+        cmd.args(["inspect", image]);
+        let o: Vec<crate::podman::Inspect> =
+            tokio::task::spawn_blocking(move || cmd.run_and_parse_json()).await??;
+        let i = o
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No images returned for inspect"))?;
+        Ok(i)
+    }
+
     /// Fetch the image if it is not already present; return whether
     /// or not the image was fetched.
     pub(crate) async fn pull(&self, image: &str, mode: PullMode) -> Result<bool> {
@@ -345,6 +351,9 @@ impl Storage {
         }
         tracing::debug!("Pulling image: {image}");
         let mut cmd = AsyncCommand::from(cmd);
+        // Don't leave podman running if the pull is cancelled, e.g. because
+        // a concurrent pull of another image failed.
+        cmd.kill_on_drop(true);
         cmd.run().await.context("Failed to pull image")?;
         Ok(true)
     }
@@ -377,6 +386,28 @@ impl Storage {
     }
 }
 
+/// Return the IDs of the named images for which none of the names (or
+/// `name@digest` references) is in `roots`.
+/// Images without a name (e.g. pulled only by digest) are retained.
+pub(crate) fn unreferenced_images(
+    images: Vec<crate::podman::ImageListEntry>,
+    roots: &HashSet<String>,
+) -> Vec<String> {
+    images
+        .into_iter()
+        .filter(|image| {
+            image.names.as_ref().is_some_and(|names| {
+                !names.is_empty()
+                    && !names
+                        .iter()
+                        .chain(image.repo_digests.iter().flatten())
+                        .any(|name| roots.contains(name.as_str()))
+            })
+        })
+        .map(|image| image.id)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -55,7 +55,7 @@ use crate::boundimage::{BoundImage, ResolvedBoundImage};
 use crate::containerenv::ContainerExecutionInfo;
 use crate::deploy::{prepare_for_pull, pull_from_prepared, PreparedImportMeta, PreparedPullResult};
 use crate::lsm;
-use crate::progress_jsonl::ProgressWriter;
+use crate::progress_jsonl::{Event, ProgressWriter, SubTaskStep};
 use crate::spec::ImageReference;
 use crate::store::Storage;
 use crate::task::Task;
@@ -74,7 +74,7 @@ const DESTRUCTIVE_CLEANUP: &str = "bootc-destructive-cleanup";
 /// This is an ext4 special directory we need to ignore.
 const LOST_AND_FOUND: &str = "lost+found";
 /// The filename of the composefs EROFS superblock; TODO move this into ostree
-const OSTREE_COMPOSEFS_SUPER: &str = ".ostree.cfs";
+pub(crate) const OSTREE_COMPOSEFS_SUPER: &str = ".ostree.cfs";
 /// The mount path for selinux
 const SELINUXFS: &str = "/sys/fs/selinux";
 /// The mount path for uefi
@@ -172,7 +172,7 @@ impl std::fmt::Display for BoundImagesOpt {
     }
 }
 
-#[derive(clap::Args, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(clap::Args, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) struct InstallConfigOpts {
     /// Disable SELinux in the target (installed) system.
     ///
@@ -216,10 +216,14 @@ pub(crate) struct InstallConfigOpts {
     /// The stateroot name to use. Defaults to `default`.
     #[clap(long)]
     pub(crate) stateroot: Option<String>,
+
+    #[clap(flatten)]
+    #[serde(skip)]
+    pub(crate) progress: crate::cli::ProgressOptions,
 }
 
 #[cfg(feature = "install-to-disk")]
-#[derive(Debug, Clone, clap::Parser, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, clap::Parser, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) struct InstallToDiskOpts {
     #[clap(flatten)]
     #[serde(flatten)]
@@ -303,7 +307,7 @@ pub(crate) struct InstallTargetFilesystemOpts {
     pub(crate) skip_finalize: bool,
 }
 
-#[derive(Debug, Clone, clap::Parser, PartialEq, Eq)]
+#[derive(Debug, clap::Parser, PartialEq, Eq)]
 pub(crate) struct InstallToFilesystemOpts {
     #[clap(flatten)]
     pub(crate) filesystem_opts: InstallTargetFilesystemOpts,
@@ -318,7 +322,7 @@ pub(crate) struct InstallToFilesystemOpts {
     pub(crate) config_opts: InstallConfigOpts,
 }
 
-#[derive(Debug, Clone, clap::Parser, PartialEq, Eq)]
+#[derive(Debug, clap::Parser, PartialEq, Eq)]
 pub(crate) struct InstallToExistingRootOpts {
     /// Configure how existing data is treated.
     #[clap(long, default_value = "alongside")]
@@ -380,6 +384,8 @@ pub(crate) struct State {
     /// The root filesystem of the running container
     pub(crate) container_root: Dir,
     pub(crate) tempdir: TempDir,
+    /// Progress reporting via `--progress-fd`
+    pub(crate) prog: ProgressWriter,
 }
 
 impl State {
@@ -754,7 +760,7 @@ async fn install_container(
     root_setup: &RootSetup,
     sysroot: &ostree::Sysroot,
     has_ostree: bool,
-) -> Result<(ostree::Deployment, InstallAleph)> {
+) -> Result<(ostree::Deployment, InstallAleph, oci_spec::image::Digest)> {
     let sepolicy = state.load_policy()?;
     let sepolicy = sepolicy.as_ref();
     let stateroot = state.stateroot();
@@ -802,7 +808,7 @@ async fn install_container(
                     &spec_imgref,
                     Some(&state.target_imgref),
                     false,
-                    ProgressWriter::default(),
+                    state.prog.clone(),
                     image_meta,
                 )
                 .await?
@@ -907,7 +913,7 @@ async fn install_container(
     }
 
     let aleph = InstallAleph::new(&src_imageref, &imgstate, &state.selinux_state)?;
-    Ok((deployment, aleph))
+    Ok((deployment, aleph, imgstate.manifest_digest))
 }
 
 /// Run a command in the host mount namespace
@@ -1177,11 +1183,13 @@ async fn verify_target_fetch(
 
 /// Preparation for an install; validates and prepares some (thereafter immutable) global state.
 async fn prepare_install(
-    config_opts: InstallConfigOpts,
+    mut config_opts: InstallConfigOpts,
     source_opts: InstallSourceOpts,
     target_opts: InstallTargetOpts,
 ) -> Result<Arc<State>> {
     tracing::trace!("Preparing install");
+    // Take ownership of the progress fd first, so it is closed on any error
+    let prog: ProgressWriter = std::mem::take(&mut config_opts.progress).try_into()?;
     let rootfs = cap_std::fs::Dir::open_ambient_dir("/", cap_std::ambient_authority())
         .context("Opening /")?;
 
@@ -1324,11 +1332,54 @@ async fn prepare_install(
         container_root: rootfs,
         tempdir,
         host_is_container,
+        prog,
     });
 
     Ok(state)
 }
 
+/// The steps of an installation, as reported via `--progress-fd`.
+const INSTALL_STEPS: &[(&str, &str)] = &[
+    ("initializing", "Initializing ostree root"),
+    ("deploying", "Deploying image"),
+    ("bootloader", "Installing bootloader"),
+    ("bound_images", "Fetching bound images"),
+    ("finalizing", "Finalizing filesystems"),
+];
+
+/// Report that an installation step has started, and hence that all previous
+/// steps are completed.  If `step` is `None`, all steps are completed.
+async fn send_install_step(state: &State, step: Option<&str>) {
+    state.prog.send(install_step_event(state, step)).await
+}
+
+/// The event for [`send_install_step`].
+fn install_step_event<'s>(state: &'s State, step: Option<&str>) -> Event<'s> {
+    let n = step
+        .and_then(|step| INSTALL_STEPS.iter().position(|(s, _)| *s == step))
+        .unwrap_or(INSTALL_STEPS.len());
+    let subtasks = INSTALL_STEPS
+        .iter()
+        .take(n + 1)
+        .enumerate()
+        .map(|(i, (subtask, description))| SubTaskStep {
+            subtask: (*subtask).into(),
+            description: (*description).into(),
+            id: (*subtask).into(),
+            completed: i < n,
+        })
+        .collect();
+    Event::ProgressSteps {
+        task: "installing".into(),
+        description: "Installing Image".into(),
+        id: state.target_imgref.imgref.name.as_str().into(),
+        steps_cached: 0,
+        steps: n as u64,
+        steps_total: INSTALL_STEPS.len() as u64,
+        subtasks,
+    }
+}
+
 /// Given a baseline root filesystem with an ostree sysroot initialized:
 /// - install the container to that root
 /// - install the bootloader
@@ -1341,10 +1392,12 @@ async fn install_with_sysroot(
     bound_images: BoundImages,
     has_ostree: bool,
     imgstore: &crate::imgstorage::Storage,
-) -> Result<()> {
+) -> Result<(InstallAleph, oci_spec::image::Digest)> {
     // And actually set up the container in that root, returning a deployment and
     // the aleph state (see below).
-    let (_deployment, aleph) = install_container(state, rootfs, &sysroot, has_ostree).await?;
+    send_install_step(state, Some("deploying")).await;
+    let (_deployment, aleph, digest) =
+        install_container(state, rootfs, &sysroot, has_ostree).await?;
     // Write the aleph data that captures the system state at the time of provisioning for aid in future debugging.
     rootfs
         .physical_root
@@ -1354,6 +1407,7 @@ async fn install_with_sysroot(
         })
         .context("Writing aleph version")?;
 
+    send_install_step(state, Some("bootloader")).await;
     if cfg!(target_arch = "s390x") {
         // TODO: Integrate s390x support into install_via_bootupd
         crate::bootloader::install_via_zipl(&rootfs.device_info, boot_uuid)?;
@@ -1368,23 +1422,23 @@ async fn install_with_sysroot(
 
     tracing::debug!("Perfoming post-deployment operations");
 
+    send_install_step(state, Some("bound_images")).await;
     match bound_images {
         BoundImages::Skip => {}
         BoundImages::Resolved(resolved_bound_images) => {
             // Now copy each bound image from the host's container storage into the target.
-            for image in resolved_bound_images {
-                let image = image.image.as_str();
-                imgstore.pull_from_host_storage(image).await?;
-            }
+            crate::boundimage::copy_from_host_storage(imgstore, resolved_bound_images).await?;
         }
         BoundImages::Unresolved(bound_images) => {
-            crate::boundimage::pull_images_impl(imgstore, bound_images)
+            // Each bound image is reported as an additional subtask of this step
+            let step = install_step_event(state, Some("bound_images"));
+            crate::boundimage::pull_images_impl(imgstore, bound_images, &state.prog, Some(&step))
                 .await
                 .context("pulling bound images")?;
         }
     }
 
-    Ok(())
+    Ok((aleph, digest))
 }
 
 enum BoundImages {
@@ -1405,7 +1459,11 @@ impl BoundImages {
                         let mut r = Vec::with_capacity(queried_images.len());
                         for image in queried_images {
                             let resolved = ResolvedBoundImage::from_image(&image).await?;
-                            tracing::debug!("Resolved {}: {}", resolved.image, resolved.digest);
+                            tracing::debug!(
+                                "Resolved {}: {}",
+                                resolved.bound_image.image,
+                                resolved.digest
+                            );
                             r.push(resolved)
                         }
                         BoundImages::Resolved(r)
@@ -1428,6 +1486,24 @@ async fn install_to_filesystem_impl(
     rootfs: &mut RootSetup,
     cleanup: Cleanup,
 ) -> Result<()> {
+    let r = install_to_filesystem_impl_inner(state, rootfs, cleanup).await;
+    match &r {
+        Ok((aleph, digest)) => {
+            state
+                .prog
+                .send_summary("install", &digest.to_string(), aleph.version.as_deref())
+                .await
+        }
+        Err(e) => state.prog.send_failure("install", e).await,
+    }
+    r.map(|_| ())
+}
+
+async fn install_to_filesystem_impl_inner(
+    state: &State,
+    rootfs: &mut RootSetup,
+    cleanup: Cleanup,
+) -> Result<(InstallAleph, oci_spec::image::Digest)> {
     if matches!(state.selinux_state, SELinuxFinalState::ForceTargetDisabled) {
         rootfs.kargs.push("selinux=0".to_string());
     }
@@ -1457,10 +1533,11 @@ async fn install_to_filesystem_impl(
 
     // Initialize the ostree sysroot (repo, stateroot, etc.)
 
-    {
+    let installed = {
+        send_install_step(state, Some("initializing")).await;
         let (sysroot, has_ostree, imgstore) = initialize_ostree_root(state, rootfs).await?;
 
-        install_with_sysroot(
+        let installed = install_with_sysroot(
             state,
             rootfs,
             &sysroot,
@@ -1479,9 +1556,11 @@ async fn install_to_filesystem_impl(
 
         // We must drop the sysroot here in order to close any open file
         // descriptors.
+        installed
     };
 
     // Run this on every install as the penultimate step
+    send_install_step(state, Some("finalizing")).await;
     install_finalize(&rootfs.physical_root_path).await?;
 
     // Finalize mounted filesystems
@@ -1491,8 +1570,9 @@ async fn install_to_filesystem_impl(
             finalize_filesystem(fsname, &rootfs.physical_root, fs)?;
         }
     }
+    send_install_step(state, None).await;
 
-    Ok(())
+    Ok(installed)
 }
 
 fn installation_complete() {
@@ -299,7 +299,7 @@ pub(crate) async fn impl_completion(
         // having a hard dependency on it.
         let imgstorage =
             &crate::imgstorage::Storage::create(&sysroot_dir, &rundir, sepolicy.as_ref())?;
-        crate::boundimage::pull_images_impl(imgstorage, bound_images)
+        crate::boundimage::pull_images_impl(imgstorage, bound_images, &Default::default(), None)
             .await
             .context("pulling bound images")?;
     }
@@ -4,41 +4,197 @@ use cap_std_ext::cap_std::fs::Dir;
 use cap_std_ext::cap_std::fs_utf8::Dir as DirUtf8;
 use cap_std_ext::dirext::CapStdExtDirExt;
 use cap_std_ext::dirext::CapStdExtDirExtUtf8;
+use fn_error_context::context;
 use ostree::gio;
 use ostree_ext::ostree;
 use ostree_ext::ostree::Deployment;
 use ostree_ext::prelude::Cast;
 use ostree_ext::prelude::FileEnumeratorExt;
 use ostree_ext::prelude::FileExt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::cli::KargsOpts;
 use crate::deploy::ImageState;
+use crate::spec::KernelArgsSpec;
 use crate::store::Storage;
 
 const KARGS_PATH: &str = "usr/lib/bootc/kargs.d";
+/// Machine-local kernel arguments, in the same format as [`KARGS_PATH`].
+/// Being in `/etc`, these are carried forward into new deployments.
+const LOCAL_KARGS_PATH: &str = "etc/bootc/kargs.d";
+/// The file in [`LOCAL_KARGS_PATH`] managed by `bootc kargs`.
+const LOCAL_KARGS_FILE: &str = "50-bootc-local.toml";
 
 /// The kargs.d configuration file.
-#[derive(Deserialize)]
+///
+/// Files are processed in lexicographic order; `delete` and `replace` operate on the
+/// kernel arguments accumulated from earlier files, before `kargs` are appended.
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct Config {
     /// Ordered list of kernel arguments.
+    #[serde(default)]
     kargs: Vec<String>,
+    /// Kernel arguments to remove.  An entry of the form `key=value` removes
+    /// exactly that argument; an entry `key` removes all arguments with that key.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    delete: Vec<String>,
+    /// Kernel arguments of the form `key=value` which replace all existing
+    /// arguments with that key.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    replace: Vec<String>,
     /// Optional list of architectures (using the Rust naming conventions);
     /// if present and the current architecture doesn't match, the file is skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
     match_architectures: Option<Vec<String>>,
 }
 
+/// The key of a kernel argument, i.e. the part before any `=`.
+fn karg_key(karg: &str) -> &str {
+    karg.split_once('=').map(|(k, _)| k).unwrap_or(karg)
+}
+
+/// Returns true if the `delete` entry matches the kernel argument.
+pub(crate) fn delete_matches(entry: &str, karg: &str) -> bool {
+    if entry.contains('=') {
+        entry == karg
+    } else {
+        karg_key(karg) == entry
+    }
+}
+
 impl Config {
     /// Return true if the filename is one we should parse.
     fn filename_matches(name: &str) -> bool {
         matches!(Utf8Path::new(name).extension(), Some("toml"))
     }
+
+    /// Reject contradictory or meaningless combinations of operations.
+    fn validate(&self) -> Result<()> {
+        let mut replaced_keys = Vec::new();
+        for entry in &self.replace {
+            let Some((key, _)) = entry.split_once('=') else {
+                anyhow::bail!("Invalid replace entry {entry}: expected key=value");
+            };
+            if replaced_keys.contains(&key) {
+                anyhow::bail!("Multiple replace entries for {key}");
+            }
+            replaced_keys.push(key);
+        }
+        for entry in &self.delete {
+            if entry.is_empty() {
+                anyhow::bail!("Invalid empty delete entry");
+            }
+            if replaced_keys.contains(&karg_key(entry)) {
+                anyhow::bail!("Kernel argument {entry} is both deleted and replaced");
+            }
+            if let Some(karg) = self.kargs.iter().find(|k| delete_matches(entry, k)) {
+                anyhow::bail!("Kernel argument {karg} is both added and deleted");
+            }
+        }
+        if let Some(karg) = self
+            .kargs
+            .iter()
+            .find(|k| replaced_keys.contains(&karg_key(k)))
+        {
+            anyhow::bail!("Kernel argument {karg} is both added and replaced");
+        }
+        Ok(())
+    }
+
+    /// Apply the `delete` and `replace` entries of this file to a kernel command line.
+    fn apply_edits(&self, kargs: &mut Vec<String>) {
+        kargs.retain(|k| !self.delete.iter().any(|entry| delete_matches(entry, k)));
+        for entry in &self.replace {
+            let key = karg_key(entry);
+            let pos = kargs.iter().position(|k| karg_key(k) == key);
+            kargs.retain(|k| karg_key(k) != key);
+            match pos {
+                Some(pos) => kargs.insert(pos, entry.clone()),
+                None => kargs.push(entry.clone()),
+            }
+        }
+    }
+
+    /// Apply this file to the kernel arguments accumulated from previous files.
+    fn apply(self, kargs: &mut Vec<String>) {
+        self.apply_edits(kargs);
+        kargs.extend(self.kargs);
+    }
+}
+
+/// The result of processing all files in a kargs.d directory.
+#[derive(Default)]
+struct KargsD {
+    /// The combined kernel arguments.
+    kargs: Vec<String>,
+    /// The files with `delete` or `replace` entries, in order.
+    edits: Vec<Config>,
+}
+
+impl KargsD {
+    /// Apply a file to the kernel arguments accumulated from previous files.
+    fn push(&mut self, mut config: Config) {
+        config.apply_edits(&mut self.kargs);
+        self.kargs.append(&mut config.kargs);
+        if !(config.delete.is_empty() && config.replace.is_empty()) {
+            self.edits.push(config);
+        }
+    }
+
+    /// Apply all deletions and replacements to a kernel command line, such as
+    /// one with kernel arguments set at install time.
+    fn apply_edits(&self, kargs: &mut Vec<String>) {
+        for config in &self.edits {
+            config.apply_edits(kargs);
+        }
+    }
 }
 
 /// Load and parse all bootc kargs.d files in the specified root, returning
 /// a combined list.
 pub(crate) fn get_kargs_in_root(d: &Dir, sys_arch: &str) -> Result<Vec<String>> {
+    get_kargs_in_dir(d, KARGS_PATH, sys_arch).map(|r| r.kargs)
+}
+
+/// Load and parse all machine-local kargs.d files in the specified root; unlike
+/// those from the image, deletions here apply to the entire kernel command line.
+fn get_local_kargs_in_root(d: &Dir, sys_arch: &str) -> Result<KernelArgsSpec> {
+    let r = get_kargs_in_dir(d, LOCAL_KARGS_PATH, sys_arch)?;
+    let remove = r
+        .edits
+        .into_iter()
+        .flat_map(|config| config.delete)
+        .collect();
+    Ok(KernelArgsSpec {
+        add: r.kargs,
+        remove,
+    })
+}
+
+/// Returns the combined kernel arguments from all files in the directory,
+/// along with all deletions and replacements.
+fn get_kargs_in_dir(d: &Dir, path: &str, sys_arch: &str) -> Result<KargsD> {
     // If the directory doesn't exist, that's OK.
+    let Some(d) = d.open_dir_optional(path)?.map(DirUtf8::from_cap_std) else {
+        return Ok(Default::default());
+    };
+    let mut ret = KargsD::default();
+    let entries = d.filenames_filtered_sorted(|_, name| Config::filename_matches(name))?;
+    for name in entries {
+        let buf = d.read_to_string(&name)?;
+        if let Some(config) =
+            parse_config(&buf, sys_arch).with_context(|| format!("Parsing {name}"))?
+        {
+            ret.push(config);
+        }
+    }
+    Ok(ret)
+}
+
+/// Find kargs.d files in the specified root with invalid combinations of
+/// operations, regardless of architecture; returns a description of each problem.
+pub(crate) fn find_invalid_kargs_in_root(d: &Dir) -> Result<Vec<String>> {
     let Some(d) = d.open_dir_optional(KARGS_PATH)?.map(DirUtf8::from_cap_std) else {
         return Ok(Default::default());
     };
@@ -46,8 +202,10 @@ pub(crate) fn get_kargs_in_root(d: &Dir, sys_arch: &str) -> Result<Vec<String>>
     let entries = d.filenames_filtered_sorted(|_, name| Config::filename_matches(name))?;
     for name in entries {
         let buf = d.read_to_string(&name)?;
-        let kargs = parse_kargs_toml(&buf, sys_arch).with_context(|| format!("Parsing {name}"))?;
-        ret.extend(kargs)
+        let config: Config = toml::from_str(&buf).with_context(|| format!("Parsing {name}"))?;
+        if let Err(e) = config.validate() {
+            ret.push(format!("{name}: {e}"));
+        }
     }
     Ok(ret)
 }
@@ -63,7 +221,7 @@ pub(crate) fn get_kargs_from_ostree_root(
     if !kargsd.query_exists(gio::Cancellable::NONE) {
         return Ok(Default::default());
     }
-    get_kargs_from_ostree(repo, kargsd, sys_arch)
+    get_kargs_from_ostree(repo, kargsd, sys_arch).map(|r| r.kargs)
 }
 
 /// Load kargs.d files from the target dir
@@ -71,12 +229,14 @@ fn get_kargs_from_ostree(
     repo: &ostree::Repo,
     fetched_tree: &ostree::RepoFile,
     sys_arch: &str,
-) -> Result<Vec<String>> {
+) -> Result<KargsD> {
     let cancellable = gio::Cancellable::NONE;
     let queryattrs = "standard::name,standard::type";
     let queryflags = gio::FileQueryInfoFlags::NOFOLLOW_SYMLINKS;
     let fetched_iter = fetched_tree.enumerate_children(queryattrs, queryflags, cancellable)?;
-    let mut ret = Vec::new();
+    // Files must be processed in order, as later ones may delete or replace
+    // kernel arguments from earlier ones.
+    let mut files = Vec::new();
     while let Some(fetched_info) = fetched_iter.next_file(cancellable)? {
         // only read and parse the file if it is a toml file
         let name = fetched_info.name();
@@ -91,6 +251,11 @@ fn get_kargs_from_ostree(
         let fetched_child = fetched_child
             .downcast::<ostree::RepoFile>()
             .expect("downcast");
+        files.push((name.to_owned(), fetched_child));
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut ret = KargsD::default();
+    for (name, fetched_child) in files {
         fetched_child.ensure_resolved()?;
         let fetched_contents_checksum = fetched_child.checksum();
         let f = ostree::Repo::load_file(repo, fetched_contents_checksum.as_str(), cancellable)?;
@@ -98,9 +263,11 @@ fn get_kargs_from_ostree(
         let mut reader =
             ostree_ext::prelude::InputStreamExtManual::into_read(file_content.unwrap());
         let s = std::io::read_to_string(&mut reader)?;
-        let parsed_kargs =
-            parse_kargs_toml(&s, sys_arch).with_context(|| format!("Parsing {name}"))?;
-        ret.extend(parsed_kargs);
+        if let Some(config) =
+            parse_config(&s, sys_arch).with_context(|| format!("Parsing {name}"))?
+        {
+            ret.push(config);
+        }
     }
     Ok(ret)
 }
@@ -113,7 +280,6 @@ pub(crate) fn get_kargs(
     merge_deployment: &Deployment,
     fetched: &ImageState,
 ) -> Result<Vec<String>> {
-    let cancellable = gio::Cancellable::NONE;
     let repo = &sysroot.repo();
     let mut kargs = vec![];
     let sys_arch = std::env::consts::ARCH;
@@ -129,17 +295,42 @@ pub(crate) fn get_kargs(
     // Get the kargs in kargs.d of the merge
     let merge_root = &crate::utils::deployment_fd(sysroot, merge_deployment)?;
     let existing_kargs = get_kargs_in_root(merge_root, sys_arch)?;
+    // Machine-local kargs always apply to the new deployment
+    let local_kargs = get_local_kargs_in_root(merge_root, sys_arch)?;
+
+    get_kargs_for_commit(
+        repo,
+        fetched.ostree_commit.as_str(),
+        kargs,
+        existing_kargs,
+        &local_kargs,
+        sys_arch,
+    )
+}
 
+/// Compute the kernel arguments for a deployment of the commit, given the kernel
+/// arguments of the merge deployment (`kargs`) and those from its kargs.d files.
+fn get_kargs_for_commit(
+    repo: &ostree::Repo,
+    commit: &str,
+    mut kargs: Vec<String>,
+    existing_kargs: Vec<String>,
+    local_kargs: &KernelArgsSpec,
+    sys_arch: &str,
+) -> Result<Vec<String>> {
+    let cancellable = gio::Cancellable::NONE;
     // Get the kargs in kargs.d of the pending image
-    let (fetched_tree, _) = repo.read_commit(fetched.ostree_commit.as_str(), cancellable)?;
+    let (fetched_tree, _) = repo.read_commit(commit, cancellable)?;
     let fetched_tree = fetched_tree.resolve_relative_path(KARGS_PATH);
     let fetched_tree = fetched_tree
         .downcast::<ostree::RepoFile>()
         .expect("downcast");
+
     // A special case: if there's no kargs.d directory in the pending (fetched) image,
     // then we can just use the combined current kargs + kargs from booted
     if !fetched_tree.query_exists(cancellable) {
         kargs.extend(existing_kargs);
+        apply_local_kargs(&mut kargs, local_kargs);
         return Ok(kargs);
     }
 
@@ -147,15 +338,15 @@ pub(crate) fn get_kargs(
     let remote_kargs = get_kargs_from_ostree(repo, &fetched_tree, sys_arch)?;
 
     // get the diff between the existing and remote kargs
-    let mut added_kargs = remote_kargs
-        .clone()
-        .into_iter()
+    let added_kargs = remote_kargs
+        .kargs
+        .iter()
         .filter(|item| !existing_kargs.contains(item))
+        .cloned()
         .collect::<Vec<_>>();
     let removed_kargs = existing_kargs
-        .clone()
         .into_iter()
-        .filter(|item| !remote_kargs.contains(item))
+        .filter(|item| !remote_kargs.kargs.contains(item))
         .collect::<Vec<_>>();
 
     tracing::debug!(
@@ -166,23 +357,224 @@ pub(crate) fn get_kargs(
 
     // apply the diff to the system kargs
     kargs.retain(|x| !removed_kargs.contains(x));
-    kargs.append(&mut added_kargs);
+    // Deletions and replacements also apply to kargs which did not come
+    // from kargs.d, e.g. those set at install time
+    remote_kargs.apply_edits(&mut kargs);
+    merge_kargs(&mut kargs, &added_kargs);
+    apply_local_kargs(&mut kargs, local_kargs);
 
     Ok(kargs)
 }
 
-/// This parses a bootc kargs.d toml file, returning the resulting
-/// vector of kernel arguments. Architecture matching is performed using
-/// `sys_arch`.
-fn parse_kargs_toml(contents: &str, sys_arch: &str) -> Result<Vec<String>> {
+/// Apply machine-local removals and additions to a kernel command line.
+fn apply_local_kargs(kargs: &mut Vec<String>, local: &KernelArgsSpec) -> Vec<String> {
+    kargs.retain(|k| !local.remove.iter().any(|entry| delete_matches(entry, k)));
+    merge_kargs(kargs, &local.add)
+}
+
+/// Append each of `new` to `kargs` if not already present; returns the
+/// kernel arguments which were added.
+fn merge_kargs(kargs: &mut Vec<String>, new: &[String]) -> Vec<String> {
+    let mut added = Vec::new();
+    for karg in new {
+        if !kargs.contains(karg) {
+            kargs.push(karg.clone());
+            added.push(karg.clone());
+        }
+    }
+    added
+}
+
+/// Read the kernel arguments managed by `bootc kargs` in the target root.
+#[context("Reading local kargs")]
+pub(crate) fn read_local_kargs(root: &Dir) -> Result<KernelArgsSpec> {
+    let path = format!("{LOCAL_KARGS_PATH}/{LOCAL_KARGS_FILE}");
+    let Some(f) = root.open_optional(&path)? else {
+        return Ok(Default::default());
+    };
+    let buf = std::io::read_to_string(f)?;
+    let config: Config = toml::from_str(&buf).with_context(|| format!("Parsing {path}"))?;
+    config
+        .validate()
+        .with_context(|| format!("Parsing {path}"))?;
+    Ok(KernelArgsSpec {
+        add: config.kargs,
+        remove: config.delete,
+    })
+}
+
+/// Write the kernel arguments managed by `bootc kargs` in the target root.
+#[context("Writing local kargs")]
+fn write_local_kargs(root: &Dir, spec: &KernelArgsSpec) -> Result<()> {
+    for karg in spec.add.iter().chain(spec.remove.iter()) {
+        anyhow::ensure!(!karg.trim().is_empty(), "Invalid empty kernel argument");
+    }
+    let config = Config {
+        kargs: spec.add.clone(),
+        delete: spec.remove.clone(),
+        replace: Vec::new(),
+        match_architectures: None,
+    };
+    config.validate()?;
+    let buf = toml::to_string(&config)?;
+    root.create_dir_all(LOCAL_KARGS_PATH)?;
+    root.atomic_write(format!("{LOCAL_KARGS_PATH}/{LOCAL_KARGS_FILE}"), buf)?;
+    Ok(())
+}
+
+/// Update the kernel arguments of the booted deployment (and the staged one, if any)
+/// in place, so that changes to the local kargs take effect on the next boot.
+#[context("Updating deployment kargs")]
+fn apply_to_deployments(sysroot: &Storage, added: &[String], removed: &[String]) -> Result<()> {
+    let cancellable = gio::Cancellable::NONE;
+    let booted = sysroot.require_booted_deployment()?;
+    let change = KernelArgsSpec {
+        add: added.to_vec(),
+        remove: removed.to_vec(),
+    };
+    for deployment in std::iter::once(booted).chain(sysroot.staged_deployment()) {
+        let options = deployment
+            .bootconfig()
+            .and_then(|b| b.get("options"))
+            .map(|s| s.to_string())
+            .unwrap_or_default();
+        let mut kargs = options
+            .split_whitespace()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        apply_local_kargs(&mut kargs, &change);
+        let new_options = kargs.join(" ");
+        if new_options == options {
+            continue;
+        }
+        tracing::debug!("Updating kargs of {}: {new_options}", deployment.csum());
+        sysroot.deployment_set_kargs_in_place(&deployment, Some(&new_options), cancellable)?;
+    }
+    Ok(())
+}
+
+/// Compute the changes to the kernel command line between two local kargs
+/// specifications, returning the added and removed kernel arguments.
+fn diff_local_kargs(old: &KernelArgsSpec, new: &KernelArgsSpec) -> (Vec<String>, Vec<String>) {
+    let added = new
+        .add
+        .iter()
+        .filter(|k| !old.add.contains(k))
+        .cloned()
+        .collect();
+    let removed = old
+        .add
+        .iter()
+        .filter(|k| !new.add.contains(k))
+        .chain(new.remove.iter().filter(|k| !old.remove.contains(k)))
+        .cloned()
+        .collect();
+    (added, removed)
+}
+
+/// Persist a new local kargs specification in the booted root, and apply
+/// the changes to the booted and staged deployments.
+#[context("Reconciling local kargs")]
+pub(crate) fn reconcile_local_kargs(
+    sysroot: &Storage,
+    root: &Dir,
+    old: &KernelArgsSpec,
+    new: &KernelArgsSpec,
+) -> Result<()> {
+    let (added, removed) = diff_local_kargs(old, new);
+    write_local_kargs(root, new)?;
+    apply_to_deployments(sysroot, &added, &removed)?;
+    for karg in added {
+        println!("Added kernel argument: {karg}");
+    }
+    for karg in removed {
+        println!("Removed kernel argument: {karg}");
+    }
+    if let Some(karg) = old.remove.iter().find(|k| !new.remove.contains(k)) {
+        println!("Note: {karg} is no longer removed, but is not restored to existing deployments");
+    }
+    println!("Kernel arguments will take effect on the next boot.");
+    Ok(())
+}
+
+/// Implementation of the `bootc kargs` CLI command.
+#[context("Managing local kargs")]
+pub(crate) async fn local_kargs(opts: KargsOpts) -> Result<()> {
+    let sysroot = &crate::cli::get_storage().await?;
+    let booted = sysroot.require_booted_deployment()?;
+    let root = &crate::utils::deployment_fd(sysroot, &booted)?;
+    let current = read_local_kargs(root)?;
+    let add = match opts {
+        KargsOpts::List => {
+            for karg in get_local_kargs_in_root(root, std::env::consts::ARCH)?.add {
+                println!("{karg}");
+            }
+            return Ok(());
+        }
+        KargsOpts::Add { kargs } => {
+            let mut new = current.add.clone();
+            merge_kargs(&mut new, &kargs);
+            new
+        }
+        KargsOpts::Remove { kargs } => {
+            if let Some(missing) = kargs.iter().find(|k| !current.add.contains(k)) {
+                anyhow::bail!("Not a local kernel argument: {missing}");
+            }
+            current
+                .add
+                .iter()
+                .filter(|k| !kargs.contains(k))
+                .cloned()
+                .collect()
+        }
+        KargsOpts::Edit => {
+            let tmpf = tempfile::NamedTempFile::new()?;
+            std::fs::write(tmpf.path(), current.add.join("\n") + "\n")?;
+            crate::utils::spawn_editor(&tmpf)?;
+            std::fs::read_to_string(tmpf.path())?
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+    };
+    if add == current.add {
+        println!("No changes.");
+        return Ok(());
+    }
+    let new = KernelArgsSpec {
+        add,
+        remove: current.remove.clone(),
+    };
+    reconcile_local_kargs(sysroot, root, &current, &new)?;
+    sysroot.update_mtime()?;
+    Ok(())
+}
+
+/// This parses and validates a bootc kargs.d toml file; returns `None` if
+/// the file does not apply to `sys_arch`.
+fn parse_config(contents: &str, sys_arch: &str) -> Result<Option<Config>> {
     let de: Config = toml::from_str(contents)?;
+    de.validate()?;
     // if arch specified, apply kargs only if the arch matches
     // if arch not specified, apply kargs unconditionally
     let matched = de
         .match_architectures
+        .as_ref()
         .map(|arches| arches.iter().any(|s| s == sys_arch))
         .unwrap_or(true);
-    let r = if matched { de.kargs } else { Vec::new() };
+    Ok(matched.then_some(de))
+}
+
+/// This parses a bootc kargs.d toml file, returning the resulting
+/// vector of kernel arguments. Architecture matching is performed using
+/// `sys_arch`.
+fn parse_kargs_toml(contents: &str, sys_arch: &str) -> Result<Vec<String>> {
+    let mut r = Vec::new();
+    if let Some(config) = parse_config(contents, sys_arch)? {
+        config.apply(&mut r);
+    }
     Ok(r)
 }
 
@@ -244,6 +636,90 @@ match-architectures = ["x86_64", "aarch64"]
         assert!(parse_kargs_toml(test_missing, "x86_64").is_err());
     }
 
+    #[test]
+    fn test_delete_replace() -> Result<()> {
+        let td = cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        td.create_dir_all(KARGS_PATH)?;
+        td.write(
+            format!("{KARGS_PATH}/00-base.toml"),
+            r##"kargs = ["rhgb", "quiet", "console=tty0", "console=ttyS0", "mitigations=auto"]"##,
+        )?;
+        td.write(
+            format!("{KARGS_PATH}/10-derived.toml"),
+            indoc::indoc! { r#"
+                kargs = ["nosmt"]
+                delete = ["quiet", "rhgb=1", "mitigations=auto"]
+                replace = ["console=ttyS1,115200"]
+            "# },
+        )?;
+        // Files not matching the architecture are skipped, including their deletions
+        td.write(
+            format!("{KARGS_PATH}/20-other-arch.toml"),
+            indoc::indoc! { r#"
+                delete = ["rhgb"]
+                match-architectures = ["s390x"]
+            "# },
+        )?;
+        similar_asserts::assert_eq!(
+            get_kargs_in_root(&td, "x86_64")?,
+            ["rhgb", "console=ttyS1,115200", "nosmt"]
+        );
+        assert_eq!(find_invalid_kargs_in_root(&td)?.len(), 0);
+
+        // Replacing a key which isn't present appends it
+        let parsed = parse_kargs_toml(r#"replace = ["console=ttyS0"]"#, "x86_64")?;
+        assert_eq!(parsed, ["console=ttyS0"]);
+
+        td.write(
+            format!("{KARGS_PATH}/30-invalid.toml"),
+            indoc::indoc! { r#"
+                kargs = ["quiet"]
+                delete = ["quiet"]
+            "# },
+        )?;
+        similar_asserts::assert_eq!(
+            find_invalid_kargs_in_root(&td)?,
+            ["30-invalid.toml: Kernel argument quiet is both added and deleted"]
+        );
+        assert!(get_kargs_in_root(&td, "x86_64").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        for (contents, err) in [
+            (
+                r#"replace = ["console"]"#,
+                "Invalid replace entry console: expected key=value",
+            ),
+            (
+                r#"replace = ["console=tty0", "console=ttyS0"]"#,
+                "Multiple replace entries for console",
+            ),
+            (
+                "delete = [\"console\"]\nreplace = [\"console=tty0\"]",
+                "Kernel argument console is both deleted and replaced",
+            ),
+            (
+                "kargs = [\"console=tty0\"]\ndelete = [\"console\"]",
+                "Kernel argument console=tty0 is both added and deleted",
+            ),
+            (
+                "kargs = [\"console=tty0\"]\nreplace = [\"console=ttyS0\"]",
+                "Kernel argument console=tty0 is both added and replaced",
+            ),
+        ] {
+            let e = parse_kargs_toml(contents, "x86_64").unwrap_err();
+            assert_eq!(e.to_string(), err);
+        }
+        // Removing a karg not present, or a different value of a karg, is fine
+        parse_kargs_toml(
+            "kargs = [\"console=tty0\"]\ndelete = [\"console=ttyS0\"]",
+            "x86_64",
+        )
+        .unwrap();
+    }
+
     #[context("writing test kargs")]
     fn write_test_kargs(td: &Dir) -> Result<()> {
         td.write(
@@ -279,6 +755,71 @@ match-architectures = ["x86_64", "aarch64"]
         Ok(())
     }
 
+    #[test]
+    fn test_merge_kargs() {
+        let mut kargs = vec!["root=UUID=abc".to_string(), "quiet".to_string()];
+        let added = merge_kargs(&mut kargs, &["quiet".into(), "console=ttyS0".into()]);
+        assert_eq!(added, ["console=ttyS0"]);
+        assert_eq!(kargs, ["root=UUID=abc", "quiet", "console=ttyS0"]);
+        assert_eq!(merge_kargs(&mut kargs, &[]).len(), 0);
+    }
+
+    #[test]
+    fn test_local_kargs() -> Result<()> {
+        let td = cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        assert!(read_local_kargs(&td)?.is_empty());
+        assert!(get_local_kargs_in_root(&td, "x86_64")?.is_empty());
+
+        let spec = KernelArgsSpec {
+            add: vec!["console=ttyS0,115200".into(), "debug".into()],
+            remove: vec!["quiet".into()],
+        };
+        write_local_kargs(&td, &spec)?;
+        assert_eq!(read_local_kargs(&td)?, spec);
+
+        // Additional admin-provided files are honored, but are not managed by us
+        td.write("etc/bootc/kargs.d/10-admin.toml", r##"kargs = ["nosmt"]"##)?;
+        assert_eq!(read_local_kargs(&td)?, spec);
+        let local = get_local_kargs_in_root(&td, "x86_64")?;
+        similar_asserts::assert_eq!(local.add, ["nosmt", "console=ttyS0,115200", "debug"]);
+        similar_asserts::assert_eq!(local.remove, ["quiet"]);
+        // Image kargs are separate
+        assert_eq!(get_kargs_in_root(&td, "x86_64")?.len(), 0);
+
+        // Local removals apply to the whole command line
+        let mut kargs = vec!["root=UUID=abc".to_string(), "quiet".into(), "nosmt".into()];
+        let added = apply_local_kargs(&mut kargs, &local);
+        assert_eq!(added, ["console=ttyS0,115200", "debug"]);
+        assert_eq!(
+            kargs,
+            ["root=UUID=abc", "nosmt", "console=ttyS0,115200", "debug"]
+        );
+
+        let invalid = KernelArgsSpec {
+            add: vec![" ".into()],
+            remove: Vec::new(),
+        };
+        assert!(write_local_kargs(&td, &invalid).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_local_kargs() {
+        let old = KernelArgsSpec {
+            add: vec!["debug".into(), "console=ttyS0".into()],
+            remove: vec!["quiet".into()],
+        };
+        let new = KernelArgsSpec {
+            add: vec!["console=ttyS0".into(), "nosmt".into()],
+            remove: vec!["quiet".into(), "rhgb".into()],
+        };
+        let (added, removed) = diff_local_kargs(&old, &new);
+        assert_eq!(added, ["nosmt"]);
+        assert_eq!(removed, ["debug", "rhgb"]);
+        let (added, removed) = diff_local_kargs(&new, &new);
+        assert!(added.is_empty() && removed.is_empty());
+    }
+
     #[context("ostree commit")]
     fn ostree_commit(
         repo: &ostree::Repo,
@@ -342,7 +883,7 @@ match-architectures = ["x86_64", "aarch64"]
             if !fetched_tree.query_exists(cancellable) {
                 return Ok(Default::default());
             }
-            get_kargs_from_ostree(repo, &fetched_tree, sys_arch)
+            get_kargs_from_ostree(repo, &fetched_tree, sys_arch).map(|r| r.kargs)
         };
 
         // rootfs is empty
@@ -357,4 +898,50 @@ match-architectures = ["x86_64", "aarch64"]
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_kargs_for_commit() -> Result<()> {
+        let td = cap_std_ext::cap_tempfile::TempDir::new(cap_std::ambient_authority())?;
+        td.create_dir("repo")?;
+        let repo = &ostree::Repo::create_at(
+            td.as_fd().as_raw_fd(),
+            "repo",
+            ostree::RepoMode::Bare,
+            None,
+            gio::Cancellable::NONE,
+        )?;
+        td.create_dir_all("rootfs/usr/lib/bootc/kargs.d")?;
+        let test_rootfs = &td.open_dir("rootfs")?;
+        test_rootfs.write(
+            "usr/lib/bootc/kargs.d/01-foo.toml",
+            indoc::indoc! { r#"
+                kargs = ["nosmt"]
+                delete = ["quiet"]
+                replace = ["console=ttyS1,115200"]
+            "# },
+        )?;
+        ostree_commit(repo, test_rootfs, ".".into(), "testref")?;
+
+        // The merge deployment has kargs set at install time, and one from its kargs.d
+        let kargs = [
+            "root=UUID=abc",
+            "quiet",
+            "console=ttyS0",
+            "rhgb",
+            "mitigations=off",
+        ]
+        .map(ToOwned::to_owned)
+        .to_vec();
+        let existing = vec!["mitigations=off".to_owned()];
+        let local = KernelArgsSpec {
+            add: vec!["debug".into()],
+            remove: vec!["rhgb".into()],
+        };
+        let kargs = get_kargs_for_commit(repo, "testref", kargs, existing, &local, "x86_64")?;
+        similar_asserts::assert_eq!(
+            kargs,
+            ["root=UUID=abc", "console=ttyS1,115200", "nosmt", "debug"]
+        );
+        Ok(())
+    }
 }
@@ -4,12 +4,14 @@
 //! to provide a fully "container native" tool for using
 //! bootable container images.
 
+mod assessment;
 mod boundimage;
 pub mod cli;
 pub(crate) mod deploy;
 pub(crate) mod fsck;
 pub(crate) mod generator;
 mod glyph;
+mod history;
 mod image;
 mod imgstorage;
 pub(crate) mod journal;
@@ -20,13 +22,16 @@ mod lsm;
 pub(crate) mod metadata;
 mod podman;
 mod progress_jsonl;
+mod progress_render;
 mod reboot;
 mod reexec;
+mod sigstore;
 pub mod spec;
 mod status;
 mod store;
 mod task;
 mod utils;
+mod varlink;
 
 #[cfg(feature = "docgen")]
 mod docgen;
@@ -461,10 +461,17 @@ fn check_usretc(root: &Dir, _config: &LintExecutionConfig) -> LintResult {
 #[distributed_slice(LINTS)]
 static LINT_KARGS: Lint = Lint::new_fatal(
     "bootc-kargs",
-    "Verify syntax of /usr/lib/bootc/kargs.d.",
+    indoc! { r#"
+Verify syntax of /usr/lib/bootc/kargs.d, and that no file contains invalid
+combinations of `kargs`, `delete` and `replace`.
+"# },
     check_parse_kargs,
 );
 fn check_parse_kargs(root: &Dir, _config: &LintExecutionConfig) -> LintResult {
+    let invalid = crate::kargs::find_invalid_kargs_in_root(root)?;
+    if !invalid.is_empty() {
+        return lint_err(format!("Invalid kargs.d: {}", invalid.join("; ")));
+    }
     let args = crate::kargs::get_kargs_in_root(root, ARCH)?;
     tracing::debug!("found kargs: {args:?}");
     lint_ok()
@@ -772,6 +779,34 @@ fn check_boot(root: &Dir, config: &LintExecutionConfig) -> LintResult {
     format_lint_err_from_items(config, header, items)
 }
 
+#[distributed_slice(LINTS)]
+static LINT_BOUND_IMAGE_DIGEST: Lint = Lint::new_warning(
+    "bound-image-digest",
+    indoc! { r#"
+Check that logically bound images in /usr/lib/bootc/bound-images.d are pinned
+by digest, e.g. `Image=quay.io/example/app@sha256:...`. Otherwise, the same
+host image may pull different bound image content over time.
+"#},
+    check_bound_image_digest,
+);
+fn check_bound_image_digest(root: &Dir, config: &LintExecutionConfig) -> LintResult {
+    let images = match crate::boundimage::query_bound_images(root) {
+        Ok(images) => images,
+        Err(e) => return lint_err(format!("{e:#}")),
+    };
+    let mut unpinned = images
+        .iter()
+        .filter(|i| i.digest.is_none())
+        .map(|i| i.image.as_str())
+        .collect::<Vec<_>>();
+    if unpinned.is_empty() {
+        return lint_ok();
+    }
+    unpinned.sort();
+    let header = "Found bound images not pinned by digest";
+    format_lint_err_from_items(config, header, unpinned.into_iter())
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::LazyLock;
@@ -910,7 +945,13 @@ mod tests {
         let root = &fixture()?;
         let config = &LintExecutionConfig::default();
         check_parse_kargs(root, config).unwrap().unwrap();
-        root.create_dir_all("usr/lib/bootc")?;
+        root.create_dir_all("usr/lib/bootc/kargs.d")?;
+        root.write(
+            "usr/lib/bootc/kargs.d/10-test.toml",
+            "kargs = [\"console=tty0\"]\nreplace = [\"console=ttyS0\"]",
+        )?;
+        assert!(check_parse_kargs(root, config).unwrap().is_err());
+        root.remove_dir_all("usr/lib/bootc/kargs.d")?;
         root.write("usr/lib/bootc/kargs.d", "not a directory")?;
         assert!(check_parse_kargs(root, config).is_err());
         Ok(())
@@ -982,6 +1023,41 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_bound_image_digest() -> Result<()> {
+        let root = &passing_fixture()?;
+        let config = &LintExecutionConfig::default();
+        check_bound_image_digest(root, config).unwrap().unwrap();
+
+        root.create_dir_all("usr/lib/bootc/bound-images.d")?;
+        root.create_dir_all("usr/share/containers/systemd")?;
+        let add = |name: &str, image: &str| -> Result<()> {
+            root.write(
+                format!("usr/share/containers/systemd/{name}"),
+                format!("[Image]\nImage={image}\n"),
+            )?;
+            root.symlink_contents(
+                format!("/usr/share/containers/systemd/{name}"),
+                format!("usr/lib/bootc/bound-images.d/{name}"),
+            )?;
+            Ok(())
+        };
+        add(
+            "pinned.image",
+            "quay.io/example/pinned@sha256:b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c",
+        )?;
+        check_bound_image_digest(root, config).unwrap().unwrap();
+        add("floating.image", "quay.io/example/floating:latest")?;
+        let Err(e) = check_bound_image_digest(root, config).unwrap() else {
+            unreachable!()
+        };
+        let e = e.to_string();
+        assert!(e.contains("quay.io/example/floating:latest"));
+        assert!(!e.contains("pinned"));
+
+        Ok(())
+    }
+
     fn run_recursive_lint(
         root: &Dir,
         f: LintRecursiveFn,
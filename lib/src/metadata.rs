@@ -2,3 +2,8 @@
 pub(crate) const BOOTC_COMPAT_LABEL: &str = "containers.bootc";
 /// The current single well-known value for the label.
 pub(crate) const COMPAT_LABEL_V1: &str = "1";
+/// Images may set this label to the minimum version of bootc required to deploy them.
+pub(crate) const BOOTC_MIN_VERSION_LABEL: &str = "containers.bootc.minimum-version";
+/// Images may set this label to a comma-separated list of host features
+/// which are required to deploy them; see [`crate::deploy::host_features`].
+pub(crate) const BOOTC_REQUIRED_FEATURES_LABEL: &str = "containers.bootc.required-features";
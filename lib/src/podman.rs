@@ -11,6 +11,9 @@ pub(crate) const CONTAINER_STORAGE: &str = "/var/lib/containers";
 #[serde(rename_all = "PascalCase")]
 pub(crate) struct Inspect {
     pub(crate) digest: String,
+    /// The `name@digest` references of the image; if it was pulled via a
+    /// manifest list (index), this includes the digest of the index.
+    pub(crate) repo_digests: Option<Vec<String>>,
 }
 
 /// This is output from `podman image list --format=json`.
@@ -19,19 +22,24 @@ pub(crate) struct Inspect {
 pub(crate) struct ImageListEntry {
     pub(crate) id: String,
     pub(crate) names: Option<Vec<String>>,
+    /// The `name@digest` references of the image
+    pub(crate) repo_digests: Option<Vec<String>>,
 }
 
-/// Given an image ID, return its manifest digest
-pub(crate) fn imageid_to_digest(imgid: &str) -> Result<String> {
+/// Inspect an image in the host container storage
+pub(crate) fn inspect(image: &str) -> Result<Inspect> {
     use bootc_utils::CommandRunExt;
     let o: Vec<Inspect> = crate::install::run_in_host_mountns("podman")
-        .args(["inspect", imgid])
+        .args(["inspect", image])
         .run_and_parse_json()?;
-    let i = o
-        .into_iter()
+    o.into_iter()
         .next()
-        .ok_or_else(|| anyhow::anyhow!("No images returned for inspect"))?;
-    Ok(i.digest)
+        .ok_or_else(|| anyhow::anyhow!("No images returned for inspect"))
+}
+
+/// Given an image ID, return its manifest digest
+pub(crate) fn imageid_to_digest(imgid: &str) -> Result<String> {
+    inspect(imgid).map(|i| i.digest)
 }
 
 /// Return true if there is apparently an active container store at the target path.
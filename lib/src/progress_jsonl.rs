@@ -5,19 +5,23 @@ use anyhow::Result;
 use schemars::JsonSchema;
 use serde::Serialize;
 use std::borrow::Cow;
-use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::net::unix::pipe::Sender;
 use tokio::sync::Mutex;
 
 // Maximum number of times per second that an event will be written.
 const REFRESH_HZ: u16 = 5;
+/// The time constant for smoothing the transfer rate.
+const RATE_SMOOTHING: Duration = Duration::from_secs(3);
+/// The minimum interval between samples of the transfer rate.
+const RATE_MIN_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Semantic version of the protocol.
-const API_VERSION: &str = "0.1.0";
+pub(crate) const API_VERSION: &str = "0.4.0";
 
 /// An incremental update to e.g. a container image layer download.
 /// The first time a given "subtask" name is seen, a new progress bar should be created.
@@ -71,18 +75,23 @@ pub struct SubTaskStep<'t> {
 
 /// An event emitted as JSON.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema, PartialEq, Eq)]
-#[serde(
-    tag = "type",
-    rename_all = "PascalCase",
-    rename_all_fields = "camelCase"
-)]
+// Note that `rename_all_fields` is not used here, as it is not understood by
+// schemars; the variants are renamed individually so that the generated
+// schema matches the serialized form.
+#[serde(tag = "type", rename_all = "PascalCase")]
 pub enum Event<'t> {
+    #[serde(rename_all = "camelCase")]
     Start {
         /// The semantic version of the progress protocol.
         #[serde(borrow)]
         version: Cow<'t, str>,
+        /// The time of the event in milliseconds, from the system monotonic clock
+        /// (`CLOCK_MONOTONIC`).
+        #[serde(default)]
+        timestamp_ms: u64,
     },
     /// An incremental update to a container image layer download
+    #[serde(rename_all = "camelCase")]
     ProgressBytes {
         /// A machine readable type (e.g., pulling) for the task (used for i18n
         /// and UI customization).
@@ -110,8 +119,19 @@ pub enum Event<'t> {
         steps_total: u64,
         /// The currently running subtasks.
         subtasks: Vec<SubTaskBytes<'t>>,
+        /// The time of the event in milliseconds, from the system monotonic clock
+        /// (`CLOCK_MONOTONIC`).
+        #[serde(default)]
+        timestamp_ms: u64,
+        /// The smoothed transfer rate in bytes per second; zero if not yet known.
+        #[serde(default)]
+        bytes_per_second: u64,
+        /// The estimated number of seconds until all bytes are fetched, if known.
+        #[serde(default)]
+        eta_seconds: Option<u64>,
     },
     /// An incremental update with discrete steps
+    #[serde(rename_all = "camelCase")]
     ProgressSteps {
         /// A machine readable type (e.g., pulling) for the task (used for i18n
         /// and UI customization).
@@ -134,6 +154,45 @@ pub enum Event<'t> {
         /// The currently running subtasks.
         subtasks: Vec<SubTaskStep<'t>>,
     },
+    /// An operation failed; this is the last event of the stream.
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        /// A machine readable type (e.g., upgrade) for the operation which failed.
+        #[serde(borrow)]
+        task: Cow<'t, str>,
+        /// The error, followed by the errors which caused it (if any).
+        errors: Vec<Cow<'t, str>>,
+    },
+    /// An operation completed successfully.
+    #[serde(rename_all = "camelCase")]
+    Summary {
+        /// A machine readable type (e.g., staging) for the operation which completed.
+        #[serde(borrow)]
+        task: Cow<'t, str>,
+        /// The manifest digest of the image.
+        #[serde(borrow)]
+        digest: Cow<'t, str>,
+        /// The version of the image, if any.
+        #[serde(borrow)]
+        version: Option<Cow<'t, str>>,
+        /// The number of bytes fetched over the network.
+        bytes_transferred: u64,
+        /// The time taken, in milliseconds, since the stream started.
+        duration_ms: u64,
+    },
+    /// An update on fetching a logically bound image.
+    #[serde(rename_all = "camelCase")]
+    BoundImage {
+        /// The image being fetched.
+        #[serde(borrow)]
+        image: Cow<'t, str>,
+        /// The number of bound images processed, including this one once completed.
+        steps: u64,
+        /// The total number of bound images.
+        steps_total: u64,
+        /// False when beginning to fetch the image, and true when it is present.
+        completed: bool,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -152,11 +211,94 @@ impl FromStr for RawProgressFd {
     }
 }
 
+impl From<OwnedFd> for RawProgressFd {
+    fn from(value: OwnedFd) -> Self {
+        Self(value.into_raw_fd())
+    }
+}
+
+impl From<RawProgressFd> for OwnedFd {
+    #[allow(unsafe_code)]
+    fn from(value: RawProgressFd) -> Self {
+        // SAFETY: The fd was passed to us to take ownership of
+        unsafe { OwnedFd::from_raw_fd(value.0) }
+    }
+}
+
+/// The current time in milliseconds, from the system monotonic clock.
+fn monotonic_ms() -> u64 {
+    let ts = rustix::time::clock_gettime(rustix::time::ClockId::Monotonic);
+    let ms = i128::from(ts.tv_sec) * 1000 + i128::from(ts.tv_nsec) / 1_000_000;
+    ms.try_into().unwrap_or_default()
+}
+
+/// Estimates the transfer rate of a task, smoothed via an exponentially
+/// weighted moving average.
+#[derive(Debug)]
+struct RateEstimator {
+    /// The task and identifier being tracked
+    key: (String, String),
+    /// The bytes fetched as of the last sample
+    bytes: u64,
+    /// The time of the last sample
+    time: Instant,
+    /// The smoothed rate in bytes per second, once known
+    rate: Option<f64>,
+}
+
+impl RateEstimator {
+    fn new(key: (String, String), bytes: u64, time: Instant) -> Self {
+        Self {
+            key,
+            bytes,
+            time,
+            rate: None,
+        }
+    }
+
+    /// Add a sample, returning the smoothed rate in bytes per second (if known).
+    fn update(&mut self, bytes: u64, now: Instant) -> Option<f64> {
+        let elapsed = now.saturating_duration_since(self.time);
+        if elapsed >= RATE_MIN_INTERVAL {
+            let elapsed = elapsed.as_secs_f64();
+            let current = bytes.saturating_sub(self.bytes) as f64 / elapsed;
+            // Weight the new sample by the time it covers
+            let alpha = 1.0 - (-elapsed / RATE_SMOOTHING.as_secs_f64()).exp();
+            let rate = self
+                .rate
+                .map_or(current, |rate| rate + alpha * (current - rate));
+            self.rate = Some(rate);
+            self.bytes = bytes;
+            self.time = now;
+        }
+        self.rate
+    }
+}
+
+/// Estimate the number of seconds until `bytes_total` is reached.
+fn estimate_remaining(bytes: u64, bytes_total: u64, rate: Option<f64>) -> Option<u64> {
+    if bytes_total == 0 {
+        return None;
+    }
+    let remaining = bytes_total.saturating_sub(bytes);
+    if remaining == 0 {
+        return Some(0);
+    }
+    let rate = rate.filter(|&r| r > 0.0)?;
+    Some((remaining as f64 / rate).ceil() as u64)
+}
+
 #[derive(Debug)]
 struct ProgressWriterInner {
     /// true if we sent the initial Start message
     sent_start: bool,
     last_write: Option<std::time::Instant>,
+    /// When the writer was created
+    start: Instant,
+    /// The number of bytes fetched over the network, for the summary
+    bytes_transferred: u64,
+    /// The transfer rate of the last byte-level task
+    rate: Option<RateEstimator>,
     fd: BufWriter<Sender>,
 }
 
@@ -179,6 +321,9 @@ impl From<Sender> for ProgressWriter {
         let inner = ProgressWriterInner {
             sent_start: false,
             last_write: None,
+            start: Instant::now(),
+            bytes_transferred: 0,
+            rate: None,
             fd: BufWriter::new(value),
         };
         Self {
@@ -190,13 +335,44 @@ impl From<Sender> for ProgressWriter {
 impl TryFrom<RawProgressFd> for ProgressWriter {
     type Error = anyhow::Error;
 
-    #[allow(unsafe_code)]
     fn try_from(fd: RawProgressFd) -> Result<Self> {
-        unsafe { OwnedFd::from_raw_fd(fd.0) }.try_into()
+        OwnedFd::from(fd).try_into()
     }
 }
 
 impl ProgressWriter {
+    /// Fill in the timestamp, and for byte-level progress the smoothed
+    /// transfer rate and estimated time remaining.
+    fn annotate(inner: &mut ProgressWriterInner, event: &mut Event<'_>, now: Instant) {
+        match event {
+            Event::Start { timestamp_ms, .. } => *timestamp_ms = monotonic_ms(),
+            Event::ProgressBytes {
+                task,
+                id,
+                bytes,
+                bytes_total,
+                timestamp_ms,
+                bytes_per_second,
+                eta_seconds,
+                ..
+            } => {
+                *timestamp_ms = monotonic_ms();
+                let key = (task.to_string(), id.to_string());
+                let rate = match inner.rate.as_mut() {
+                    Some(rate) if rate.key == key => rate.update(*bytes, now),
+                    _ => {
+                        // A new task; start estimating afresh
+                        inner.rate = Some(RateEstimator::new(key, *bytes, now));
+                        None
+                    }
+                };
+                *bytes_per_second = rate.map(|r| r as u64).unwrap_or_default();
+                *eta_seconds = estimate_remaining(*bytes, *bytes_total, rate);
+            }
+            _ => {}
+        }
+    }
+
     /// Serialize the target value as a single line of JSON and write it.
     async fn send_impl_inner<T: Serialize>(inner: &mut ProgressWriterInner, v: T) -> Result<()> {
         // serde is guaranteed not to output newlines here
@@ -209,8 +385,8 @@ impl ProgressWriter {
         Ok(())
     }
 
-    /// Serialize the target object to JSON as a single line
-    pub(crate) async fn send_impl<T: Serialize>(&self, v: T, required: bool) -> Result<()> {
+    /// Serialize the target event to JSON as a single line
+    pub(crate) async fn send_impl(&self, mut event: Event<'_>, required: bool) -> Result<()> {
         let mut guard = self.inner.lock().await;
         // Check if we have an inner value; if not, nothing to do.
         let Some(inner) = guard.as_mut() else {
@@ -220,15 +396,20 @@ impl ProgressWriter {
         // If this is our first message, emit the Start message
         if !inner.sent_start {
             inner.sent_start = true;
-            let start = Event::Start {
+            let mut start = Event::Start {
                 version: API_VERSION.into(),
+                timestamp_ms: 0,
             };
+            Self::annotate(inner, &mut start, Instant::now());
             Self::send_impl_inner(inner, &start).await?;
         }
 
+        // Always update the rate estimate, even if the event is dropped below
+        let now = Instant::now();
+        Self::annotate(inner, &mut event, now);
+
         // For messages that can be dropped, if we already sent an update within this cycle, discard this one.
         // TODO: Also consider querying the pipe buffer and also dropping if we can't do this write.
-        let now = Instant::now();
         if !required {
             const REFRESH_MS: u32 = 1000 / REFRESH_HZ as u32;
             if let Some(elapsed) = inner.last_write.map(|w| now.duration_since(w)) {
@@ -238,7 +419,7 @@ impl ProgressWriter {
             }
         }
 
-        Self::send_impl_inner(inner, &v).await?;
+        Self::send_impl_inner(inner, &event).await?;
         // Update the last write time
         inner.last_write = Some(now);
         Ok(())
@@ -264,6 +445,43 @@ impl ProgressWriter {
         }
     }
 
+    /// Record bytes fetched over the network, to be included in the summary.
+    pub(crate) async fn add_bytes_transferred(&self, bytes: u64) {
+        if let Some(inner) = self.inner.lock().await.as_mut() {
+            inner.bytes_transferred = inner.bytes_transferred.saturating_add(bytes);
+        }
+    }
+
+    /// Send the summary of a successfully completed operation.
+    pub(crate) async fn send_summary(&self, task: &str, digest: &str, version: Option<&str>) {
+        let Some((bytes_transferred, elapsed)) = self
+            .inner
+            .lock()
+            .await
+            .as_ref()
+            .map(|inner| (inner.bytes_transferred, inner.start.elapsed()))
+        else {
+            return;
+        };
+        self.send(Event::Summary {
+            task: task.into(),
+            digest: digest.into(),
+            version: version.map(Into::into),
+            bytes_transferred,
+            duration_ms: elapsed.as_millis().try_into().unwrap_or(u64::MAX),
+        })
+        .await
+    }
+
+    /// Send the failure of an operation, including the chain of errors.
+    pub(crate) async fn send_failure(&self, task: &str, e: &anyhow::Error) {
+        self.send(Event::Failed {
+            task: task.into(),
+            errors: e.chain().map(|e| e.to_string().into()).collect(),
+        })
+        .await
+    }
+
     /// Flush remaining data and return the underlying file.
     #[allow(dead_code)]
     pub(crate) async fn into_inner(self) -> Result<Option<Sender>> {
@@ -313,6 +531,9 @@ mod test {
                 steps: 0,
                 steps_total: 3,
                 subtasks: Vec::new(),
+                timestamp_ms: 0,
+                bytes_per_second: 0,
+                eta_seconds: None,
             },
         ];
         let (send, recv) = tokio::net::unix::pipe::pipe()?;
@@ -331,11 +552,21 @@ mod test {
             let mut lines = tf.lines();
             let mut got_first = false;
             while let Some(line) = lines.next_line().await? {
-                let found: Event = serde_json::from_str(&line)?;
+                let mut found: Event = serde_json::from_str(&line)?;
+                // Timestamps are filled in by the writer
+                match &mut found {
+                    Event::Start { timestamp_ms, .. }
+                    | Event::ProgressBytes { timestamp_ms, .. } => {
+                        assert_ne!(*timestamp_ms, 0);
+                        *timestamp_ms = 0;
+                    }
+                    _ => {}
+                }
                 let expected_value = if !got_first {
                     got_first = true;
                     &Event::Start {
                         version: API_VERSION.into(),
+                        timestamp_ms: 0,
                     }
                 } else {
                     expected.next().unwrap()
@@ -347,4 +578,73 @@ mod test {
         tokio::try_join!(sender, receiver)?;
         Ok(())
     }
+
+    #[test]
+    fn test_rate() {
+        let start = Instant::now();
+        let key = || ("pulling".to_string(), "someid".to_string());
+        let mut rate = RateEstimator::new(key(), 0, start);
+        // Samples too close together are ignored
+        assert_eq!(rate.update(100, start + Duration::from_millis(10)), None);
+        let r = rate.update(1000, start + Duration::from_secs(1)).unwrap();
+        assert_eq!(r, 1000.0);
+        // A sudden stall only gradually reduces the rate
+        let r = rate.update(1000, start + Duration::from_secs(2)).unwrap();
+        assert!(r > 500.0 && r < 1000.0, "{r}");
+
+        assert_eq!(estimate_remaining(0, 0, Some(100.0)), None);
+        assert_eq!(estimate_remaining(0, 1000, None), None);
+        assert_eq!(estimate_remaining(0, 1000, Some(0.0)), None);
+        assert_eq!(estimate_remaining(1000, 1000, None), Some(0));
+        assert_eq!(estimate_remaining(100, 1000, Some(200.0)), Some(5));
+        assert_eq!(estimate_remaining(100, 1000, Some(400.0)), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_final_events() -> Result<()> {
+        let (send, recv) = tokio::net::unix::pipe::pipe()?;
+        let sender = async move {
+            let w = ProgressWriter::try_from(send)?;
+            w.add_bytes_transferred(42).await;
+            w.send_summary("staging", "sha256:abc", Some("1.0")).await;
+            let e = anyhow::anyhow!("connection refused").context("Pulling");
+            w.send_failure("upgrade", &e).await;
+            anyhow::Ok(())
+        };
+        let receiver = async move {
+            let mut lines = BufReader::new(recv).lines();
+            let mut r = Vec::new();
+            while let Some(line) = lines.next_line().await? {
+                r.push(line);
+            }
+            anyhow::Ok(r)
+        };
+        let ((), lines) = tokio::try_join!(sender, receiver)?;
+        let events = lines
+            .iter()
+            .map(|l| serde_json::from_str(l))
+            .collect::<serde_json::Result<Vec<Event>>>()?;
+        assert_eq!(events.len(), 3);
+        match &events[1] {
+            Event::Summary {
+                digest,
+                version,
+                bytes_transferred,
+                ..
+            } => {
+                assert_eq!(digest, "sha256:abc");
+                assert_eq!(version.as_deref(), Some("1.0"));
+                assert_eq!(*bytes_transferred, 42);
+            }
+            o => panic!("Unexpected event {o:?}"),
+        }
+        assert_eq!(
+            events[2],
+            Event::Failed {
+                task: "upgrade".into(),
+                errors: vec!["Pulling".into(), "connection refused".into()],
+            }
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,677 @@
+//! Rendering of progress streams written via `--progress-fd`.
+//!
+//! Every line of the stream is validated against the `progress` JSON schema
+//! (as generated from [`Event`] into `docs/src/progress-v0.schema.json`), and
+//! then displayed either as progress bars, or as a compact log if standard
+//! output is not a terminal.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, IsTerminal, Write};
+use std::os::fd::OwnedFd;
+
+use anyhow::{Context, Result};
+use fn_error_context::context;
+use indicatif::{
+    HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle,
+};
+
+use serde_json::Value;
+
+use crate::cli::ProgressRenderOpts;
+use crate::progress_jsonl::{Event, API_VERSION};
+
+/// Parse a semantic version into its numeric components.
+fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = v.splitn(3, '.').map(|p| p.parse::<u64>().ok());
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// Check that we understand the protocol version of a stream; it must
+/// not be newer than our own version.
+fn check_version(version: &str) -> Result<()> {
+    let ours = parse_version(API_VERSION).expect("valid API version");
+    let Some(theirs) = parse_version(version) else {
+        anyhow::bail!("Invalid progress protocol version: {version}");
+    };
+    if theirs > ours {
+        anyhow::bail!("Unsupported progress protocol version {version} (supported: {API_VERSION})");
+    }
+    Ok(())
+}
+
+/// Resolve a local `$ref` (e.g. `#/definitions/SubTaskStep`) in a schema.
+fn resolve_ref<'a>(root: &'a Value, r: &str) -> Result<&'a Value> {
+    r.strip_prefix('#')
+        .and_then(|p| root.pointer(p))
+        .ok_or_else(|| anyhow::anyhow!("Unresolved schema reference: {r}"))
+}
+
+/// Check whether a value is of the given JSON schema type.
+fn is_schema_type(ty: &str, value: &Value) -> bool {
+    match ty {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => false,
+    }
+}
+
+/// Validate a value against a JSON schema.  This supports the subset of
+/// keywords which schemars generates for our types; others are ignored.
+fn validate_schema(root: &Value, schema: &Value, value: &Value, path: &str) -> Result<()> {
+    if let Some(r) = schema.get("$ref").and_then(Value::as_str) {
+        return validate_schema(root, resolve_ref(root, r)?, value, path);
+    }
+    match schema.get("type") {
+        Some(Value::String(ty)) if !is_schema_type(ty, value) => {
+            anyhow::bail!("{path}: expected {ty}")
+        }
+        Some(Value::Array(tys))
+            if !tys
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|ty| is_schema_type(ty, value)) =>
+        {
+            anyhow::bail!("{path}: unexpected type")
+        }
+        _ => {}
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        if !values.contains(value) {
+            anyhow::bail!("{path}: unexpected value {value}");
+        }
+    }
+    if let (Some(min), Some(n)) = (
+        schema.get("minimum").and_then(Value::as_f64),
+        value.as_f64(),
+    ) {
+        if n < min {
+            anyhow::bail!("{path}: {n} is less than {min}");
+        }
+    }
+    if let Some(obj) = value.as_object() {
+        for k in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !obj.contains_key(k) {
+                anyhow::bail!("{path}: missing property {k}");
+            }
+        }
+        if let Some(props) = schema.get("properties").and_then(Value::as_object) {
+            for (k, v) in obj {
+                if let Some(propschema) = props.get(k) {
+                    validate_schema(root, propschema, v, &format!("{path}.{k}"))?;
+                }
+            }
+        }
+    }
+    if let (Some(items), Some(arr)) = (schema.get("items"), value.as_array()) {
+        for (i, v) in arr.iter().enumerate() {
+            validate_schema(root, items, v, &format!("{path}[{i}]"))?;
+        }
+    }
+    if let Some(variants) = schema.get("anyOf").and_then(Value::as_array) {
+        if !variants
+            .iter()
+            .any(|s| validate_schema(root, s, value, path).is_ok())
+        {
+            anyhow::bail!("{path}: no matching schema");
+        }
+    }
+    if let Some(variants) = schema.get("oneOf").and_then(Value::as_array) {
+        let mut errs = Vec::new();
+        for s in variants {
+            if let Err(e) = validate_schema(root, s, value, path) {
+                errs.push(e);
+            }
+        }
+        match variants.len() - errs.len() {
+            1 => {}
+            0 => {
+                // Report the error for the variant which has the same tag, if any,
+                // as that is the most useful one.
+                let tag = value.get("type");
+                let tagged = variants
+                    .iter()
+                    .position(|s| tag.is_some() && s.pointer("/properties/type/enum/0") == tag);
+                match tagged {
+                    Some(i) => return Err(errs.swap_remove(i)),
+                    None => anyhow::bail!("{path}: no matching schema"),
+                }
+            }
+            _ => anyhow::bail!("{path}: multiple matching schemas"),
+        }
+    }
+    Ok(())
+}
+
+/// Validates the sequence of events in a stream.
+#[derive(Debug)]
+struct Validator {
+    /// The JSON schema for events
+    schema: Value,
+    /// The number of lines seen
+    lineno: u64,
+    /// True if we have seen the initial `Start` event
+    started: bool,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        let schema = schemars::schema_for!(Event);
+        Self {
+            schema: serde_json::to_value(schema).expect("serializing schema"),
+            lineno: 0,
+            started: false,
+        }
+    }
+}
+
+impl Validator {
+    /// Parse and validate a single line of the stream.
+    fn parse<'a>(&mut self, line: &'a str) -> Result<Event<'a>> {
+        self.lineno += 1;
+        let lineno = self.lineno;
+        let event = serde_json::from_str::<Value>(line)
+            .map_err(anyhow::Error::new)
+            .and_then(|v| validate_schema(&self.schema, &self.schema, &v, "event"))
+            .and_then(|()| serde_json::from_str::<Event>(line).map_err(Into::into))
+            .with_context(|| format!("Invalid progress event on line {lineno}"))?;
+        match (&event, self.started) {
+            (Event::Start { version, .. }, false) => {
+                check_version(version)?;
+                self.started = true;
+            }
+            (Event::Start { .. }, true) => {
+                anyhow::bail!("Unexpected Start event on line {lineno}")
+            }
+            (_, false) => anyhow::bail!("Expected Start event on line {lineno}"),
+            (_, true) => {}
+        }
+        Ok(event)
+    }
+}
+
+/// The fields common to all progress events.
+struct Progress<'a> {
+    task: &'a str,
+    description: &'a str,
+    id: &'a str,
+    steps: u64,
+    steps_total: u64,
+    /// The bytes fetched and the total, if this is a byte-level update.
+    bytes: Option<(u64, u64)>,
+    /// Descriptions of the completed subtasks (and their sizes, if known)
+    completed: Vec<(&'a str, Option<u64>)>,
+    /// The description of the currently running subtask, if any
+    current: Option<&'a str>,
+}
+
+impl<'a> Progress<'a> {
+    fn new(event: &'a Event<'a>) -> Option<Self> {
+        let r = match event {
+            Event::Start { .. } | Event::Failed { .. } | Event::Summary { .. } => return None,
+            Event::ProgressBytes {
+                task,
+                description,
+                id,
+                bytes,
+                bytes_total,
+                steps,
+                steps_total,
+                subtasks,
+                ..
+            } => Self {
+                task,
+                description,
+                id,
+                steps: *steps,
+                steps_total: *steps_total,
+                bytes: Some((*bytes, *bytes_total)),
+                completed: subtasks
+                    .iter()
+                    .filter(|s| s.bytes == s.bytes_total)
+                    .map(|s| (&*s.description, Some(s.bytes_total)))
+                    .collect(),
+                current: subtasks
+                    .iter()
+                    .rfind(|s| s.bytes != s.bytes_total)
+                    .map(|s| &*s.description),
+            },
+            Event::ProgressSteps {
+                task,
+                description,
+                id,
+                steps,
+                steps_total,
+                subtasks,
+                ..
+            } => Self {
+                task,
+                description,
+                id,
+                steps: *steps,
+                steps_total: *steps_total,
+                bytes: None,
+                completed: subtasks
+                    .iter()
+                    .filter(|s| s.completed)
+                    .map(|s| (&*s.description, None))
+                    .collect(),
+                current: subtasks
+                    .iter()
+                    .rfind(|s| !s.completed)
+                    .map(|s| &*s.description),
+            },
+            Event::BoundImage {
+                image,
+                steps,
+                steps_total,
+                completed,
+            } => Self {
+                task: "bound_images",
+                description: "Fetching bound images",
+                id: "bound_images",
+                steps: *steps,
+                steps_total: *steps_total,
+                bytes: None,
+                completed: completed
+                    .then_some(&**image)
+                    .map(|s| (s, None))
+                    .into_iter()
+                    .collect(),
+                current: (!completed).then_some(&**image),
+            },
+        };
+        Some(r)
+    }
+
+    /// A unique key for the task
+    fn key(&self) -> (String, String) {
+        (self.task.to_owned(), self.id.to_owned())
+    }
+
+    fn is_done(&self) -> bool {
+        self.steps >= self.steps_total
+    }
+}
+
+/// The message to display for events which conclude an operation.
+fn final_message(event: &Event) -> Option<String> {
+    match event {
+        Event::Failed { task, errors } => Some(format!("error: {task}: {}", errors.join(": "))),
+        Event::Summary {
+            task,
+            digest,
+            version,
+            bytes_transferred,
+            duration_ms,
+        } => {
+            let version = version
+                .as_deref()
+                .map(|v| format!(" (version {v})"))
+                .unwrap_or_default();
+            let duration = std::time::Duration::from_millis(*duration_ms);
+            Some(format!(
+                "{task}: {digest}{version}; fetched {} in {}",
+                HumanBytes(*bytes_transferred),
+                HumanDuration(duration)
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// State of a task rendered as a log
+#[derive(Debug, Default)]
+struct LogTask {
+    completed: HashSet<String>,
+    done: bool,
+}
+
+/// Renders progress as a compact log, with one line per notable change.
+#[derive(Debug, Default)]
+struct LogRenderer {
+    tasks: HashMap<(String, String), LogTask>,
+}
+
+impl LogRenderer {
+    /// Compute the log lines for an event.
+    fn lines(&mut self, event: &Event) -> Vec<String> {
+        let Some(p) = Progress::new(event) else {
+            return final_message(event).into_iter().collect();
+        };
+        let mut r = Vec::new();
+        let state = self.tasks.entry(p.key()).or_insert_with(|| {
+            r.push(p.description.to_owned());
+            Default::default()
+        });
+        for (subtask, size) in p.completed.iter() {
+            if state.completed.insert((*subtask).to_owned()) {
+                match size {
+                    Some(size) => r.push(format!("  {subtask} ({})", HumanBytes(*size))),
+                    None => r.push(format!("  {subtask}")),
+                }
+            }
+        }
+        if p.is_done() && !state.done {
+            state.done = true;
+            match p.bytes {
+                Some((bytes, _)) => r.push(format!(
+                    "{}: done ({} steps, {})",
+                    p.description,
+                    p.steps,
+                    HumanBytes(bytes)
+                )),
+                None => r.push(format!("{}: done ({} steps)", p.description, p.steps)),
+            }
+        }
+        r
+    }
+}
+
+/// The progress bars of a task
+#[derive(Debug)]
+struct TaskBars {
+    steps: ProgressBar,
+    bytes: Option<ProgressBar>,
+}
+
+/// Renders progress with a progress bar per task.
+#[derive(Debug)]
+struct BarRenderer {
+    bars: MultiProgress,
+    tasks: HashMap<(String, String), TaskBars>,
+}
+
+impl BarRenderer {
+    fn new() -> Self {
+        Self {
+            bars: MultiProgress::with_draw_target(ProgressDrawTarget::stdout()),
+            tasks: Default::default(),
+        }
+    }
+
+    fn update(&mut self, event: &Event) {
+        let Some(p) = Progress::new(event) else {
+            if let Some(msg) = final_message(event) {
+                if let Err(e) = self.bars.println(msg) {
+                    tracing::warn!("writing to stdout: {e}");
+                }
+            }
+            return;
+        };
+        let bars = &self.bars;
+        let task = self.tasks.entry(p.key()).or_insert_with(|| {
+            let steps = bars.add(ProgressBar::new(p.steps_total));
+            steps.set_style(
+                ProgressStyle::default_bar()
+                    .template("{prefix} {bar} {pos}/{len} {wide_msg}")
+                    .unwrap(),
+            );
+            steps.set_prefix(p.description.to_owned());
+            let bytes = p.bytes.map(|_| {
+                let bytes = bars.add(ProgressBar::new(0));
+                bytes.set_style(
+                    ProgressStyle::default_bar()
+                        .template(
+                            " └ {bar} {binary_bytes}/{binary_total_bytes} ({binary_bytes_per_sec})",
+                        )
+                        .unwrap(),
+                );
+                bytes
+            });
+            TaskBars { steps, bytes }
+        });
+        task.steps.set_length(p.steps_total);
+        task.steps.set_position(p.steps);
+        task.steps
+            .set_message(p.current.unwrap_or_default().to_owned());
+        if let (Some(bar), Some((bytes, bytes_total))) = (task.bytes.as_ref(), p.bytes) {
+            bar.set_length(bytes_total);
+            bar.set_position(bytes);
+        }
+        if p.is_done() && !task.steps.is_finished() {
+            task.steps.finish_with_message("done");
+            if let Some(bar) = task.bytes.as_ref() {
+                bar.finish();
+            }
+        }
+    }
+
+    fn finish(self) {
+        for task in self.tasks.into_values() {
+            task.steps.abandon();
+            if let Some(bar) = task.bytes {
+                bar.abandon();
+            }
+        }
+    }
+}
+
+/// Validate and render a progress stream.
+fn render_stream(input: impl BufRead, tty: bool) -> Result<()> {
+    let mut validator = Validator::default();
+    let mut bars = tty.then(BarRenderer::new);
+    let mut log = LogRenderer::default();
+    let mut stdout = std::io::stdout();
+    let r = (|| {
+        for line in input.lines() {
+            let line = line.context("Reading progress")?;
+            let event = validator.parse(&line)?;
+            if let Some(bars) = bars.as_mut() {
+                bars.update(&event);
+            } else {
+                for line in log.lines(&event) {
+                    writeln!(stdout, "{line}")?;
+                }
+                stdout.flush()?;
+            }
+        }
+        anyhow::Ok(())
+    })();
+    // Leave the bars in place, even on error
+    if let Some(bars) = bars {
+        bars.finish();
+    }
+    r
+}
+
+/// Implementation of the `bootc progress` CLI command.
+#[context("Rendering progress")]
+pub(crate) fn render(opts: ProgressRenderOpts) -> Result<()> {
+    let tty = std::io::stdout().is_terminal();
+    match (opts.fd, opts.input) {
+        (Some(fd), _) => {
+            let f = std::fs::File::from(OwnedFd::from(fd));
+            render_stream(std::io::BufReader::new(f), tty)
+        }
+        (None, Some(path)) if path != "-" => {
+            let f = std::fs::File::open(&path).with_context(|| format!("Opening {path}"))?;
+            render_stream(std::io::BufReader::new(f), tty)
+        }
+        (None, _) => render_stream(std::io::stdin().lock(), tty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_version() {
+        check_version(API_VERSION).unwrap();
+        check_version("0.0.1").unwrap();
+        assert!(check_version("1.0.0").is_err());
+        assert!(check_version("0.1").is_err());
+        assert!(check_version("foo").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let start = format!(r#"{{"type": "Start", "version": "{API_VERSION}"}}"#);
+        let steps = r#"{"type": "ProgressSteps", "task": "staging", "description": "Deploying", "id": "staging", "stepsCached": 0, "steps": 1, "stepsTotal": 3, "subtasks": []}"#;
+
+        let mut v = Validator::default();
+        v.parse(&start).unwrap();
+        v.parse(steps).unwrap();
+        let e = v.parse(&start).unwrap_err();
+        assert_eq!(e.to_string(), "Unexpected Start event on line 3");
+        let e = v.parse(r#"{"type": "ProgressSteps"}"#).unwrap_err();
+        assert_eq!(e.to_string(), "Invalid progress event on line 4");
+
+        let mut v = Validator::default();
+        let e = v.parse(steps).unwrap_err();
+        assert_eq!(e.to_string(), "Expected Start event on line 1");
+
+        let mut v = Validator::default();
+        assert!(v
+            .parse(r#"{"type": "Start", "version": "99.0.0"}"#)
+            .is_err());
+
+        // These are rejected by the schema
+        let mut v = Validator::default();
+        v.parse(&start).unwrap();
+        for (line, cause) in [
+            (r#"{"type": "Unknown"}"#, "event: no matching schema"),
+            (
+                r#"{"type": "ProgressSteps", "task": "staging", "description": "Deploying", "id": "staging", "steps_cached": 0, "steps": 1, "stepsTotal": 3, "subtasks": []}"#,
+                "event: missing property stepsCached",
+            ),
+            (
+                r#"{"type": "ProgressSteps", "task": "staging", "description": "Deploying", "id": "staging", "stepsCached": -1, "steps": 1, "stepsTotal": 3, "subtasks": []}"#,
+                "event.stepsCached: -1 is less than 0",
+            ),
+            (
+                r#"{"type": "ProgressSteps", "task": "staging", "description": "Deploying", "id": "staging", "stepsCached": 0, "steps": 1, "stepsTotal": 3, "subtasks": [{"subtask": "x"}]}"#,
+                "event.subtasks[0]: missing property completed",
+            ),
+        ] {
+            let e = v.parse(line).unwrap_err();
+            assert_eq!(e.root_cause().to_string(), cause);
+        }
+    }
+
+    #[test]
+    fn test_schema_up_to_date() {
+        let schema = serde_json::to_value(schemars::schema_for!(Event)).unwrap();
+        let docs: Value =
+            serde_json::from_str(include_str!("../../docs/src/progress-v0.schema.json")).unwrap();
+        assert_eq!(schema, docs);
+    }
+
+    #[test]
+    fn test_log_renderer() {
+        let mut log = LogRenderer::default();
+        let pulling = |bytes: u64, steps: u64, n: usize| Event::ProgressBytes {
+            task: "pulling".into(),
+            description: "Pulling Image: sha256:abc".into(),
+            id: "sha256:abc".into(),
+            bytes_cached: 0,
+            bytes,
+            bytes_total: 2048,
+            steps_cached: 0,
+            steps,
+            steps_total: 2,
+            subtasks: (0..n)
+                .map(|i| crate::progress_jsonl::SubTaskBytes {
+                    subtask: "layer".into(),
+                    description: format!("layer: {i}").into(),
+                    id: format!("{i}").into(),
+                    bytes_cached: 0,
+                    bytes: if i < steps as usize { 1024 } else { 10 },
+                    bytes_total: 1024,
+                })
+                .collect(),
+            timestamp_ms: 0,
+            bytes_per_second: 0,
+            eta_seconds: None,
+        };
+        assert!(log
+            .lines(&Event::Start {
+                version: API_VERSION.into(),
+                timestamp_ms: 0,
+            })
+            .is_empty());
+        assert_eq!(log.lines(&pulling(10, 0, 1)), ["Pulling Image: sha256:abc"]);
+        assert!(log.lines(&pulling(20, 0, 1)).is_empty());
+        assert_eq!(log.lines(&pulling(1034, 1, 2)), ["  layer: 0 (1.00 KiB)"]);
+        assert_eq!(
+            log.lines(&pulling(2048, 2, 2)),
+            [
+                "  layer: 1 (1.00 KiB)",
+                "Pulling Image: sha256:abc: done (2 steps, 2.00 KiB)"
+            ]
+        );
+        assert!(log.lines(&pulling(2048, 2, 2)).is_empty());
+
+        let bound = |steps: u64, completed: bool| Event::BoundImage {
+            image: "quay.io/example/app:latest".into(),
+            steps,
+            steps_total: 1,
+            completed,
+        };
+        assert_eq!(log.lines(&bound(0, false)), ["Fetching bound images"]);
+        assert_eq!(
+            log.lines(&bound(1, true)),
+            [
+                "  quay.io/example/app:latest",
+                "Fetching bound images: done (1 steps)"
+            ]
+        );
+
+        // Events for concurrently fetched bound images are interleaved
+        let mut log = LogRenderer::default();
+        let concurrent = |image: &str, steps: u64, completed: bool| Event::BoundImage {
+            image: image.to_owned().into(),
+            steps,
+            steps_total: 2,
+            completed,
+        };
+        assert_eq!(
+            log.lines(&concurrent("quay.io/example/a:latest", 0, false)),
+            ["Fetching bound images"]
+        );
+        assert!(log
+            .lines(&concurrent("quay.io/example/b:latest", 0, false))
+            .is_empty());
+        assert_eq!(
+            log.lines(&concurrent("quay.io/example/b:latest", 1, true)),
+            ["  quay.io/example/b:latest"]
+        );
+        assert_eq!(
+            log.lines(&concurrent("quay.io/example/a:latest", 2, true)),
+            [
+                "  quay.io/example/a:latest",
+                "Fetching bound images: done (2 steps)"
+            ]
+        );
+        let summary = Event::Summary {
+            task: "staging".into(),
+            digest: "sha256:abc".into(),
+            version: Some("42.1".into()),
+            bytes_transferred: 2048,
+            duration_ms: 3000,
+        };
+        assert_eq!(
+            log.lines(&summary),
+            ["staging: sha256:abc (version 42.1); fetched 2.00 KiB in 3 seconds"]
+        );
+        let failed = Event::Failed {
+            task: "upgrade".into(),
+            errors: vec!["Pulling".into(), "connection refused".into()],
+        };
+        assert_eq!(
+            log.lines(&failed),
+            ["error: upgrade: Pulling: connection refused"]
+        );
+    }
+}
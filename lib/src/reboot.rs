@@ -2,20 +2,194 @@
 
 use std::io::Write;
 
+use anyhow::{anyhow, Result};
 use fn_error_context::context;
+use ostree::gio;
+use ostree_ext::ostree;
+use ostree_ext::prelude::*;
 
+use crate::store::Storage;
 use crate::task::Task;
 
+/// The systemd unit which implements a userspace-only restart; present
+/// since systemd v254.
+const SOFT_REBOOT_TARGET: &str = "/usr/lib/systemd/system/soft-reboot.target";
+
+/// The boot-critical state of a deployment; if any of this differs
+/// between two deployments, then switching between them requires
+/// a full reboot.
+#[derive(Debug, PartialEq, Eq)]
+struct BootArtifacts {
+    /// Content checksum of the kernel binary
+    kernel: String,
+    /// Content checksum of the initramfs, if any
+    initramfs: Option<String>,
+    /// The kernel arguments, excluding the per-deployment `ostree=` argument
+    kargs: Vec<String>,
+}
+
+/// Split the kernel arguments from a deployment's boot configuration, dropping
+/// the `ostree=` argument which points to the deployment itself (and which a
+/// staged deployment may not have yet).
+fn comparable_kargs(options: &str) -> Vec<String> {
+    options
+        .split_whitespace()
+        .filter(|k| !k.starts_with("ostree="))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+impl BootArtifacts {
+    /// Gather the kernel, initramfs and kernel arguments of a deployment.
+    /// Returns `None` if no kernel could be found.
+    #[context("Finding kernel of deployment {}", deployment.csum())]
+    fn new(repo: &ostree::Repo, deployment: &ostree::Deployment) -> Result<Option<Self>> {
+        let cancellable = gio::Cancellable::NONE;
+        let (root, _) = repo.read_commit(&deployment.csum(), cancellable)?;
+        let Some(kernel_dir) = ostree_ext::bootabletree::find_kernel_dir(&root, cancellable)?
+        else {
+            return Ok(None);
+        };
+        let checksum_of = |name: &str| -> Result<Option<String>> {
+            let f = kernel_dir.child(name);
+            if !f.query_exists(cancellable) {
+                return Ok(None);
+            }
+            let f = f
+                .downcast::<ostree::RepoFile>()
+                .map_err(|_| anyhow!("Expected a repository file for {name}"))?;
+            f.ensure_resolved()?;
+            Ok(Some(f.checksum().to_string()))
+        };
+        let Some(kernel) = checksum_of("vmlinuz")? else {
+            return Ok(None);
+        };
+        let initramfs = checksum_of("initramfs.img")?;
+        let kargs = deployment
+            .bootconfig()
+            .and_then(|b| b.get("options"))
+            .map(|s| comparable_kargs(&s))
+            .unwrap_or_default();
+        Ok(Some(Self {
+            kernel,
+            initramfs,
+            kargs,
+        }))
+    }
+}
+
+/// Returns true if the host systemd supports a userspace-only restart.
+pub(crate) fn soft_reboot_supported() -> bool {
+    std::path::Path::new(SOFT_REBOOT_TARGET).exists()
+}
+
+/// Returns true if the target deployment has the same kernel, initramfs and
+/// kernel arguments as the booted deployment, and can hence be entered via a
+/// userspace-only restart.
+#[context("Checking for soft reboot capability")]
+fn can_soft_reboot(sysroot: &Storage, target: &ostree::Deployment) -> Result<bool> {
+    if !soft_reboot_supported() {
+        tracing::debug!("systemd does not support soft-reboot");
+        return Ok(false);
+    }
+    let Some(booted) = sysroot.booted_deployment() else {
+        return Ok(false);
+    };
+    if booted.equal(target) {
+        return Ok(false);
+    }
+    let repo = &sysroot.repo();
+    let Some(booted) = BootArtifacts::new(repo, &booted)? else {
+        return Ok(false);
+    };
+    let Some(target) = BootArtifacts::new(repo, target)? else {
+        return Ok(false);
+    };
+    tracing::debug!("booted: {booted:?} target: {target:?}");
+    Ok(booted == target)
+}
+
+fn flush_output() {
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+}
+
+/// Restart into the target deployment.  If it shares the kernel, initramfs and
+/// kernel arguments with the booted deployment, only userspace is restarted;
+/// otherwise this performs a full reboot.
+/// This function will only return in case of error.
+#[context("Restarting into deployment")]
+pub(crate) fn reboot_into(sysroot: &Storage, target: &ostree::Deployment) -> Result<()> {
+    if !can_soft_reboot(sysroot, target)? {
+        return reboot();
+    }
+    let cancellable = gio::Cancellable::NONE;
+    sysroot.deployment_set_soft_reboot(target, false, cancellable)?;
+    flush_output();
+    Task::new("Restarting userspace", "systemctl")
+        .arg("soft-reboot")
+        .run()?;
+    tracing::debug!("Initiated soft reboot, sleeping forever...");
+    loop {
+        std::thread::park();
+    }
+}
+
 /// Initiate a system reboot.
 /// This function will only return in case of error.
 #[context("Initiating reboot")]
 pub(crate) fn reboot() -> anyhow::Result<()> {
-    // Flush output streams
-    let _ = std::io::stdout().flush();
-    let _ = std::io::stderr().flush();
+    flush_output();
     Task::new("Rebooting system", "reboot").run()?;
     tracing::debug!("Initiated reboot, sleeping forever...");
     loop {
         std::thread::park();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparable_kargs() {
+        assert!(comparable_kargs("").is_empty());
+        assert_eq!(
+            comparable_kargs("root=UUID=abc  ostree=/ostree/boot.1/default/0123/0 rw quiet"),
+            ["root=UUID=abc", "rw", "quiet"]
+        );
+    }
+
+    #[test]
+    fn test_boot_artifacts_eq() {
+        let booted = BootArtifacts {
+            kernel: "k1".into(),
+            initramfs: Some("i1".into()),
+            kargs: comparable_kargs("root=UUID=abc ostree=/ostree/boot.1/default/0123/0 rw"),
+        };
+        // A staged deployment without its ostree= argument yet
+        let target = BootArtifacts {
+            kernel: "k1".into(),
+            initramfs: Some("i1".into()),
+            kargs: comparable_kargs("root=UUID=abc rw"),
+        };
+        assert_eq!(booted, target);
+        let target = BootArtifacts {
+            kernel: "k2".into(),
+            ..target
+        };
+        assert_ne!(booted, target);
+        let target = BootArtifacts {
+            kernel: "k1".into(),
+            initramfs: None,
+            ..target
+        };
+        assert_ne!(booted, target);
+        let target = BootArtifacts {
+            initramfs: Some("i1".into()),
+            kargs: comparable_kargs("root=UUID=abc rw debug"),
+            ..target
+        };
+        assert_ne!(booted, target);
+    }
+}
@@ -0,0 +1,601 @@
+//! # Native verification of sigstore (cosign) signatures
+//!
+//! This verifies signatures and attestations created by `cosign sign --key` and
+//! `cosign attest --key` against a locally configured public key. The transparency
+//! log is not consulted, so this also works offline, including with the `oci` and
+//! `oci-archive` transports.
+//!
+//! For an image with manifest digest `sha256:<hex>`, cosign stores signatures in
+//! an artifact tagged `sha256-<hex>.sig` in the same repository. Each layer of
+//! the artifact is a "simple signing" JSON payload, and its base64-encoded signature
+//! is in the `dev.cosignproject.cosign/signature` annotation. Attestations are stored
+//! the same way under the `sha256-<hex>.att` tag, as DSSE envelopes wrapping in-toto
+//! statements.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use fn_error_context::context;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+use ostree_ext::container as ostree_container;
+use ostree_ext::containers_image_proxy::{ImageProxy, ImageProxyConfig};
+use ostree_ext::oci_spec::distribution::Reference;
+use ostree_ext::oci_spec::image::{Descriptor, Digest};
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+
+use crate::spec::ImageReference;
+
+/// The key in the `bootc` group of a deployment origin which holds the path
+/// to the public key used to verify the image.
+pub(crate) const ORIGIN_PUBLIC_KEY: &str = "sigstore-public-key";
+/// The tag suffix of the signature artifact
+const SIGNATURE_SUFFIX: &str = "sig";
+/// The tag suffix of the attestation artifact
+const ATTESTATION_SUFFIX: &str = "att";
+/// The annotation holding the signature of a simple signing payload
+const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+/// The value of `critical.type` in a simple signing payload
+const COSIGN_SIGNATURE_TYPE: &str = "cosign container image signature";
+/// The payload type of an in-toto statement in a DSSE envelope
+const INTOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+/// We only expect small JSON documents in signature artifacts
+const MAX_LAYER_SIZE: u64 = 4 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct SimpleSigning {
+    critical: SimpleSigningCritical,
+}
+
+#[derive(Deserialize)]
+struct SimpleSigningCritical {
+    #[serde(rename = "type")]
+    ty: String,
+    image: SimpleSigningImage,
+}
+
+#[derive(Deserialize)]
+struct SimpleSigningImage {
+    #[serde(rename = "docker-manifest-digest")]
+    docker_manifest_digest: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DsseEnvelope {
+    payload_type: String,
+    payload: String,
+    signatures: Vec<DsseSignature>,
+}
+
+#[derive(Deserialize)]
+struct DsseSignature {
+    sig: String,
+}
+
+#[derive(Deserialize)]
+struct InTotoStatement {
+    subject: Vec<InTotoSubject>,
+}
+
+#[derive(Deserialize)]
+struct InTotoSubject {
+    digest: HashMap<String, String>,
+}
+
+/// Compute the reference of a cosign artifact (e.g. the signatures) of an image.
+fn artifact_imgref(
+    imgref: &ostree_container::ImageReference,
+    digest: &Digest,
+    suffix: &str,
+) -> Result<ostree_container::ImageReference> {
+    use ostree_container::Transport;
+    let tag = format!("{}-{}.{suffix}", digest.algorithm(), digest.digest());
+    let name = match imgref.transport {
+        Transport::Registry => {
+            let r: Reference = imgref.name.parse()?;
+            format!("{}/{}:{tag}", r.registry(), r.repository())
+        }
+        Transport::OciDir | Transport::OciArchive => {
+            let path = imgref
+                .name
+                .split_once(':')
+                .map_or(imgref.name.as_str(), |(path, _)| path);
+            format!("{path}:{tag}")
+        }
+        o => anyhow::bail!("Sigstore verification is not supported with transport {o}"),
+    };
+    Ok(ostree_container::ImageReference {
+        transport: imgref.transport,
+        name,
+    })
+}
+
+/// Fetch all layers of a cosign artifact, returning `None` if it does not exist.
+async fn fetch_artifact(
+    proxy: &ImageProxy,
+    imgref: &ostree_container::ImageReference,
+) -> Result<Option<Vec<(Descriptor, Vec<u8>)>>> {
+    let Some(img) = proxy.open_image_optional(&imgref.to_string()).await? else {
+        return Ok(None);
+    };
+    let (_, manifest) = proxy.fetch_manifest(&img).await?;
+    let mut layers = Vec::new();
+    for layer in manifest.layers() {
+        let size = layer.size();
+        anyhow::ensure!(
+            size <= MAX_LAYER_SIZE,
+            "Layer {} is too large ({size} bytes)",
+            layer.digest()
+        );
+        let (mut blob, driver) = proxy.get_blob(&img, layer.digest(), size).await?;
+        let mut buf = Vec::new();
+        let (read, driver) = tokio::join!(blob.read_to_end(&mut buf), driver);
+        driver?;
+        read?;
+        let actual = hex::encode(openssl::sha::sha256(&buf));
+        anyhow::ensure!(
+            actual == layer.digest().digest(),
+            "Corrupted layer {}: found sha256:{actual}",
+            layer.digest()
+        );
+        layers.push((layer.clone(), buf));
+    }
+    proxy.close_image(&img).await?;
+    Ok(Some(layers))
+}
+
+/// Verify a base64-encoded signature of `data`.
+fn verify_blob(key: &PKey<Public>, data: &[u8], signature: &str) -> Result<()> {
+    let signature =
+        openssl::base64::decode_block(signature.trim()).context("Decoding signature")?;
+    let valid = if key.id() == Id::ED25519 {
+        Verifier::new_without_digest(key)?.verify_oneshot(&signature, data)
+    } else {
+        let mut verifier = Verifier::new(MessageDigest::sha256(), key)?;
+        verifier.update(data)?;
+        verifier.verify(&signature)
+    };
+    // A malformed signature is an error from openssl; treat it as invalid.
+    anyhow::ensure!(valid.unwrap_or_default(), "Signature verification failed");
+    Ok(())
+}
+
+/// Verify a simple signing payload and its signature against the expected manifest digest.
+fn verify_signature(
+    key: &PKey<Public>,
+    payload: &[u8],
+    signature: &str,
+    digest: &Digest,
+) -> Result<()> {
+    verify_blob(key, payload, signature)?;
+    let payload: SimpleSigning =
+        serde_json::from_slice(payload).context("Parsing signature payload")?;
+    let critical = payload.critical;
+    anyhow::ensure!(
+        critical.ty == COSIGN_SIGNATURE_TYPE,
+        "Unexpected signature type: {}",
+        critical.ty
+    );
+    let signed = critical.image.docker_manifest_digest;
+    anyhow::ensure!(
+        signed == digest.as_ref(),
+        "Signature is for {signed}, not {digest}"
+    );
+    Ok(())
+}
+
+/// Compute the DSSE pre-authentication encoding of a payload.
+fn dsse_pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut r = format!(
+        "DSSEv1 {} {payload_type} {} ",
+        payload_type.len(),
+        payload.len()
+    )
+    .into_bytes();
+    r.extend_from_slice(payload);
+    r
+}
+
+/// Verify a DSSE envelope holding an in-toto statement about the expected manifest digest.
+/// Returns `false` if the envelope is not signed by `key`, in which case it should be
+/// ignored, just like signatures made with other keys.
+fn verify_attestation(key: &PKey<Public>, envelope: &[u8], digest: &Digest) -> Result<bool> {
+    let envelope: DsseEnvelope =
+        serde_json::from_slice(envelope).context("Parsing attestation envelope")?;
+    let payload = openssl::base64::decode_block(&envelope.payload).context("Decoding payload")?;
+    let pae = dsse_pae(&envelope.payload_type, &payload);
+    if !envelope
+        .signatures
+        .iter()
+        .any(|s| verify_blob(key, &pae, &s.sig).is_ok())
+    {
+        return Ok(false);
+    }
+    anyhow::ensure!(
+        envelope.payload_type == INTOTO_PAYLOAD_TYPE,
+        "Unexpected attestation payload type: {}",
+        envelope.payload_type
+    );
+    let statement: InTotoStatement =
+        serde_json::from_slice(&payload).context("Parsing attestation statement")?;
+    let algorithm = digest.algorithm().to_string();
+    anyhow::ensure!(
+        statement
+            .subject
+            .iter()
+            .any(|s| s.digest.get(&algorithm).map(String::as_str) == Some(digest.digest())),
+        "Attestation does not apply to {digest}"
+    );
+    Ok(true)
+}
+
+/// Verify that an image with the given manifest digest has at least one valid
+/// sigstore signature made with the public key at the given path. If the image has
+/// attestations made with that key, all of them must also be valid.
+pub(crate) async fn verify(
+    imgref: &ImageReference,
+    public_key: &str,
+    digest: &Digest,
+) -> Result<()> {
+    let public_key =
+        std::fs::read(public_key).with_context(|| format!("Reading public key {public_key}"))?;
+    let imgref = ostree_container::OstreeImageReference::from(imgref.clone()).imgref;
+    let proxy = &new_proxy().await?;
+    verify_with_key(proxy, &imgref, &public_key, digest).await
+}
+
+/// Create an image proxy for fetching signatures, which may be shared by
+/// multiple calls to [`verify_with_key`] (and for other registry queries).
+pub(crate) async fn new_proxy() -> Result<ImageProxy> {
+    let mut config = ImageProxyConfig::default();
+    ostree_container::merge_default_container_proxy_opts(&mut config)?;
+    ImageProxy::new_with_config(config).await
+}
+
+/// Like [`verify`], but with a PEM-encoded public key, using the given proxy.
+#[context("Verifying sigstore signature of {imgref}")]
+pub(crate) async fn verify_with_key(
+    proxy: &ImageProxy,
+    imgref: &ostree_container::ImageReference,
+    public_key: &[u8],
+    digest: &Digest,
+) -> Result<()> {
+    let key = &PKey::public_key_from_pem(public_key).context("Parsing public key")?;
+
+    let sigref = artifact_imgref(imgref, digest, SIGNATURE_SUFFIX)?;
+    let Some(signatures) = fetch_artifact(proxy, &sigref).await? else {
+        anyhow::bail!("No sigstore signature found at {sigref}");
+    };
+    let mut n_valid = 0;
+    for (desc, payload) in signatures.iter() {
+        let Some(signature) = desc
+            .annotations()
+            .as_ref()
+            .and_then(|a| a.get(SIGNATURE_ANNOTATION))
+        else {
+            continue;
+        };
+        match verify_signature(key, payload, signature, digest) {
+            Ok(()) => n_valid += 1,
+            Err(e) => tracing::debug!("Ignoring signature {}: {e:#}", desc.digest()),
+        }
+    }
+    anyhow::ensure!(n_valid > 0, "No valid sigstore signature for {digest}");
+    tracing::debug!("Found {n_valid} valid signatures for {digest}");
+
+    let attref = artifact_imgref(imgref, digest, ATTESTATION_SUFFIX)?;
+    if let Some(attestations) = fetch_artifact(proxy, &attref).await? {
+        let mut n_verified = 0;
+        for (desc, envelope) in attestations.iter() {
+            let verified = verify_attestation(key, envelope, digest)
+                .with_context(|| format!("Verifying attestation {}", desc.digest()))?;
+            if verified {
+                n_verified += 1;
+            } else {
+                tracing::debug!(
+                    "Ignoring attestation {} made with another key",
+                    desc.digest()
+                );
+            }
+        }
+        tracing::debug!("Verified {n_verified} attestations");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+    use ostree_ext::oci_spec::image::MediaType;
+
+    use super::*;
+
+    const DIGEST: &str = "sha256:0d3c4d8d2c5b9d0ac2a9b6e5ee2ec1c8c2c1f7d8a4e3b2a1908f7e6d5c4b3a29";
+
+    fn new_key() -> (PKey<Private>, PKey<Public>) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let private = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let public = PKey::public_key_from_pem(&private.public_key_to_pem().unwrap()).unwrap();
+        (private, public)
+    }
+
+    fn sign(key: &PKey<Private>, data: &[u8]) -> String {
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer.update(data).unwrap();
+        openssl::base64::encode_block(&signer.sign_to_vec().unwrap())
+    }
+
+    /// A simple signing payload for the given manifest digest, as signed by cosign.
+    fn signature_payload(digest: &str) -> String {
+        serde_json::json!({
+            "critical": {
+                "identity": { "docker-reference": "quay.io/example/os" },
+                "image": { "docker-manifest-digest": digest },
+                "type": COSIGN_SIGNATURE_TYPE,
+            },
+            "optional": null,
+        })
+        .to_string()
+    }
+
+    /// A DSSE envelope holding an in-toto statement about the given (hex) digest.
+    fn attestation(key: &PKey<Private>, subject: &str) -> String {
+        let statement = serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "predicateType": "https://slsa.dev/provenance/v0.2",
+            "subject": [{ "name": "quay.io/example/os", "digest": { "sha256": subject } }],
+            "predicate": {},
+        })
+        .to_string();
+        let sig = sign(key, &dsse_pae(INTOTO_PAYLOAD_TYPE, statement.as_bytes()));
+        serde_json::json!({
+            "payloadType": INTOTO_PAYLOAD_TYPE,
+            "payload": openssl::base64::encode_block(statement.as_bytes()),
+            "signatures": [{ "keyid": "", "sig": sig }],
+        })
+        .to_string()
+    }
+
+    /// A layer of a manifest written by [`OciLayout::add_manifest`].
+    type TestLayer = (MediaType, Vec<u8>, Option<HashMap<String, String>>);
+
+    /// A minimal OCI image layout, as written by e.g. `cosign save`.
+    struct OciLayout {
+        dir: tempfile::TempDir,
+        /// The tagged manifests in the index
+        manifests: Vec<(String, Descriptor)>,
+    }
+
+    impl OciLayout {
+        fn new() -> Result<Self> {
+            let dir = tempfile::tempdir()?;
+            std::fs::create_dir_all(dir.path().join("blobs/sha256"))?;
+            std::fs::write(
+                dir.path().join("oci-layout"),
+                r#"{"imageLayoutVersion":"1.0.0"}"#,
+            )?;
+            Ok(Self {
+                dir,
+                manifests: Vec::new(),
+            })
+        }
+
+        fn write_blob(&self, media_type: MediaType, data: &[u8]) -> Result<Descriptor> {
+            let hex = hex::encode(openssl::sha::sha256(data));
+            std::fs::write(self.dir.path().join("blobs/sha256").join(&hex), data)?;
+            let digest = Digest::from_str(&format!("sha256:{hex}"))?;
+            Ok(Descriptor::new(media_type, data.len() as u64, digest))
+        }
+
+        /// Write a manifest with the given layers, and tag it (replacing any
+        /// previous manifest with the same tag).
+        fn add_manifest(&mut self, tag: &str, layers: Vec<TestLayer>) -> Result<Digest> {
+            let config = self.write_blob(
+                MediaType::ImageConfig,
+                br#"{"architecture":"amd64","os":"linux","config":{},"rootfs":{"type":"layers","diff_ids":[]}}"#,
+            )?;
+            let layers = layers
+                .into_iter()
+                .map(|(media_type, data, annotations)| {
+                    let mut desc = self.write_blob(media_type, &data)?;
+                    desc.set_annotations(annotations);
+                    Ok(desc)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let manifest = serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": config,
+                "layers": layers,
+            });
+            let mut desc =
+                self.write_blob(MediaType::ImageManifest, &serde_json::to_vec(&manifest)?)?;
+            desc.set_annotations(Some(HashMap::from([(
+                "org.opencontainers.image.ref.name".to_owned(),
+                tag.to_owned(),
+            )])));
+            let digest = desc.digest().clone();
+            self.manifests.retain(|(t, _)| t != tag);
+            self.manifests.push((tag.to_owned(), desc));
+            let index = serde_json::json!({
+                "schemaVersion": 2,
+                "manifests": self.manifests.iter().map(|(_, d)| d).collect::<Vec<_>>(),
+            });
+            std::fs::write(
+                self.dir.path().join("index.json"),
+                serde_json::to_vec(&index)?,
+            )?;
+            Ok(digest)
+        }
+
+        fn imgref(&self, tag: &str) -> Result<ostree_container::ImageReference> {
+            let path = self.dir.path().to_str().unwrap();
+            ostree_container::ImageReference::try_from(format!("oci:{path}:{tag}").as_str())
+        }
+    }
+
+    #[test]
+    fn test_artifact_imgref() {
+        let digest = Digest::from_str(DIGEST).unwrap();
+        let cases = [
+            (
+                "registry:quay.io/example/os:latest",
+                "docker://quay.io/example/os:sha256-0d3c4d8d2c5b9d0ac2a9b6e5ee2ec1c8c2c1f7d8a4e3b2a1908f7e6d5c4b3a29.sig",
+            ),
+            (
+                "oci:/var/lib/images/os:latest",
+                "oci:/var/lib/images/os:sha256-0d3c4d8d2c5b9d0ac2a9b6e5ee2ec1c8c2c1f7d8a4e3b2a1908f7e6d5c4b3a29.sig",
+            ),
+            (
+                "oci-archive:/srv/os.ociarchive",
+                "oci-archive:/srv/os.ociarchive:sha256-0d3c4d8d2c5b9d0ac2a9b6e5ee2ec1c8c2c1f7d8a4e3b2a1908f7e6d5c4b3a29.sig",
+            ),
+        ];
+        for (src, expected) in cases {
+            let imgref = ostree_container::ImageReference::try_from(src).unwrap();
+            let r = artifact_imgref(&imgref, &digest, SIGNATURE_SUFFIX).unwrap();
+            assert_eq!(r.to_string(), expected);
+        }
+        let imgref =
+            ostree_container::ImageReference::try_from("containers-storage:localhost/os").unwrap();
+        assert!(artifact_imgref(&imgref, &digest, SIGNATURE_SUFFIX).is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let digest = Digest::from_str(DIGEST).unwrap();
+        let (private, public) = new_key();
+        let payload = signature_payload(DIGEST);
+        let signature = sign(&private, payload.as_bytes());
+        verify_signature(&public, payload.as_bytes(), &signature, &digest).unwrap();
+
+        // Signed by a different key
+        let (_, other) = new_key();
+        assert!(verify_signature(&other, payload.as_bytes(), &signature, &digest).is_err());
+        // Tampered payload
+        let tampered = payload.replace("0d3c", "1d3c");
+        assert!(verify_signature(&public, tampered.as_bytes(), &signature, &digest).is_err());
+        // Validly signed, but for a different image
+        let signature = sign(&private, tampered.as_bytes());
+        assert!(verify_signature(&public, tampered.as_bytes(), &signature, &digest).is_err());
+        // Garbage signature
+        assert!(verify_signature(&public, payload.as_bytes(), "Zm9v", &digest).is_err());
+    }
+
+    #[test]
+    fn test_verify_attestation() {
+        let digest = Digest::from_str(DIGEST).unwrap();
+        let (private, public) = new_key();
+        let valid = attestation(&private, digest.digest());
+        assert!(verify_attestation(&public, valid.as_bytes(), &digest).unwrap());
+        // Attestations made with other keys are ignored
+        let (_, other) = new_key();
+        assert!(!verify_attestation(&other, valid.as_bytes(), &digest).unwrap());
+        let wrong_subject = attestation(&private, &"f".repeat(64));
+        assert!(verify_attestation(&public, wrong_subject.as_bytes(), &digest).is_err());
+    }
+
+    #[test]
+    fn test_dsse_pae() {
+        assert_eq!(
+            dsse_pae("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_oci() -> Result<()> {
+        // The image proxy is implemented by skopeo
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        if !std::env::split_paths(&path).any(|d| d.join("skopeo").exists()) {
+            eprintln!("skopeo not found; skipping");
+            return Ok(());
+        }
+        let (private, public) = new_key();
+        let public = public.public_key_to_pem()?;
+        let mut layout = OciLayout::new()?;
+        let digest = layout.add_manifest(
+            "latest",
+            vec![(MediaType::ImageLayer, b"rootfs".to_vec(), None)],
+        )?;
+        let imgref = layout.imgref("latest")?;
+        let proxy = &new_proxy().await?;
+        let sigref = artifact_imgref(&imgref, &digest, SIGNATURE_SUFFIX)?;
+        let attref = artifact_imgref(&imgref, &digest, ATTESTATION_SUFFIX)?;
+        let tag =
+            |r: &ostree_container::ImageReference| r.name.rsplit_once(':').unwrap().1.to_owned();
+
+        // Without any signature
+        assert!(verify_with_key(proxy, &imgref, &public, &digest)
+            .await
+            .is_err());
+
+        let payload = signature_payload(digest.as_ref());
+        let signature = sign(&private, payload.as_bytes());
+        layout.add_manifest(
+            &tag(&sigref),
+            vec![(
+                MediaType::Other("application/vnd.dev.cosign.simplesigning.v1+json".into()),
+                payload.into_bytes(),
+                Some(HashMap::from([(
+                    SIGNATURE_ANNOTATION.to_owned(),
+                    signature,
+                )])),
+            )],
+        )?;
+        let signatures = fetch_artifact(proxy, &sigref).await?.unwrap();
+        assert_eq!(signatures.len(), 1);
+        assert!(fetch_artifact(proxy, &attref).await?.is_none());
+        verify_with_key(proxy, &imgref, &public, &digest).await?;
+        let (_, other) = new_key();
+        assert!(
+            verify_with_key(proxy, &imgref, &other.public_key_to_pem()?, &digest)
+                .await
+                .is_err()
+        );
+
+        // An attestation made with another key is ignored, but an invalid one
+        // made with ours is not.
+        let dsse = MediaType::Other("application/vnd.dsse.envelope.v1+json".into());
+        let (other_private, _) = new_key();
+        let wrong_subject = "f".repeat(64);
+        layout.add_manifest(
+            &tag(&attref),
+            vec![(
+                dsse.clone(),
+                attestation(&other_private, &wrong_subject).into_bytes(),
+                None,
+            )],
+        )?;
+        verify_with_key(proxy, &imgref, &public, &digest).await?;
+        layout.add_manifest(
+            &tag(&attref),
+            vec![(
+                dsse.clone(),
+                attestation(&private, &wrong_subject).into_bytes(),
+                None,
+            )],
+        )?;
+        assert!(verify_with_key(proxy, &imgref, &public, &digest)
+            .await
+            .is_err());
+        layout.add_manifest(
+            &tag(&attref),
+            vec![(
+                dsse,
+                attestation(&private, digest.digest()).into_bytes(),
+                None,
+            )],
+        )?;
+        verify_with_key(proxy, &imgref, &public, &digest).await?;
+        Ok(())
+    }
+}
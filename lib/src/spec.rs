@@ -65,6 +65,33 @@ pub struct HostSpec {
     /// If set, and there is a rollback deployment, it will be set for the next boot.
     #[serde(default)]
     pub boot_order: BootOrder,
+    /// Machine-local changes to the kernel arguments
+    #[serde(default, skip_serializing_if = "KernelArgsSpec::is_empty")]
+    pub kargs: KernelArgsSpec,
+    /// Additional container images to keep pulled, beyond those bound by the host image
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bound_images: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+/// Machine-local kernel arguments, applied to every deployment in addition to those
+/// from the host image
+pub struct KernelArgsSpec {
+    /// Kernel arguments to add
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add: Vec<String>,
+    /// Kernel arguments to remove; an entry of the form `key` removes all arguments
+    /// with that key, and `key=value` removes exactly that argument.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+}
+
+impl KernelArgsSpec {
+    /// Returns true if there are no kernel argument changes
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
@@ -77,6 +104,13 @@ pub enum ImageSignature {
     ContainerPolicy,
     /// No signature verification will be performed
     Insecure,
+    /// Fetches will be verified by bootc against sigstore (cosign) signatures and attestations
+    /// stored alongside the image, using a locally configured public key.
+    #[serde(rename_all = "camelCase")]
+    Sigstore {
+        /// Path to the PEM-encoded public key
+        public_key: String,
+    },
 }
 
 /// A container image reference with attached transport and signature verification
@@ -164,6 +198,28 @@ pub struct BootEntryOstree {
     pub deploy_serial: u32,
 }
 
+/// The automatic boot assessment state of a deployment
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum BootAssessmentState {
+    /// The deployment has not yet passed its health checks
+    Pending,
+    /// The deployment passed its health checks
+    Good,
+    /// The deployment failed its health checks or exhausted its boot attempts
+    Bad,
+}
+
+/// Automatic boot assessment status of a deployment
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BootAssessment {
+    /// The current assessment state
+    pub state: BootAssessmentState,
+    /// The number of remaining boot attempts before the deployment is considered failed
+    pub boot_attempts_left: u32,
+}
+
 /// A bootable entry
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -181,6 +237,37 @@ pub struct BootEntry {
     pub store: Option<Store>,
     /// If this boot entry is ostree based, the corresponding state
     pub ostree: Option<BootEntryOstree>,
+    /// The automatic boot assessment state, if the deployment is subject to it
+    #[serde(default)]
+    pub assessment: Option<BootAssessment>,
+    /// Whether this staged entry was downloaded only, and will not be applied
+    /// at shutdown until explicitly requested
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub download_only: bool,
+    /// The version of the kernel in this deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    /// The effective kernel command line of this deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kargs: Option<Vec<String>>,
+    /// The logically bound images of this deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bound_images: Option<Vec<String>>,
+    /// The state of composefs for this deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub composefs: Option<ComposefsState>,
+}
+
+/// The state of composefs for a deployment
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ComposefsState {
+    /// composefs is not enabled for the deployment, or it does not have a composefs image
+    Disabled,
+    /// composefs is enabled, and the deployment has a composefs image without fsverity
+    Enabled,
+    /// composefs is enabled, and fsverity is enabled on the composefs image of the deployment
+    Verity,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
@@ -214,6 +301,70 @@ pub struct HostStatus {
     /// The detected type of system
     #[serde(rename = "type")]
     pub ty: Option<HostType>,
+
+    /// Conditions summarizing the state of the host, in the style of Kubernetes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+
+    /// Set to true if this is the status of an offline (not booted) system, such
+    /// as a mounted disk image.  The booted entry is then the default boot entry.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub offline: bool,
+}
+
+/// The aspect of the host state described by a condition
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[non_exhaustive]
+pub enum ConditionType {
+    /// An update for the booted image was found by `bootc upgrade --check`
+    UpdateAvailable,
+    /// An update is staged to be applied on the next boot
+    UpdateStaged,
+    /// The rollback deployment is queued for the next boot
+    RollbackQueued,
+    /// The host has errors which may need manual intervention
+    Degraded,
+    /// The booted deployment has local changes bootc does not understand
+    Incompatible,
+}
+
+/// Whether a condition applies
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub enum ConditionStatus {
+    /// The condition applies
+    True,
+    /// The condition does not apply
+    False,
+    /// It is not known whether the condition applies
+    Unknown,
+}
+
+impl From<bool> for ConditionStatus {
+    fn from(v: bool) -> Self {
+        if v {
+            Self::True
+        } else {
+            Self::False
+        }
+    }
+}
+
+/// A condition describing one aspect of the state of the host
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    /// The aspect of the host state described by this condition
+    #[serde(rename = "type")]
+    pub ty: ConditionType,
+    /// Whether the condition applies
+    pub status: ConditionStatus,
+    /// A machine readable reason for the condition's status, in CamelCase
+    pub reason: String,
+    /// A human readable message with details about the condition
+    pub message: String,
+    /// When the condition last changed its status, if known
+    #[serde(default)]
+    pub last_transition_time: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl Host {
@@ -268,6 +419,42 @@ impl HostSpec {
         if rollback && image_change {
             anyhow::bail!("Invalid state transition: rollback and image change");
         }
+        // Local state lives in /etc of the booted deployment, which the
+        // rollback deployment does not share.
+        if rollback && self.kargs != new.kargs {
+            anyhow::bail!("Invalid state transition: rollback and kernel argument change");
+        }
+        if rollback && self.bound_images != new.bound_images {
+            anyhow::bail!("Invalid state transition: rollback and bound image change");
+        }
+        new.kargs.validate()?;
+        for (i, image) in new.bound_images.iter().enumerate() {
+            image
+                .parse::<Reference>()
+                .map_err(|e| anyhow::anyhow!("Invalid bound image {image}: {e}"))?;
+            if new.bound_images[..i].contains(image) {
+                anyhow::bail!("Duplicate bound image: {image}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl KernelArgsSpec {
+    fn validate(&self) -> anyhow::Result<()> {
+        for karg in self.add.iter().chain(self.remove.iter()) {
+            if karg.is_empty() || karg.contains(char::is_whitespace) {
+                anyhow::bail!("Invalid kernel argument: {karg:?}");
+            }
+        }
+        // This matches the semantics of `delete` in kargs.d
+        if let Some(karg) = self.add.iter().find(|k| {
+            self.remove
+                .iter()
+                .any(|entry| crate::kargs::delete_matches(entry, k))
+        }) {
+            anyhow::bail!("Kernel argument {karg} is both added and removed");
+        }
         Ok(())
     }
 }
@@ -474,6 +661,87 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sigstore_signature() {
+        let sig = ImageSignature::Sigstore {
+            public_key: "/etc/pki/containers/cosign.pub".into(),
+        };
+        let serialized = serde_json::to_string(&sig).unwrap();
+        assert_eq!(
+            serialized,
+            r#"{"sigstore":{"publicKey":"/etc/pki/containers/cosign.pub"}}"#
+        );
+        let parsed: ImageSignature = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(parsed, sig);
+    }
+
+    #[test]
+    fn test_parse_spec_local_state() {
+        const SPEC_FIXTURE: &str = include_str!("fixtures/spec-local-state.yaml");
+        let host: Host = serde_yaml::from_str(SPEC_FIXTURE).unwrap();
+        assert_eq!(host.spec.kargs.add, ["console=ttyS0,115200"]);
+        assert_eq!(host.spec.kargs.remove, ["quiet"]);
+        assert_eq!(host.spec.bound_images, ["quay.io/example/app:latest"]);
+
+        // Empty local state is omitted
+        let spec = HostSpec::default();
+        let serialized = serde_yaml::to_string(&spec).unwrap();
+        assert!(!serialized.contains("kargs"));
+        assert!(!serialized.contains("boundImages"));
+    }
+
+    #[test]
+    fn test_verify_transition() {
+        const SPEC_FIXTURE: &str = include_str!("fixtures/spec-local-state.yaml");
+        let host: Host = serde_yaml::from_str(SPEC_FIXTURE).unwrap();
+        let orig = &host.spec;
+        orig.verify_transition(orig).unwrap();
+
+        let mut new = orig.clone();
+        new.kargs.add.push("debug".into());
+        new.bound_images.push("quay.io/example/other:latest".into());
+        orig.verify_transition(&new).unwrap();
+
+        new.boot_order = BootOrder::Rollback;
+        let e = orig.verify_transition(&new).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Invalid state transition: rollback and kernel argument change"
+        );
+
+        let mut new = orig.clone();
+        new.kargs.add.push("quiet".into());
+        let e = orig.verify_transition(&new).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Kernel argument quiet is both added and removed"
+        );
+
+        let mut new = orig.clone();
+        new.kargs.remove.push("console".into());
+        let e = orig.verify_transition(&new).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Kernel argument console=ttyS0,115200 is both added and removed"
+        );
+
+        let mut new = orig.clone();
+        new.kargs.remove.push("foo bar".into());
+        assert!(orig.verify_transition(&new).is_err());
+
+        let mut new = orig.clone();
+        new.bound_images.push("quay.io/example/app:latest".into());
+        let e = orig.verify_transition(&new).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Duplicate bound image: quay.io/example/app:latest"
+        );
+
+        let mut new = orig.clone();
+        new.bound_images.push("Not A Valid Reference".into());
+        assert!(orig.verify_transition(&new).is_err());
+    }
+
     #[test]
     fn test_display_imgref() {
         let src = "ostree-unverified-registry:quay.io/example/foo:sometag";
@@ -520,6 +788,12 @@ mod tests {
                 pinned: false,
                 store: None,
                 ostree: None,
+                assessment: None,
+                download_only: false,
+                kernel: None,
+                kargs: None,
+                bound_images: None,
+                composefs: None,
             }
         }
 
@@ -3,19 +3,26 @@ use std::collections::VecDeque;
 use std::io::IsTerminal;
 use std::io::Read;
 use std::io::Write;
+use std::os::fd::AsFd;
 
 use anyhow::{Context, Result};
+use cap_std_ext::cap_std::fs::Dir;
+use cap_std_ext::dirext::CapStdExtDirExt;
 use fn_error_context::context;
 use ostree::glib;
 use ostree_container::OstreeImageReference;
+use ostree_ext::composefs::fsverity;
 use ostree_ext::container as ostree_container;
 use ostree_ext::container_utils::ostree_booted;
 use ostree_ext::keyfileext::KeyFileExt;
 use ostree_ext::oci_spec;
 use ostree_ext::ostree;
 
-use crate::cli::OutputFormat;
-use crate::spec::{BootEntry, BootOrder, Host, HostSpec, HostStatus, HostType};
+use crate::cli::StatusOutputFormat;
+use crate::fsck::FsckState;
+use crate::history::{HistoryEntry, HistoryEvent};
+use crate::spec::{BootEntry, BootOrder, ComposefsState, Host, HostSpec, HostStatus, HostType};
+use crate::spec::{Condition, ConditionType};
 use crate::spec::{ImageReference, ImageSignature};
 use crate::store::{CachedImageStatus, ContainerImageStore, Storage};
 
@@ -37,6 +44,9 @@ impl From<ImageSignature> for ostree_container::SignatureSource {
             ImageSignature::OstreeRemote(r) => SignatureSource::OstreeRemote(r),
             ImageSignature::ContainerPolicy => Self::ContainerPolicy,
             ImageSignature::Insecure => Self::ContainerPolicyAllowInsecure,
+            // The fetch itself is unverified; bootc checks the signatures against
+            // the manifest digest before fetching any layers.
+            ImageSignature::Sigstore { .. } => Self::ContainerPolicyAllowInsecure,
         }
     }
 }
@@ -102,22 +112,96 @@ pub(crate) struct Deployments {
     pub(crate) other: VecDeque<ostree::Deployment>,
 }
 
+/// Find the creation timestamp of an image, preferring the standard label
+/// over the config `created` field.
+pub(crate) fn timestamp_of_config(
+    config: &oci_spec::image::ImageConfiguration,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    labels_of_config(config)
+        .and_then(|l| {
+            l.get(oci_spec::image::ANNOTATION_CREATED)
+                .map(|s| s.as_str())
+        })
+        .or_else(|| config.created().as_deref())
+        .and_then(bootc_utils::try_deserialize_timestamp)
+}
+
 pub(crate) fn labels_of_config(
     config: &oci_spec::image::ImageConfiguration,
 ) -> Option<&std::collections::HashMap<String, String>> {
     config.config().as_ref().and_then(|c| c.labels().as_ref())
 }
 
-/// Given an OSTree deployment, parse out metadata into our spec.
+/// Find the version of the kernel in a deployment root.
+#[context("Finding kernel version")]
+fn kernel_version_of_root(root: &Dir) -> Result<Option<String>> {
+    let kernel_dir = ostree_ext::bootabletree::find_kernel_dir_fs(root)?;
+    Ok(kernel_dir.and_then(|d| d.file_name().map(ToOwned::to_owned)))
+}
+
+/// Determine whether a deployment root is mounted via composefs, and if so
+/// whether fsverity is enabled on its composefs image.  This requires both that
+/// composefs is enabled in the deployment's `ostree/prepare-root.conf`, and that
+/// the deployment has a composefs image.
+#[context("Querying composefs state")]
+pub(crate) fn composefs_state_of_root(root: &Dir) -> Result<ComposefsState> {
+    use ostree_ext::ostree_prepareroot::{self, Tristate};
+
+    let configured = ostree_prepareroot::load_config_from_root(root)?
+        .map(|config| config.optional_string("composefs", "enabled"))
+        .transpose()?
+        .flatten()
+        .map(|v| v.parse::<ostree_prepareroot::ComposefsState>())
+        .transpose()?
+        .unwrap_or_default();
+    let enabled = match configured {
+        ostree_prepareroot::ComposefsState::Signed | ostree_prepareroot::ComposefsState::Verity => {
+            true
+        }
+        ostree_prepareroot::ComposefsState::Tristate(t) => t != Tristate::Disabled,
+    };
+    if !enabled {
+        return Ok(ComposefsState::Disabled);
+    }
+    let Some(f) = root.open_optional(crate::install::OSTREE_COMPOSEFS_SUPER)? else {
+        return Ok(ComposefsState::Disabled);
+    };
+    let digest: Option<fsverity::Sha256HashValue> = fsverity::measure_verity_opt(f.as_fd())?;
+    let r = if digest.is_some() {
+        ComposefsState::Verity
+    } else {
+        ComposefsState::Enabled
+    };
+    Ok(r)
+}
+
+/// Log a failure to determine an informational part of the status.  As the status
+/// is also used by e.g. upgrades and rollbacks, such failures are only warned about.
+fn warn_on_error<T>(r: Result<T>) -> Option<T> {
+    r.map_err(|e| tracing::warn!("{e:#}")).ok()
+}
+
+/// Fill in the machine-local parts of the spec from the given root.
+fn read_local_spec(root: &Dir, spec: &mut HostSpec) {
+    if let Some(kargs) = warn_on_error(crate::kargs::read_local_kargs(root)) {
+        spec.kargs = kargs;
+    }
+    if let Some(images) = warn_on_error(crate::boundimage::query_local_bound_images(root)) {
+        spec.bound_images = images;
+    }
+}
+
+/// Given an OSTree deployment, parse out metadata into our spec.  A failure to query
+/// its bound images is also returned, as it is reflected in the conditions.
 #[context("Reading deployment metadata")]
 fn boot_entry_from_deployment(
     sysroot: &Storage,
     deployment: &ostree::Deployment,
-) -> Result<BootEntry> {
+) -> Result<(BootEntry, Option<String>)> {
     let (
         store,
         CachedImageStatus {
-            image,
+            mut image,
             cached_update,
         },
         incompatible,
@@ -142,6 +226,33 @@ fn boot_entry_from_deployment(
         // The deployment has no origin at all (this generally shouldn't happen)
         (None, CachedImageStatus::default(), false)
     };
+    // Sigstore verification is implemented by us, so it isn't part of the ostree image reference
+    if let Some((image, origin)) = image.as_mut().zip(deployment.origin()) {
+        if let Some(public_key) =
+            origin.optional_string("bootc", crate::sigstore::ORIGIN_PUBLIC_KEY)?
+        {
+            image.image.signature = Some(ImageSignature::Sigstore {
+                public_key: public_key.into(),
+            });
+        }
+    }
+
+    let root = &crate::utils::deployment_fd(sysroot, deployment)?;
+    let kargs = deployment
+        .bootconfig()
+        .and_then(|b| b.get("options"))
+        .map(|o| o.split_whitespace().map(ToOwned::to_owned).collect());
+    let kernel = warn_on_error(kernel_version_of_root(root)).flatten();
+    let composefs = warn_on_error(composefs_state_of_root(root));
+    let (bound_images, bound_images_error) =
+        match crate::boundimage::query_bound_images_for_deployment(sysroot, deployment) {
+            Ok(images) => (Some(images.into_iter().map(|i| i.image).collect()), None),
+            Err(e) => {
+                let e = format!("{e:#}");
+                tracing::warn!("Failed to query bound images: {e}");
+                (None, Some(e))
+            }
+        };
 
     let r = BootEntry {
         image,
@@ -155,8 +266,14 @@ fn boot_entry_from_deployment(
             deploy_serial: deployment.deployserial().try_into().unwrap(),
             stateroot: deployment.stateroot().into(),
         }),
+        assessment: crate::assessment::read_state(sysroot, deployment)?,
+        download_only: deployment.is_staged() && deployment.is_finalization_locked(),
+        kernel,
+        kargs,
+        bound_images,
+        composefs,
     };
-    Ok(r)
+    Ok((r, bound_images_error))
 }
 
 impl BootEntry {
@@ -185,6 +302,203 @@ pub(crate) fn get_status_require_booted(
     Ok((booted_deployment, deployments, host))
 }
 
+/// The update found by `bootc upgrade --check`, if it differs from the booted image.
+fn cached_update_available(status: &HostStatus) -> Option<&crate::spec::ImageStatus> {
+    let booted = status.booted.as_ref()?;
+    let cached = booted.cached_update.as_ref()?;
+    let differs = booted
+        .image
+        .as_ref()
+        .map_or(true, |b| b.image_digest != cached.image_digest);
+    differs.then_some(cached)
+}
+
+/// Describe an image for a condition message.
+fn describe_image(entry: &BootEntry) -> String {
+    match entry.image.as_ref() {
+        Some(image) => format!("{} ({})", format_imgref(&image.image), image.image_digest),
+        None => "ostree deployment".into(),
+    }
+}
+
+fn new_condition(
+    ty: ConditionType,
+    status: bool,
+    reason: &str,
+    message: impl Into<String>,
+    last_transition_time: Option<chrono::DateTime<chrono::Utc>>,
+) -> Condition {
+    Condition {
+        ty,
+        status: status.into(),
+        reason: reason.into(),
+        message: message.into(),
+        last_transition_time,
+    }
+}
+
+/// Compute the conditions summarizing the state of the host.  The transition
+/// times are taken from the history where possible.
+fn compute_conditions(
+    status: &HostStatus,
+    history: &[HistoryEntry],
+    fsck: Option<&FsckState>,
+    bound_images_error: Option<&str>,
+) -> Vec<Condition> {
+    let Some(booted) = status.booted.as_ref() else {
+        return Vec::new();
+    };
+    let last_event = |f: &dyn Fn(&HistoryEntry) -> bool| {
+        history.iter().rev().find(|e| f(e)).map(|e| e.timestamp)
+    };
+    let mut r = Vec::new();
+
+    r.push(match cached_update_available(status) {
+        Some(cached) => new_condition(
+            ConditionType::UpdateAvailable,
+            true,
+            "UpdateFound",
+            format!(
+                "Update {} is available for {}",
+                cached.image_digest,
+                format_imgref(&cached.image)
+            ),
+            None,
+        ),
+        None => new_condition(
+            ConditionType::UpdateAvailable,
+            false,
+            "NoUpdate",
+            "No update was found by the last check",
+            None,
+        ),
+    });
+
+    r.push(match status.staged.as_ref() {
+        Some(staged) => {
+            let id = staged
+                .ostree
+                .as_ref()
+                .map(|o| format!("{}.{}", o.checksum, o.deploy_serial));
+            let staged_at = last_event(&|e| {
+                matches!(e.event, HistoryEvent::Stage | HistoryEvent::Switch)
+                    && e.deployment.is_some()
+                    && e.deployment == id
+            });
+            let image = describe_image(staged);
+            if staged.download_only {
+                new_condition(
+                    ConditionType::UpdateStaged,
+                    false,
+                    "DownloadOnly",
+                    format!("{image} is downloaded, but will not be applied until requested"),
+                    staged_at,
+                )
+            } else {
+                new_condition(
+                    ConditionType::UpdateStaged,
+                    true,
+                    "Staged",
+                    format!("{image} will be applied on the next boot"),
+                    staged_at,
+                )
+            }
+        }
+        None => new_condition(
+            ConditionType::UpdateStaged,
+            false,
+            "NoStagedDeployment",
+            "No deployment is staged",
+            None,
+        ),
+    });
+
+    r.push(if status.rollback_queued {
+        new_condition(
+            ConditionType::RollbackQueued,
+            true,
+            "RollbackQueued",
+            "The rollback deployment will be booted next",
+            last_event(&|e| e.event == HistoryEvent::Rollback),
+        )
+    } else {
+        new_condition(
+            ConditionType::RollbackQueued,
+            false,
+            "DefaultBootOrder",
+            "The staged or booted deployment will be booted next",
+            None,
+        )
+    });
+
+    let mut degraded = Vec::new();
+    let mut degraded_at = None;
+    if let Some(fsck) = fsck.filter(|f| !f.errors.is_empty()) {
+        degraded.push((
+            "FsckFailed",
+            format!("Consistency checks failed: {}", fsck.errors.join("; ")),
+        ));
+        degraded_at = Some(fsck.timestamp);
+    }
+    if let Some(e) = bound_images_error {
+        degraded.push((
+            "BoundImagesInvalid",
+            format!("Failed to query the bound images of the booted deployment: {e}"),
+        ));
+    }
+    r.push(match degraded.first() {
+        Some((reason, _)) => {
+            let message = degraded
+                .iter()
+                .map(|(_, m)| m.as_str())
+                .collect::<Vec<_>>()
+                .join("; ");
+            new_condition(ConditionType::Degraded, true, reason, message, degraded_at)
+        }
+        None => new_condition(
+            ConditionType::Degraded,
+            false,
+            "AsExpected",
+            "No errors were found",
+            None,
+        ),
+    });
+
+    r.push(if booted.incompatible {
+        new_condition(
+            ConditionType::Incompatible,
+            true,
+            "LocalModifications",
+            "The booted deployment has local changes bootc does not understand, such as layered packages",
+            None,
+        )
+    } else {
+        new_condition(
+            ConditionType::Incompatible,
+            false,
+            "AsExpected",
+            "The booted deployment can be managed by bootc",
+            None,
+        )
+    });
+
+    r
+}
+
+/// A variant of [`get_status_require_booted`] which also supports offline sysroots,
+/// where the default deployment takes the place of the booted one.
+pub(crate) fn get_status_require_booted_or_default(
+    sysroot: &Storage,
+) -> Result<(ostree::Deployment, Deployments, Host)> {
+    if sysroot.booted_deployment().is_some() {
+        return get_status_require_booted(sysroot);
+    }
+    let (default_deployment, deployments, host) = get_status_offline(sysroot)?;
+    let default_deployment =
+        default_deployment.ok_or_else(|| anyhow::anyhow!("No deployments found"))?;
+    Ok((default_deployment, deployments, host))
+}
+
 /// Gather the ostree deployment objects, but also extract metadata from them into
 /// a more native Rust structure.
 #[context("Computing status")]
@@ -227,38 +541,47 @@ pub(crate) fn get_status(
         other,
     };
 
+    let boot_entry =
+        |d: &ostree::Deployment| boot_entry_from_deployment(sysroot, d).map(|(entry, _)| entry);
     let staged = deployments
         .staged
         .as_ref()
-        .map(|d| boot_entry_from_deployment(sysroot, d))
+        .map(boot_entry)
         .transpose()
         .context("Staged deployment")?;
-    let booted = booted_deployment
+    let (booted, bound_images_error) = booted_deployment
         .as_ref()
         .map(|d| boot_entry_from_deployment(sysroot, d))
         .transpose()
-        .context("Booted deployment")?;
+        .context("Booted deployment")?
+        .map_or((None, None), |(entry, e)| (Some(entry), e));
     let rollback = deployments
         .rollback
         .as_ref()
-        .map(|d| boot_entry_from_deployment(sysroot, d))
+        .map(boot_entry)
         .transpose()
         .context("Rollback deployment")?;
     let other_deployments = deployments
         .other
         .iter()
-        .map(|d| boot_entry_from_deployment(sysroot, d))
+        .map(boot_entry)
         .collect::<Result<Vec<_>>>()
         .context("Other deployments")?;
-    let spec = staged
+    let mut spec = staged
         .as_ref()
         .or(booted.as_ref())
         .and_then(|entry| entry.image.as_ref())
         .map(|img| HostSpec {
             image: Some(img.image.clone()),
             boot_order,
+            ..Default::default()
         })
         .unwrap_or_default();
+    // Machine-local state is read from the booted root
+    if let Some(booted_deployment) = booted_deployment {
+        let root = &crate::utils::deployment_fd(sysroot, booted_deployment)?;
+        read_local_spec(root, &mut spec);
+    }
 
     let ty = if booted
         .as_ref()
@@ -279,10 +602,48 @@ pub(crate) fn get_status(
         other_deployments,
         rollback_queued,
         ty,
+        conditions: Vec::new(),
+        offline: false,
     };
+    if host.status.booted.is_some() {
+        let history = warn_on_error(crate::history::load_recent(sysroot)).unwrap_or_default();
+        let fsck = warn_on_error(crate::fsck::read_state(sysroot)).flatten();
+        host.status.conditions = compute_conditions(
+            &host.status,
+            &history,
+            fsck.as_ref(),
+            bound_images_error.as_deref(),
+        );
+    }
     Ok((deployments, host))
 }
 
+/// Gather the status of an offline (not booted) sysroot, such as a mounted disk
+/// image.  The default deployment (if any) takes the place of the booted one.
+#[context("Computing offline status")]
+pub(crate) fn get_status_offline(
+    sysroot: &Storage,
+) -> Result<(Option<ostree::Deployment>, Deployments, Host)> {
+    let default_deployment = sysroot.deployments().into_iter().next();
+    let (deployments, mut host) = get_status(sysroot, default_deployment.as_ref())?;
+    host.status.offline = true;
+    Ok((default_deployment, deployments, host))
+}
+
+/// Gather the status of the host; if the system is not booted via ostree,
+/// a default (empty) status is returned.
+pub(crate) async fn get_host() -> Result<Host> {
+    let host = if !ostree_booted()? {
+        Default::default()
+    } else {
+        let sysroot = super::cli::get_storage().await?;
+        let booted_deployment = sysroot.booted_deployment();
+        let (_deployments, host) = get_status(&sysroot, booted_deployment.as_ref())?;
+        host
+    };
+    Ok(host)
+}
+
 /// Implementation of the `bootc status` CLI command.
 #[context("Status")]
 pub(crate) async fn status(opts: super::cli::StatusOpts) -> Result<()> {
@@ -291,13 +652,12 @@ pub(crate) async fn status(opts: super::cli::StatusOpts) -> Result<()> {
         0 | 1 => {}
         o => anyhow::bail!("Unsupported format version: {o}"),
     };
-    let mut host = if !ostree_booted()? {
-        Default::default()
-    } else {
-        let sysroot = super::cli::get_storage().await?;
-        let booted_deployment = sysroot.booted_deployment();
-        let (_deployments, host) = get_status(&sysroot, booted_deployment.as_ref())?;
+    let mut host = if let Some(sysroot) = opts.sysroot.as_deref() {
+        let sysroot = &crate::cli::get_storage_offline(sysroot).await?;
+        let (_default_deployment, _deployments, host) = get_status_offline(sysroot)?;
         host
+    } else {
+        get_host().await?
     };
 
     // We could support querying the staged or rollback deployments
@@ -312,24 +672,29 @@ pub(crate) async fn status(opts: super::cli::StatusOpts) -> Result<()> {
     let out = std::io::stdout();
     let mut out = out.lock();
     let legacy_opt = if opts.json {
-        OutputFormat::Json
+        StatusOutputFormat::Json
     } else if std::io::stdout().is_terminal() {
-        OutputFormat::HumanReadable
+        StatusOutputFormat::HumanReadable
     } else {
-        OutputFormat::Yaml
+        StatusOutputFormat::Yaml
     };
     let format = opts.format.unwrap_or(legacy_opt);
     match format {
-        OutputFormat::Json => serde_json::to_writer(&mut out, &host).map_err(anyhow::Error::new),
-        OutputFormat::Yaml => serde_yaml::to_writer(&mut out, &host).map_err(anyhow::Error::new),
-        OutputFormat::HumanReadable => human_readable_output(&mut out, &host),
+        StatusOutputFormat::Json => {
+            serde_json::to_writer(&mut out, &host).map_err(anyhow::Error::new)
+        }
+        StatusOutputFormat::Yaml => {
+            serde_yaml::to_writer(&mut out, &host).map_err(anyhow::Error::new)
+        }
+        StatusOutputFormat::HumanReadable => human_readable_output(&mut out, &host, opts.verbose),
+        StatusOutputFormat::Prometheus => prometheus_output(&mut out, &host),
     }
     .context("Writing to stdout")?;
 
     Ok(())
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Slot {
     Staged,
     Booted,
@@ -356,25 +721,55 @@ fn write_row_name(mut out: impl Write, s: &str, prefix_len: usize) -> Result<()>
     Ok(())
 }
 
+/// Format an image reference for display.
+fn format_imgref(imgref: &ImageReference) -> Cow<'_, str> {
+    let transport = &imgref.transport;
+    let imagename = &imgref.image;
+    // Registry is the default, so don't show that
+    if transport == "registry" {
+        Cow::Borrowed(imagename)
+    } else {
+        // But for non-registry we include the transport
+        Cow::Owned(format!("{transport}:{imagename}"))
+    }
+}
+
+/// Options for rendering the status in a human readable way.
+#[derive(Debug, Default, Clone, Copy)]
+struct HumanRenderOpts {
+    /// Include more details about each deployment
+    verbose: bool,
+    /// The status is of an offline system, where the booted slot holds the default deployment
+    offline: bool,
+}
+
+impl HumanRenderOpts {
+    /// The name for the booted slot.
+    fn booted_name(&self) -> &'static str {
+        if self.offline {
+            "Default"
+        } else {
+            "Booted"
+        }
+    }
+}
+
 /// Write the data for a container image based status.
 fn human_render_slot(
     mut out: impl Write,
     slot: Option<Slot>,
     entry: &crate::spec::BootEntry,
     image: &crate::spec::ImageStatus,
+    opts: HumanRenderOpts,
 ) -> Result<()> {
-    let transport = &image.image.transport;
-    let imagename = &image.image.image;
-    // Registry is the default, so don't show that
-    let imageref = if transport == "registry" {
-        Cow::Borrowed(imagename)
-    } else {
-        // But for non-registry we include the transport
-        Cow::Owned(format!("{transport}:{imagename}"))
-    };
+    let imageref = format_imgref(&image.image);
     let prefix = match slot {
         Some(Slot::Staged) => "  Staged image".into(),
-        Some(Slot::Booted) => format!("{} Booted image", crate::glyph::Glyph::BlackCircle),
+        Some(Slot::Booted) => format!(
+            "{} {} image",
+            crate::glyph::Glyph::BlackCircle,
+            opts.booted_name()
+        ),
         Some(Slot::Rollback) => "  Rollback image".into(),
         _ => "   Other image".into(),
     };
@@ -412,22 +807,97 @@ fn human_render_slot(
         writeln!(out, "yes")?;
     }
 
+    if entry.download_only {
+        write_row_name(&mut out, "Download only", prefix_len)?;
+        writeln!(out, "yes")?;
+    }
+
+    if let Some(assessment) = entry.assessment.as_ref() {
+        write_row_name(&mut out, "Assessment", prefix_len)?;
+        human_render_assessment(&mut out, assessment)?;
+    }
+
+    if opts.verbose {
+        human_render_details(&mut out, entry, prefix_len)?;
+    }
+
     tracing::debug!("pinned={}", entry.pinned);
 
     Ok(())
 }
 
+/// Render the details of a boot entry shown in verbose mode.
+fn human_render_details(
+    mut out: impl Write,
+    entry: &crate::spec::BootEntry,
+    prefix_len: usize,
+) -> Result<()> {
+    if let Some(kernel) = entry.kernel.as_deref() {
+        write_row_name(&mut out, "Kernel", prefix_len)?;
+        writeln!(out, "{kernel}")?;
+    }
+    if let Some(kargs) = entry.kargs.as_ref() {
+        write_row_name(&mut out, "Kargs", prefix_len)?;
+        writeln!(out, "{}", kargs.join(" "))?;
+    }
+    if let Some(images) = entry.bound_images.as_ref() {
+        write_row_name(&mut out, "Bound images", prefix_len)?;
+        let mut images = images.iter();
+        match images.next() {
+            Some(first) => writeln!(out, "{first}")?,
+            None => writeln!(out, "none")?,
+        }
+        // Align the remaining images with the first one
+        for image in images {
+            writeln!(out, "{:width$}{image}", "", width = prefix_len + 2)?;
+        }
+    }
+    if let Some(composefs) = entry.composefs {
+        write_row_name(&mut out, "Composefs", prefix_len)?;
+        let state = match composefs {
+            ComposefsState::Disabled => "disabled",
+            ComposefsState::Enabled => "enabled",
+            ComposefsState::Verity => "enabled (fsverity)",
+        };
+        writeln!(out, "{state}")?;
+    }
+    Ok(())
+}
+
+/// Render the boot assessment state of an entry.
+fn human_render_assessment(
+    mut out: impl Write,
+    assessment: &crate::spec::BootAssessment,
+) -> Result<()> {
+    use crate::spec::BootAssessmentState;
+    match assessment.state {
+        BootAssessmentState::Pending => writeln!(
+            out,
+            "pending ({} boot attempts left)",
+            assessment.boot_attempts_left
+        )?,
+        BootAssessmentState::Good => writeln!(out, "good")?,
+        BootAssessmentState::Bad => writeln!(out, "bad")?,
+    }
+    Ok(())
+}
+
 /// Output a rendering of a non-container boot entry.
 fn human_render_slot_ostree(
     mut out: impl Write,
     slot: Option<Slot>,
     entry: &crate::spec::BootEntry,
     ostree_commit: &str,
+    opts: HumanRenderOpts,
 ) -> Result<()> {
     // TODO consider rendering more ostree stuff here like rpm-ostree status does
     let prefix = match slot {
         Some(Slot::Staged) => "  Staged ostree".into(),
-        Some(Slot::Booted) => format!("{} Booted ostree", crate::glyph::Glyph::BlackCircle),
+        Some(Slot::Booted) => format!(
+            "{} {} ostree",
+            crate::glyph::Glyph::BlackCircle,
+            opts.booted_name()
+        ),
         Some(Slot::Rollback) => "  Rollback ostree".into(),
         _ => " Other ostree".into(),
     };
@@ -441,11 +911,19 @@ fn human_render_slot_ostree(
         writeln!(out, "yes")?;
     }
 
+    if opts.verbose {
+        human_render_details(&mut out, entry, prefix_len)?;
+    }
+
     tracing::debug!("pinned={}", entry.pinned);
     Ok(())
 }
 
-fn human_readable_output_booted(mut out: impl Write, host: &Host) -> Result<()> {
+fn human_readable_output_booted(
+    mut out: impl Write,
+    host: &Host,
+    opts: HumanRenderOpts,
+) -> Result<()> {
     let mut first = true;
     for (slot_name, status) in [
         (Slot::Staged, &host.status.staged),
@@ -459,9 +937,15 @@ fn human_readable_output_booted(mut out: impl Write, host: &Host) -> Result<()>
                 writeln!(out)?;
             }
             if let Some(image) = &host_status.image {
-                human_render_slot(&mut out, Some(slot_name), host_status, image)?;
+                human_render_slot(&mut out, Some(slot_name), host_status, image, opts)?;
             } else if let Some(ostree) = host_status.ostree.as_ref() {
-                human_render_slot_ostree(&mut out, Some(slot_name), host_status, &ostree.checksum)?;
+                human_render_slot_ostree(
+                    &mut out,
+                    Some(slot_name),
+                    host_status,
+                    &ostree.checksum,
+                    opts,
+                )?;
             } else {
                 writeln!(out, "Current {slot_name} state is unknown")?;
             }
@@ -473,9 +957,9 @@ fn human_readable_output_booted(mut out: impl Write, host: &Host) -> Result<()>
             writeln!(out)?;
 
             if let Some(image) = &entry.image {
-                human_render_slot(&mut out, None, entry, image)?;
+                human_render_slot(&mut out, None, entry, image, opts)?;
             } else if let Some(ostree) = entry.ostree.as_ref() {
-                human_render_slot_ostree(&mut out, None, entry, &ostree.checksum)?;
+                human_render_slot_ostree(&mut out, None, entry, &ostree.checksum, opts)?;
             }
         }
     }
@@ -483,10 +967,152 @@ fn human_readable_output_booted(mut out: impl Write, host: &Host) -> Result<()>
     Ok(())
 }
 
+/// Escape a Prometheus label value.
+fn escape_label_value(v: &str) -> String {
+    v.replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Format the labels identifying an image.
+fn image_labels(image: Option<&crate::spec::ImageStatus>) -> String {
+    let (imgref, digest) = image
+        .map(|i| (format_imgref(&i.image), i.image_digest.as_str()))
+        .unwrap_or_default();
+    format!(
+        r#"imgref="{}",digest="{}""#,
+        escape_label_value(&imgref),
+        escape_label_value(digest)
+    )
+}
+
+/// Write a metric family header.
+fn write_metric_header(mut out: impl Write, name: &str, help: &str) -> Result<()> {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} gauge")?;
+    Ok(())
+}
+
+/// Render the host status as metrics in the Prometheus text exposition format,
+/// e.g. for the node-exporter textfile collector.
+fn prometheus_output(mut out: impl Write, host: &Host) -> Result<()> {
+    let status = &host.status;
+    let slots = [
+        (Slot::Staged, status.staged.as_ref()),
+        (Slot::Booted, status.booted.as_ref()),
+        (Slot::Rollback, status.rollback.as_ref()),
+    ];
+    // Other deployments may use the same image, so they are distinguished by
+    // their index to give each series a unique set of labels.
+    let entries = slots
+        .into_iter()
+        .filter_map(|(slot, entry)| entry.map(|e| (format!(r#"slot="{slot}""#), e)))
+        .chain(
+            status
+                .other_deployments
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (format!(r#"slot="other",index="{i}""#), e)),
+        )
+        .collect::<Vec<_>>();
+
+    write_metric_header(
+        &mut out,
+        "bootc_deployment_info",
+        "Deployments of container images, by slot.",
+    )?;
+    for (slot, entry) in entries.iter() {
+        let Some(image) = entry.image.as_ref() else {
+            continue;
+        };
+        let version = image.version.as_deref().unwrap_or_default();
+        writeln!(
+            out,
+            r#"bootc_deployment_info{{{slot},{},version="{}"}} 1"#,
+            image_labels(Some(image)),
+            escape_label_value(version)
+        )?;
+    }
+
+    write_metric_header(
+        &mut out,
+        "bootc_image_timestamp_seconds",
+        "The build timestamp of the image of a deployment.",
+    )?;
+    for (slot, entry) in entries.iter() {
+        let Some(image) = entry.image.as_ref() else {
+            continue;
+        };
+        if let Some(timestamp) = image.timestamp {
+            writeln!(
+                out,
+                r#"bootc_image_timestamp_seconds{{{slot},{}}} {}"#,
+                image_labels(Some(image)),
+                timestamp.timestamp()
+            )?;
+        }
+    }
+
+    write_metric_header(
+        &mut out,
+        "bootc_deployments",
+        "The number of deployments, including pinned ones.",
+    )?;
+    writeln!(out, "bootc_deployments {}", entries.len())?;
+
+    write_metric_header(
+        &mut out,
+        "bootc_staged_update_pending",
+        "Whether an update is staged to be applied on the next boot.",
+    )?;
+    let staged = status.staged.as_ref();
+    let pending = staged.is_some_and(|s| !s.download_only);
+    writeln!(
+        out,
+        "bootc_staged_update_pending{{{}}} {}",
+        image_labels(staged.and_then(|s| s.image.as_ref())),
+        u8::from(pending)
+    )?;
+
+    write_metric_header(
+        &mut out,
+        "bootc_rollback_queued",
+        "Whether the rollback deployment is queued for the next boot.",
+    )?;
+    writeln!(
+        out,
+        "bootc_rollback_queued {}",
+        u8::from(status.rollback_queued)
+    )?;
+
+    write_metric_header(
+        &mut out,
+        "bootc_cached_update_available",
+        "Whether an update for the booted image was found by `bootc upgrade --check`.",
+    )?;
+    let cached = status
+        .booted
+        .as_ref()
+        .and_then(|b| b.cached_update.as_ref());
+    let available = cached_update_available(status).is_some();
+    writeln!(
+        out,
+        "bootc_cached_update_available{{{}}} {}",
+        image_labels(cached),
+        u8::from(available)
+    )?;
+
+    Ok(())
+}
+
 /// Implementation of rendering our host structure in a "human readable" way.
-fn human_readable_output(mut out: impl Write, host: &Host) -> Result<()> {
+fn human_readable_output(mut out: impl Write, host: &Host, verbose: bool) -> Result<()> {
+    let opts = HumanRenderOpts {
+        verbose,
+        offline: host.status.offline,
+    };
     if host.status.booted.is_some() {
-        human_readable_output_booted(out, host)?;
+        human_readable_output_booted(out, host, opts)?;
     } else {
         writeln!(out, "System is not deployed via bootc.")?;
     }
@@ -500,7 +1126,7 @@ mod tests {
     fn human_status_from_spec_fixture(spec_fixture: &str) -> Result<String> {
         let host: Host = serde_yaml::from_str(spec_fixture).unwrap();
         let mut w = Vec::new();
-        human_readable_output(&mut w, &host).unwrap();
+        human_readable_output(&mut w, &host, false).unwrap();
         let w = String::from_utf8(w).unwrap();
         Ok(w)
     }
@@ -522,6 +1148,85 @@ mod tests {
         similar_asserts::assert_eq!(w, expected);
     }
 
+    #[test]
+    fn test_prometheus_output() {
+        let host: Host =
+            serde_yaml::from_str(include_str!("fixtures/spec-staged-booted.yaml")).unwrap();
+        let mut w = Vec::new();
+        prometheus_output(&mut w, &host).unwrap();
+        let w = String::from_utf8(w).unwrap();
+        let staged = r#"imgref="quay.io/example/someimage:latest",digest="sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566""#;
+        let booted = r#"imgref="quay.io/example/someimage:latest",digest="sha256:736b359467c9437c1ac915acaae952aad854e07eb4a16a94999a48af08c83c34""#;
+        let expected = indoc::formatdoc! { r#"
+            # HELP bootc_deployment_info Deployments of container images, by slot.
+            # TYPE bootc_deployment_info gauge
+            bootc_deployment_info{{slot="staged",{staged},version="nightly"}} 1
+            bootc_deployment_info{{slot="booted",{booted},version="nightly"}} 1
+            # HELP bootc_image_timestamp_seconds The build timestamp of the image of a deployment.
+            # TYPE bootc_image_timestamp_seconds gauge
+            bootc_image_timestamp_seconds{{slot="staged",{staged}}} 1697311335
+            bootc_image_timestamp_seconds{{slot="booted",{booted}}} 1696101736
+            # HELP bootc_deployments The number of deployments, including pinned ones.
+            # TYPE bootc_deployments gauge
+            bootc_deployments 2
+            # HELP bootc_staged_update_pending Whether an update is staged to be applied on the next boot.
+            # TYPE bootc_staged_update_pending gauge
+            bootc_staged_update_pending{{{staged}}} 1
+            # HELP bootc_rollback_queued Whether the rollback deployment is queued for the next boot.
+            # TYPE bootc_rollback_queued gauge
+            bootc_rollback_queued 0
+            # HELP bootc_cached_update_available Whether an update for the booted image was found by `bootc upgrade --check`.
+            # TYPE bootc_cached_update_available gauge
+            bootc_cached_update_available{{imgref="",digest=""}} 0
+        "# };
+        similar_asserts::assert_eq!(w, expected);
+
+        // Other deployments of the same image are distinguished by index
+        let mut host = host;
+        let entry = host.status.booted.clone().unwrap();
+        host.status.other_deployments = vec![entry.clone(), entry];
+        let mut w = Vec::new();
+        prometheus_output(&mut w, &host).unwrap();
+        let w = String::from_utf8(w).unwrap();
+        let other = w
+            .lines()
+            .filter(|l| l.starts_with("bootc_deployment_info{slot=\"other\""))
+            .collect::<Vec<_>>();
+        similar_asserts::assert_eq!(
+            other,
+            [
+                format!(
+                    r#"bootc_deployment_info{{slot="other",index="0",{booted},version="nightly"}} 1"#
+                ),
+                format!(
+                    r#"bootc_deployment_info{{slot="other",index="1",{booted},version="nightly"}} 1"#
+                ),
+            ]
+        );
+
+        assert_eq!(escape_label_value("a\\b\"c\nd"), r#"a\\b\"c\nd"#);
+    }
+
+    #[test]
+    fn test_human_readable_assessment() {
+        let w = human_status_from_spec_fixture(include_str!(
+            "fixtures/spec-staged-booted-assessment.yaml"
+        ))
+        .expect("No spec found");
+        let expected = indoc::indoc! { r"
+            Staged image: quay.io/example/someimage:latest
+                  Digest: sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566 (arm64)
+                 Version: nightly (2023-10-14T19:22:15Z)
+              Assessment: pending (3 boot attempts left)
+
+          ● Booted image: quay.io/example/someimage:latest
+                  Digest: sha256:736b359467c9437c1ac915acaae952aad854e07eb4a16a94999a48af08c83c34 (arm64)
+                 Version: nightly (2023-09-30T19:22:16Z)
+              Assessment: good
+        "};
+        similar_asserts::assert_eq!(w, expected);
+    }
+
     #[test]
     fn test_human_readable_rfe_spec() {
         // Basic rhel for edge bootc install with nothing
@@ -631,4 +1336,286 @@ mod tests {
         "};
         similar_asserts::assert_eq!(w, expected);
     }
+
+    #[test]
+    fn test_conditions() {
+        use crate::spec::ConditionStatus;
+
+        let host: Host =
+            serde_yaml::from_str(include_str!("fixtures/spec-staged-booted.yaml")).unwrap();
+        let mut status = host.status;
+        let staged_at = "2024-08-07T12:00:00Z".parse().unwrap();
+        let history = [HistoryEntry {
+            timestamp: staged_at,
+            event: HistoryEvent::Stage,
+            image: status
+                .staged
+                .as_ref()
+                .unwrap()
+                .image
+                .as_ref()
+                .unwrap()
+                .image
+                .clone(),
+            manifest_digest:
+                "sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566".into(),
+            version: None,
+            deployment: Some(
+                "3c6dad657109522e0b2e49bf44b5420f16f0b438b5b9357e5132211cfbad135d.0".into(),
+            ),
+        }];
+        let summarize = |conditions: &[Condition]| {
+            conditions
+                .iter()
+                .map(|c| (c.ty, c.status, c.reason.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let conditions = compute_conditions(&status, &history, None, None);
+        assert_eq!(
+            summarize(&conditions),
+            [
+                (
+                    ConditionType::UpdateAvailable,
+                    ConditionStatus::False,
+                    "NoUpdate".into()
+                ),
+                (
+                    ConditionType::UpdateStaged,
+                    ConditionStatus::True,
+                    "Staged".into()
+                ),
+                (
+                    ConditionType::RollbackQueued,
+                    ConditionStatus::False,
+                    "DefaultBootOrder".into()
+                ),
+                (
+                    ConditionType::Degraded,
+                    ConditionStatus::False,
+                    "AsExpected".into()
+                ),
+                (
+                    ConditionType::Incompatible,
+                    ConditionStatus::False,
+                    "AsExpected".into()
+                ),
+            ]
+        );
+        assert_eq!(conditions[1].last_transition_time, Some(staged_at));
+
+        // An update was found, the staged deployment is download-only, and fsck failed
+        let booted = status.booted.as_mut().unwrap();
+        booted.cached_update = status.staged.as_ref().unwrap().image.clone();
+        status.staged.as_mut().unwrap().download_only = true;
+        let fsck = FsckState {
+            timestamp: staged_at,
+            errors: vec!["etc-resolvconf: Found usr/etc/resolv.conf as zero-sized file".into()],
+        };
+        let conditions = compute_conditions(
+            &status,
+            &[],
+            Some(&fsck),
+            Some("Parsing bound image config"),
+        );
+        assert_eq!(
+            summarize(&conditions)[..4],
+            [
+                (
+                    ConditionType::UpdateAvailable,
+                    ConditionStatus::True,
+                    "UpdateFound".into()
+                ),
+                (
+                    ConditionType::UpdateStaged,
+                    ConditionStatus::False,
+                    "DownloadOnly".into()
+                ),
+                (
+                    ConditionType::RollbackQueued,
+                    ConditionStatus::False,
+                    "DefaultBootOrder".into()
+                ),
+                (
+                    ConditionType::Degraded,
+                    ConditionStatus::True,
+                    "FsckFailed".into()
+                ),
+            ]
+        );
+        assert_eq!(conditions[1].last_transition_time, None);
+        let degraded = &conditions[3];
+        assert!(degraded.message.contains("zero-sized"));
+        assert!(degraded.message.contains("bound images"));
+        assert_eq!(degraded.last_transition_time, Some(staged_at));
+
+        // Nothing is reported for a host which isn't booted via bootc
+        assert!(compute_conditions(&HostStatus::default(), &history, None, None).is_empty());
+    }
+
+    #[test]
+    fn test_human_readable_offline() {
+        let mut host: Host =
+            serde_yaml::from_str(include_str!("fixtures/spec-only-booted.yaml")).unwrap();
+        host.status.offline = true;
+        let mut w = Vec::new();
+        human_readable_output(&mut w, &host, false).unwrap();
+        let w = String::from_utf8(w).unwrap();
+        assert!(w.starts_with("● Default image: "), "{w}");
+        assert!(!w.contains("Booted"));
+        let json = serde_json::to_value(&host).unwrap();
+        assert_eq!(json["status"]["offline"], true);
+        host.status.offline = false;
+        let json = serde_json::to_value(&host).unwrap();
+        assert!(json["status"].get("offline").is_none());
+    }
+
+    #[test]
+    fn test_human_readable_download_only() {
+        let mut host: Host =
+            serde_yaml::from_str(include_str!("fixtures/spec-staged-booted.yaml")).unwrap();
+        let json = serde_json::to_value(&host).unwrap();
+        assert!(json["status"]["staged"].get("downloadOnly").is_none());
+        host.status.staged.as_mut().unwrap().download_only = true;
+        let mut w = Vec::new();
+        human_readable_output(&mut w, &host, false).unwrap();
+        let w = String::from_utf8(w).unwrap();
+        let expected = indoc::indoc! { r"
+            Staged image: quay.io/example/someimage:latest
+                  Digest: sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566 (arm64)
+                 Version: nightly (2023-10-14T19:22:15Z)
+           Download only: yes
+
+          ● Booted image: quay.io/example/someimage:latest
+                  Digest: sha256:736b359467c9437c1ac915acaae952aad854e07eb4a16a94999a48af08c83c34 (arm64)
+                 Version: nightly (2023-09-30T19:22:16Z)
+        "};
+        similar_asserts::assert_eq!(w, expected);
+        let json = serde_json::to_value(&host).unwrap();
+        assert_eq!(json["status"]["staged"]["downloadOnly"], true);
+    }
+
+    #[test]
+    fn test_human_readable_verbose() {
+        let mut host: Host =
+            serde_yaml::from_str(include_str!("fixtures/spec-staged-booted.yaml")).unwrap();
+        let booted = host.status.booted.as_mut().unwrap();
+        booted.kernel = Some("6.12.0-1.el10.aarch64".into());
+        booted.kargs = Some(vec!["root=UUID=abc".into(), "rw".into()]);
+        booted.bound_images = Some(vec![
+            "quay.io/example/app:latest".into(),
+            "quay.io/example/db:latest".into(),
+        ]);
+        booted.composefs = Some(ComposefsState::Verity);
+        let staged = host.status.staged.as_mut().unwrap();
+        staged.bound_images = Some(Vec::new());
+        staged.composefs = Some(ComposefsState::Disabled);
+        let mut w = Vec::new();
+        human_readable_output(&mut w, &host, true).unwrap();
+        let w = String::from_utf8(w).unwrap();
+        let expected = indoc::indoc! { r"
+            Staged image: quay.io/example/someimage:latest
+                  Digest: sha256:16dc2b6256b4ff0d2ec18d2dbfb06d117904010c8cf9732cdb022818cf7a7566 (arm64)
+                 Version: nightly (2023-10-14T19:22:15Z)
+            Bound images: none
+               Composefs: disabled
+
+          ● Booted image: quay.io/example/someimage:latest
+                  Digest: sha256:736b359467c9437c1ac915acaae952aad854e07eb4a16a94999a48af08c83c34 (arm64)
+                 Version: nightly (2023-09-30T19:22:16Z)
+                  Kernel: 6.12.0-1.el10.aarch64
+                   Kargs: root=UUID=abc rw
+            Bound images: quay.io/example/app:latest
+                          quay.io/example/db:latest
+               Composefs: enabled (fsverity)
+        "};
+        similar_asserts::assert_eq!(w, expected);
+    }
+
+    #[test]
+    fn test_read_local_spec() -> Result<()> {
+        let td =
+            cap_std_ext::cap_tempfile::TempDir::new(cap_std_ext::cap_std::ambient_authority())?;
+        let mut spec = HostSpec::default();
+        read_local_spec(&td, &mut spec);
+        assert_eq!(spec, HostSpec::default());
+        td.create_dir_all("etc/bootc/kargs.d")?;
+        td.write(
+            "etc/bootc/kargs.d/50-bootc-local.toml",
+            r#"kargs = ["debug"]"#,
+        )?;
+        read_local_spec(&td, &mut spec);
+        assert_eq!(spec.kargs.add, ["debug"]);
+        // A malformed configuration is skipped, rather than failing
+        td.write("etc/bootc/kargs.d/50-bootc-local.toml", "kargs = [")?;
+        let mut spec = HostSpec::default();
+        read_local_spec(&td, &mut spec);
+        assert!(spec.kargs.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deployment_root_details() -> Result<()> {
+        let td =
+            cap_std_ext::cap_tempfile::TempDir::new(cap_std_ext::cap_std::ambient_authority())?;
+        assert_eq!(kernel_version_of_root(&td)?, None);
+        assert_eq!(composefs_state_of_root(&td)?, ComposefsState::Disabled);
+        // A composefs image alone doesn't mean composefs is used
+        td.write(crate::install::OSTREE_COMPOSEFS_SUPER, "cfs")?;
+        assert_eq!(composefs_state_of_root(&td)?, ComposefsState::Disabled);
+        td.create_dir_all("usr/lib/ostree")?;
+        td.write(
+            "usr/lib/ostree/prepare-root.conf",
+            "[composefs]\nenabled = no\n",
+        )?;
+        assert_eq!(composefs_state_of_root(&td)?, ComposefsState::Disabled);
+        for enabled in ["yes", "maybe", "verity"] {
+            td.write(
+                "usr/lib/ostree/prepare-root.conf",
+                format!("[composefs]\nenabled = {enabled}\n"),
+            )?;
+            assert_ne!(
+                composefs_state_of_root(&td)?,
+                ComposefsState::Disabled,
+                "{enabled}"
+            );
+        }
+        td.remove_file(crate::install::OSTREE_COMPOSEFS_SUPER)?;
+        assert_eq!(composefs_state_of_root(&td)?, ComposefsState::Disabled);
+        td.create_dir_all("usr/lib/modules/6.12.0-1.el10.x86_64")?;
+        td.write("usr/lib/modules/6.12.0-1.el10.x86_64/vmlinuz", "kernel")?;
+        assert_eq!(
+            kernel_version_of_root(&td)?.as_deref(),
+            Some("6.12.0-1.el10.x86_64")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_status_offline() -> Result<()> {
+        use ostree_ext::gio;
+
+        // An initialized, but never booted sysroot, as e.g. created by `ostree admin init-fs`
+        let td = tempfile::tempdir()?;
+        let sysroot = ostree::Sysroot::new(Some(&gio::File::for_path(td.path())));
+        sysroot.ensure_initialized(gio::Cancellable::NONE)?;
+        sysroot.init_osname("default", gio::Cancellable::NONE)?;
+        sysroot.load(gio::Cancellable::NONE)?;
+        assert!(sysroot.booted_deployment().is_none());
+        let sysroot = ostree_ext::sysroot::SysrootLock::new_from_sysroot(&sysroot).await?;
+        let run =
+            cap_std_ext::cap_tempfile::TempDir::new(cap_std_ext::cap_std::ambient_authority())?;
+        let storage = Storage::new_offline(sysroot, &run)?;
+
+        let (default_deployment, deployments, host) = get_status_offline(&storage)?;
+        assert!(default_deployment.is_none());
+        assert!(deployments.staged.is_none());
+        assert!(deployments.rollback.is_none());
+        assert!(deployments.other.is_empty());
+        assert!(host.status.offline);
+        assert!(host.status.booted.is_none());
+        assert!(host.spec.image.is_none());
+        assert!(host.status.conditions.is_empty());
+        Ok(())
+    }
 }
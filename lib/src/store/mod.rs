@@ -27,6 +27,8 @@ pub(crate) const BOOTC_ROOT: &str = "ostree/bootc";
 pub(crate) struct Storage {
     pub sysroot: SysrootLock,
     run: Dir,
+    /// A private runtime directory backing `run`, for offline sysroots
+    _run_tmp: Option<cap_std_ext::cap_tempfile::TempDir>,
     imgstore: OnceCell<crate::imgstorage::Storage>,
     pub store: Box<dyn ContainerImageStoreImpl>,
 }
@@ -77,11 +79,23 @@ impl Storage {
         Ok(Self {
             sysroot,
             run,
+            _run_tmp: None,
             store,
             imgstore: Default::default(),
         })
     }
 
+    /// Create storage for an offline (not booted) sysroot, such as a mounted disk image.
+    /// This uses a private runtime directory in `run`, so that e.g. the container
+    /// image storage doesn't conflict with that of the host.
+    pub(crate) fn new_offline(sysroot: SysrootLock, run: &Dir) -> Result<Self> {
+        let run_tmp = cap_std_ext::cap_tempfile::TempDir::new_in(run)
+            .context("Creating runtime directory")?;
+        let mut r = Self::new(sysroot, &run_tmp)?;
+        r._run_tmp = Some(run_tmp);
+        Ok(r)
+    }
+
     /// Access the image storage; will automatically initialize it if necessary.
     pub(crate) fn get_ensure_imgstore(&self) -> Result<&crate::imgstorage::Storage> {
         if let Some(imgstore) = self.imgstore.get() {
@@ -89,19 +103,23 @@ impl Storage {
         }
         let sysroot_dir = crate::utils::sysroot_dir(&self.sysroot)?;
 
-        let sepolicy = if self.sysroot.booted_deployment().is_none() {
+        // For an offline sysroot, use the merge deployment of the default stateroot
+        let deployment = self.sysroot.booted_deployment().or_else(|| {
+            let osname = self.sysroot.deployments().into_iter().next()?.osname();
+            self.sysroot.merge_deployment(Some(osname.as_str()))
+        });
+        let sepolicy = if let Some(dep) = deployment {
+            // load the sepolicy from the ostree deployment so the imgstorage can be
+            // properly labeled with /var/lib/container/storage labels
+            tracing::trace!("loading sepolicy from ostree deployment");
+            let dep_fs = deployment_fd(&self.sysroot, &dep)?;
+            lsm::new_sepolicy_at(&dep_fs)?
+        } else {
             // fallback to policy from container root
             // this should only happen during cleanup of a broken install
             tracing::trace!("falling back to container root's selinux policy");
             let container_root = Dir::open_ambient_dir("/", cap_std::ambient_authority())?;
             lsm::new_sepolicy_at(&container_root)?
-        } else {
-            // load the sepolicy from the booted ostree deployment so the imgstorage can be
-            // properly labeled with /var/lib/container/storage labels
-            tracing::trace!("loading sepolicy from booted ostree deployment");
-            let dep = self.sysroot.booted_deployment().unwrap();
-            let dep_fs = deployment_fd(&self.sysroot, &dep)?;
-            lsm::new_sepolicy_at(&dep_fs)?
         };
 
         tracing::trace!("sepolicy in get_ensure_imgstore: {sepolicy:?}");
@@ -1,7 +1,6 @@
 use anyhow::Result;
 
 use ostree_ext::container as ostree_container;
-use ostree_ext::oci_spec;
 use ostree_ext::oci_spec::image::{Digest, ImageConfiguration};
 use ostree_ext::ostree;
 use ostree_ext::sysroot::SysrootLock;
@@ -45,14 +44,7 @@ fn create_imagestatus(
     manifest_digest: &Digest,
     config: &ImageConfiguration,
 ) -> ImageStatus {
-    let labels = labels_of_config(config);
-    let timestamp = labels
-        .and_then(|l| {
-            l.get(oci_spec::image::ANNOTATION_CREATED)
-                .map(|s| s.as_str())
-        })
-        .or_else(|| config.created().as_deref())
-        .and_then(bootc_utils::try_deserialize_timestamp);
+    let timestamp = crate::status::timestamp_of_config(config);
 
     let version = ostree_container::version_for_config(config).map(ToOwned::to_owned);
     let architecture = config.architecture().to_string();
@@ -64,9 +56,3 @@ fn create_imagestatus(
         architecture,
     }
 }
-
-fn labels_of_config(
-    config: &oci_spec::image::ImageConfiguration,
-) -> Option<&std::collections::HashMap<String, String>> {
-    config.config().as_ref().and_then(|c| c.labels().as_ref())
-}
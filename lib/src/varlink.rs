@@ -0,0 +1,578 @@
+//! # Varlink API
+//!
+//! This implements a [varlink](https://varlink.org/) service exposing host status
+//! and the `upgrade`, `switch`, `rollback` and `edit` operations as typed methods.
+//! It is socket activated via `bootc-varlink.socket` (listening on
+//! `/run/bootc/varlink.sock`), and every operation is implemented by the
+//! same code as the corresponding CLI verb.
+//!
+//! Calls made with `more: true` to methods which fetch images receive progress
+//! as a stream of replies, each carrying a [`crate::progress_jsonl::Event`].
+
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::unix::pipe;
+use tokio::net::UnixStream;
+
+use crate::cli::{EditOpts, RollbackOpts, SwitchOpts, UpgradeOpts};
+use crate::progress_jsonl::{Event, RawProgressFd};
+use crate::spec::Host;
+
+/// The name of our interface
+const INTERFACE: &str = "org.containers.bootc";
+/// The varlink service interface, which all services implement
+const SERVICE_INTERFACE: &str = "org.varlink.service";
+/// Exit after this long without any connections; we will be re-activated on demand.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The interface definition, in the varlink IDL.
+const INTERFACE_DESCRIPTION: &str = r#"# Manage a bootc host system
+interface org.containers.bootc
+
+# Get the host status, as output by `bootc status --json`.
+method GetStatus() -> (host: object)
+
+# Fetch and stage an updated image, as `bootc upgrade`.  With `more`, each
+# reply carries a progress event, until the final empty reply.
+method Upgrade(
+  check: ?bool,
+  downloadOnly: ?bool,
+  fromDownloaded: ?bool,
+  allowDowngrade: ?bool
+) -> (event: ?object)
+
+# Fetch and stage a different image, as `bootc switch`.  With `more`, each
+# reply carries a progress event, until the final empty reply.
+method Switch(
+  target: string,
+  transport: ?string,
+  enforceContainerSigpolicy: ?bool,
+  retain: ?bool,
+  allowDowngrade: ?bool
+) -> (event: ?object)
+
+# Queue the rollback deployment for the next boot, as `bootc rollback`.
+method Rollback() -> ()
+
+# Apply changes to the host specification, as `bootc edit`.
+method Edit(host: object) -> ()
+
+# The operation failed.
+error Failed (message: string)
+"#;
+
+/// A method call
+#[derive(Debug, Deserialize)]
+struct Call {
+    method: String,
+    #[serde(default)]
+    parameters: Option<Value>,
+    #[serde(default)]
+    more: bool,
+    #[serde(default)]
+    oneway: bool,
+}
+
+/// A reply to a method call
+#[derive(Debug, Serialize, PartialEq)]
+struct Reply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    parameters: Value,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    continues: bool,
+}
+
+impl Reply {
+    fn new(parameters: Value) -> Self {
+        Self {
+            error: None,
+            parameters,
+            continues: false,
+        }
+    }
+}
+
+/// The ways in which a call can fail
+#[derive(Debug)]
+enum CallError {
+    MethodNotFound(String),
+    InvalidParameter(String),
+    Failed(anyhow::Error),
+}
+
+impl From<CallError> for Reply {
+    fn from(value: CallError) -> Self {
+        let (error, parameters) = match value {
+            CallError::MethodNotFound(method) => (
+                format!("{SERVICE_INTERFACE}.MethodNotFound"),
+                serde_json::json!({ "method": method }),
+            ),
+            CallError::InvalidParameter(parameter) => (
+                format!("{SERVICE_INTERFACE}.InvalidParameter"),
+                serde_json::json!({ "parameter": parameter }),
+            ),
+            CallError::Failed(e) => (
+                format!("{INTERFACE}.Failed"),
+                serde_json::json!({ "message": format!("{e:#}") }),
+            ),
+        };
+        Self {
+            error: Some(error),
+            parameters,
+            continues: false,
+        }
+    }
+}
+
+// Note that there is deliberately no `apply` parameter for any method: rebooting
+// from within a call would leave this server (and hence all clients) waiting forever.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+struct UpgradeParams {
+    check: bool,
+    download_only: bool,
+    from_downloaded: bool,
+    allow_downgrade: bool,
+}
+
+impl UpgradeParams {
+    /// Convert to the CLI options, with the same validation.
+    fn to_opts(&self) -> Result<UpgradeOpts, clap::Error> {
+        let flags = [
+            (self.check, "--check"),
+            (self.download_only, "--download-only"),
+            (self.from_downloaded, "--from-downloaded"),
+            (self.allow_downgrade, "--allow-downgrade"),
+        ];
+        let args = flags.into_iter().filter_map(|(v, f)| v.then_some(f));
+        UpgradeOpts::try_parse_from(["upgrade", "--quiet"].into_iter().chain(args))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+struct SwitchParams {
+    target: String,
+    transport: Option<String>,
+    enforce_container_sigpolicy: bool,
+    retain: bool,
+    allow_downgrade: bool,
+}
+
+impl SwitchParams {
+    /// Convert to the CLI options, with the same validation.
+    fn to_opts(&self) -> Result<SwitchOpts, clap::Error> {
+        let mut args = vec!["switch", "--quiet"];
+        if let Some(transport) = self.transport.as_deref() {
+            args.extend(["--transport", transport]);
+        }
+        let flags = [
+            (
+                self.enforce_container_sigpolicy,
+                "--enforce-container-sigpolicy",
+            ),
+            (self.retain, "--retain"),
+            (self.allow_downgrade, "--allow-downgrade"),
+        ];
+        args.extend(flags.into_iter().filter_map(|(v, f)| v.then_some(f)));
+        args.extend(["--", self.target.as_str()]);
+        SwitchOpts::try_parse_from(args)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoParams {}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct EditParams {
+    host: Host,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct InterfaceDescriptionParams {
+    interface: String,
+}
+
+/// The varlink `InvalidParameter` error carries just the name of the offending
+/// parameter; extract it from a deserialization error where possible.
+fn invalid_parameter(e: serde_json::Error) -> CallError {
+    let msg = e.to_string();
+    let name = ["unknown field `", "missing field `"]
+        .into_iter()
+        .find_map(|prefix| msg.strip_prefix(prefix))
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(name, _)| name.to_owned());
+    CallError::InvalidParameter(name.unwrap_or(msg))
+}
+
+/// Parse a method call.  A malformed call is answered with an error, rather than
+/// dropping the connection.
+fn parse_call(buf: &[u8]) -> Result<Call, CallError> {
+    serde_json::from_slice(buf).map_err(invalid_parameter)
+}
+
+/// Parse the parameters of a call.
+fn parse_params<T: serde::de::DeserializeOwned>(params: Option<Value>) -> Result<T, CallError> {
+    let params = params.unwrap_or_else(|| Value::Object(Default::default()));
+    serde_json::from_value(params).map_err(invalid_parameter)
+}
+
+/// Serialize a message, terminated by a NUL byte as the protocol requires.
+async fn write_message(out: &mut (impl AsyncWrite + Unpin), reply: &Reply) -> Result<()> {
+    let mut buf = serde_json::to_vec(reply)?;
+    buf.push(0);
+    out.write_all(&buf).await?;
+    out.flush().await?;
+    Ok(())
+}
+
+/// Forward progress events written to the pipe as replies, until the writer is closed.
+async fn forward_progress(recv: pipe::Receiver, out: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+    let mut lines = BufReader::new(recv).lines();
+    while let Some(line) = lines.next_line().await? {
+        let event: Event = serde_json::from_str(&line).context("Parsing progress")?;
+        let reply = Reply {
+            error: None,
+            parameters: serde_json::json!({ "event": event }),
+            continues: true,
+        };
+        write_message(out, &reply).await?;
+    }
+    Ok(())
+}
+
+/// Run an operation which reports progress; if the client asked for more
+/// replies, progress events are streamed to it as the operation runs.
+async fn with_progress<F, Fut>(
+    more: bool,
+    out: &mut (impl AsyncWrite + Unpin),
+    f: F,
+) -> Result<(), CallError>
+where
+    F: FnOnce(Option<RawProgressFd>) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    if !more {
+        return f(None).await.map_err(CallError::Failed);
+    }
+    let (recv, send) = rustix::pipe::pipe_with(rustix::pipe::PipeFlags::CLOEXEC)
+        .context("Creating progress pipe")
+        .map_err(CallError::Failed)?;
+    let recv = pipe::Receiver::from_owned_fd(recv)
+        .context("Creating progress pipe")
+        .map_err(CallError::Failed)?;
+    // The operation owns the write side, and closes it when done
+    let (r, forwarded) = tokio::join!(f(Some(send.into())), forward_progress(recv, out));
+    if let Err(e) = forwarded {
+        tracing::warn!("Failed to forward progress: {e:#}");
+    }
+    r.map_err(CallError::Failed)
+}
+
+/// Dispatch a method call, returning the parameters of the final reply.
+async fn dispatch(call: Call, out: &mut (impl AsyncWrite + Unpin)) -> Result<Value, CallError> {
+    let empty = || Value::Object(Default::default());
+    let Some((interface, method)) = call.method.rsplit_once('.') else {
+        return Err(CallError::MethodNotFound(call.method));
+    };
+    match (interface, method) {
+        (SERVICE_INTERFACE, "GetInfo") => Ok(serde_json::json!({
+            "vendor": "bootc",
+            "product": "bootc",
+            "version": env!("CARGO_PKG_VERSION"),
+            "url": "https://github.com/bootc-dev/bootc",
+            "interfaces": [SERVICE_INTERFACE, INTERFACE],
+        })),
+        (SERVICE_INTERFACE, "GetInterfaceDescription") => {
+            let params: InterfaceDescriptionParams = parse_params(call.parameters)?;
+            if params.interface != INTERFACE {
+                return Err(CallError::InvalidParameter("interface".into()));
+            }
+            Ok(serde_json::json!({ "description": INTERFACE_DESCRIPTION }))
+        }
+        (INTERFACE, "GetStatus") => {
+            parse_params::<NoParams>(call.parameters)?;
+            let host = crate::status::get_host().await.map_err(CallError::Failed)?;
+            Ok(serde_json::json!({ "host": host }))
+        }
+        (INTERFACE, "Upgrade") => {
+            let params: UpgradeParams = parse_params(call.parameters)?;
+            let mut opts = params
+                .to_opts()
+                .map_err(|e| CallError::InvalidParameter(e.to_string()))?;
+            with_progress(call.more, out, |fd| {
+                opts.progress.progress_fd = fd;
+                crate::cli::upgrade(opts)
+            })
+            .await?;
+            Ok(empty())
+        }
+        (INTERFACE, "Switch") => {
+            let params: SwitchParams = parse_params(call.parameters)?;
+            let mut opts = params
+                .to_opts()
+                .map_err(|e| CallError::InvalidParameter(e.to_string()))?;
+            with_progress(call.more, out, |fd| {
+                opts.progress.progress_fd = fd;
+                crate::cli::switch(opts)
+            })
+            .await?;
+            Ok(empty())
+        }
+        (INTERFACE, "Rollback") => {
+            parse_params::<NoParams>(call.parameters)?;
+            crate::cli::rollback(RollbackOpts { apply: false })
+                .await
+                .map_err(CallError::Failed)?;
+            Ok(empty())
+        }
+        (INTERFACE, "Edit") => {
+            let params: EditParams = parse_params(call.parameters)?;
+            let edit = async {
+                let tmpf = tempfile::NamedTempFile::new()?;
+                serde_json::to_writer(tmpf.as_file(), &params.host)?;
+                let filename = tmpf.path().to_str().map(ToOwned::to_owned);
+                crate::cli::edit(EditOpts {
+                    filename,
+                    quiet: true,
+                })
+                .await
+            };
+            edit.await.map_err(CallError::Failed)?;
+            Ok(empty())
+        }
+        _ => Err(CallError::MethodNotFound(call.method)),
+    }
+}
+
+/// Handle all calls on a single connection.
+async fn handle_connection(conn: UnixStream) -> Result<()> {
+    let (read, mut write) = conn.into_split();
+    let mut read = BufReader::new(read);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if read.read_until(0, &mut buf).await? == 0 {
+            return Ok(());
+        }
+        if buf.pop() != Some(0) {
+            anyhow::bail!("Truncated message");
+        }
+        let (reply, oneway) = match parse_call(&buf) {
+            Ok(call) => {
+                tracing::debug!("Varlink call: {}", call.method);
+                let oneway = call.oneway;
+                let reply = match dispatch(call, &mut write).await {
+                    Ok(parameters) => Reply::new(parameters),
+                    Err(e) => e.into(),
+                };
+                (reply, oneway)
+            }
+            Err(e) => {
+                tracing::debug!("Invalid varlink call: {e:?}");
+                (e.into(), false)
+            }
+        };
+        if !oneway {
+            write_message(&mut write, &reply).await?;
+        }
+    }
+}
+
+/// Take the listening socket passed by systemd.
+#[allow(unsafe_code)]
+fn listener_from_systemd() -> Result<tokio::net::UnixListener> {
+    let mut fds = libsystemd::activation::receive_descriptors(true)
+        .context("Receiving socket from systemd")?;
+    let fd = match (fds.pop(), fds.is_empty()) {
+        (Some(fd), true) => fd,
+        _ => anyhow::bail!("Expected exactly one socket from systemd"),
+    };
+    // SAFETY: systemd passes us ownership of the file descriptor
+    let fd = unsafe { OwnedFd::from_raw_fd(fd.into_raw_fd()) };
+    let listener = std::os::unix::net::UnixListener::from(fd);
+    listener.set_nonblocking(true)?;
+    tokio::net::UnixListener::from_std(listener).map_err(Into::into)
+}
+
+/// Implementation of `bootc internals varlink`: serve connections until idle.
+pub(crate) async fn serve() -> Result<()> {
+    let listener = listener_from_systemd()?;
+    // Our operations are not `Send`, so connections are handled on this thread.
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async move {
+            let mut connections = tokio::task::JoinSet::new();
+            loop {
+                let accepted = if connections.is_empty() {
+                    match tokio::time::timeout(IDLE_TIMEOUT, listener.accept()).await {
+                        Ok(r) => r,
+                        Err(_) => {
+                            tracing::debug!("Exiting after idle timeout");
+                            return Ok(());
+                        }
+                    }
+                } else {
+                    tokio::select! {
+                        r = listener.accept() => r,
+                        Some(r) = connections.join_next() => {
+                            match r {
+                                Ok(Err(e)) => tracing::warn!("Connection failed: {e:#}"),
+                                Err(e) => tracing::warn!("Connection task failed: {e}"),
+                                Ok(Ok(())) => {}
+                            }
+                            continue;
+                        }
+                    }
+                };
+                let (conn, _) = accepted?;
+                connections.spawn_local(handle_connection(conn));
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_to_opts() {
+        let opts = parse_params::<UpgradeParams>(Some(serde_json::json!({ "check": true })))
+            .unwrap()
+            .to_opts()
+            .unwrap();
+        assert!(opts.check && opts.quiet && !opts.apply);
+        // The same validation as the CLI applies
+        let params = UpgradeParams {
+            check: true,
+            download_only: true,
+            ..Default::default()
+        };
+        assert!(params.to_opts().is_err());
+        assert!(matches!(
+            parse_params::<UpgradeParams>(Some(serde_json::json!({ "foo": true }))),
+            Err(CallError::InvalidParameter(p)) if p == "foo"
+        ));
+        // Rebooting is not supported
+        assert!(matches!(
+            parse_params::<UpgradeParams>(Some(serde_json::json!({ "apply": true }))),
+            Err(CallError::InvalidParameter(p)) if p == "apply"
+        ));
+        assert!(matches!(
+            parse_params::<SwitchParams>(Some(serde_json::json!({
+                "target": "quay.io/example/os:latest",
+                "apply": true,
+            }))),
+            Err(CallError::InvalidParameter(p)) if p == "apply"
+        ));
+        assert!(matches!(
+            parse_params::<InterfaceDescriptionParams>(None),
+            Err(CallError::InvalidParameter(p)) if p == "interface"
+        ));
+        assert!(matches!(
+            parse_params::<UpgradeParams>(Some(serde_json::json!({ "check": "yes" }))),
+            Err(CallError::InvalidParameter(_))
+        ));
+
+        let params: SwitchParams = parse_params(Some(serde_json::json!({
+            "target": "quay.io/example/os:latest",
+            "transport": "oci",
+            "retain": true,
+        })))
+        .unwrap();
+        let opts = params.to_opts().unwrap();
+        assert_eq!(opts.target, "quay.io/example/os:latest");
+        assert_eq!(opts.transport, "oci");
+        assert!(opts.retain && !opts.apply);
+        // Targets which look like options are not interpreted as such
+        let params = SwitchParams {
+            target: "--apply".into(),
+            ..Default::default()
+        };
+        let opts = params.to_opts().unwrap();
+        assert_eq!(opts.target, "--apply");
+        assert!(!opts.apply);
+    }
+
+    #[test]
+    fn test_reply() {
+        let reply: Reply = CallError::MethodNotFound("org.example.Foo".into()).into();
+        assert_eq!(
+            serde_json::to_value(&reply).unwrap(),
+            serde_json::json!({
+                "error": "org.varlink.service.MethodNotFound",
+                "parameters": { "method": "org.example.Foo" },
+            })
+        );
+        let reply = Reply::new(serde_json::json!({}));
+        assert_eq!(
+            serde_json::to_string(&reply).unwrap(),
+            r#"{"parameters":{}}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_call() {
+        let call = parse_call(br#"{"method": "org.containers.bootc.GetStatus"}"#).unwrap();
+        assert_eq!(call.method, "org.containers.bootc.GetStatus");
+        assert!(!call.more && !call.oneway);
+        // Malformed calls are answered with an error
+        for (msg, parameter) in [
+            (r#"{"parameters": {}}"#, "method"),
+            (
+                r#"{"method": "org.containers.bootc.GetStatus", "more": 1}"#,
+                "",
+            ),
+            ("not json", ""),
+        ] {
+            let Err(CallError::InvalidParameter(p)) = parse_call(msg.as_bytes()) else {
+                panic!("Expected an error for {msg}");
+            };
+            if !parameter.is_empty() {
+                assert_eq!(p, parameter);
+            }
+            let reply: Reply = CallError::InvalidParameter(p).into();
+            assert_eq!(
+                reply.error.as_deref(),
+                Some("org.varlink.service.InvalidParameter")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let mut out = Vec::new();
+        let call: Call = serde_json::from_str(
+            r#"{"method": "org.varlink.service.GetInterfaceDescription", "parameters": {"interface": "org.containers.bootc"}}"#,
+        )
+        .unwrap();
+        let r = dispatch(call, &mut out).await.unwrap();
+        assert_eq!(r["description"], INTERFACE_DESCRIPTION);
+
+        let call: Call =
+            serde_json::from_str(r#"{"method": "org.containers.bootc.Frobnicate"}"#).unwrap();
+        assert!(matches!(
+            dispatch(call, &mut out).await,
+            Err(CallError::MethodNotFound(_))
+        ));
+        let call: Call = serde_json::from_str(r#"{"method": "Unqualified"}"#).unwrap();
+        assert!(matches!(
+            dispatch(call, &mut out).await,
+            Err(CallError::MethodNotFound(_))
+        ));
+        // Nothing was streamed
+        assert!(out.is_empty());
+    }
+}